@@ -1,12 +1,13 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use nalgebra::Vector3;
 use simulation::load_mesh;
+use simulation::mesh::{box_mesh, VertexOrdering};
 use simulation::ode::{
-  EulerSettings, Integrator, IntegratorType, MidpointSettings, RK4Settings,
-  SwappableIntegrator,
+  EulerSettings, Integrator, IntegratorType, MidpointSettings, RK45Settings,
+  RK4Settings, SwappableIntegrator,
 };
 use simulation::simulated_scene::{
-  MeshParams, SceneModel, SceneModelParams, SimMesh, S,
+  Material, MeshParams, SceneModel, SceneModelParams, SimMesh, S,
 };
 use std::path::Path;
 
@@ -21,11 +22,9 @@ fn get_mesh_for_repeat(repeat: usize) -> (SimMesh, Vec<Vector3<S>>) {
 
   let total_size = repeat * vertices.len();
 
-  assert!(total_size < std::u16::MAX as usize);
-
   for _ in 0..repeat {
     full_vertices.extend(&vertices);
-    full_tet.extend(tets.iter().map(|tet: &[u16; 4]| {
+    full_tet.extend(tets.iter().map(|tet: &[u32; 4]| {
       let mut new_tet = [0; 4];
       for (tet, new_tet) in tet.iter().zip(&mut new_tet) {
         *new_tet = tet + count;
@@ -33,7 +32,7 @@ fn get_mesh_for_repeat(repeat: usize) -> (SimMesh, Vec<Vector3<S>>) {
       new_tet
     }));
 
-    count += vertices.len() as u16;
+    count += vertices.len() as u32;
   }
 
   let zeros = vec![Vector3::zeros(); total_size];
@@ -43,14 +42,27 @@ fn get_mesh_for_repeat(repeat: usize) -> (SimMesh, Vec<Vector3<S>>) {
     rigidity: 1.0,
     viscous_incompressibility: 1.0,
     viscous_rigidity: 1.0,
+    mass_damping: 0.0,
+    stiffness_damping: 0.0,
+    air_drag: 0.0,
     density: 1.0,
+    material: Material::StVenantKirchhoff,
+    fiber_stiffness: 0.0,
+    yield_strain: 0.05,
+    creep_rate: 0.0,
+    max_plastic_strain: 0.5,
+    toughness: None,
   };
 
-  (SimMesh::new((full_vertices, full_tet), mesh_params), zeros)
+  (
+    SimMesh::new((full_vertices, full_tet), mesh_params).unwrap(),
+    zeros,
+  )
 }
 
 fn vertex_accels(c: &mut Criterion) {
-  for repeat_mesh_count in [1, 5, 10, 100].iter() {
+  // 1000 copies is past the old u16 vertex limit
+  for repeat_mesh_count in [1, 5, 10, 100, 1000].iter() {
     let (mesh, zeros) = get_mesh_for_repeat(*repeat_mesh_count);
 
     let g = 9.8;
@@ -71,11 +83,108 @@ fn vertex_accels(c: &mut Criterion) {
   }
 }
 
+fn vertex_accels_generated(c: &mut Criterion) {
+  for resolution in [8, 24, 48].iter() {
+    let mesh = box_mesh(Vector3::repeat(1.0), [*resolution; 3]);
+    let mesh = SimMesh::new(mesh, MeshParams::default()).unwrap();
+    let zeros = vec![Vector3::zeros(); mesh.num_vertices() as usize];
+
+    c.bench_function(&format!("vertex accels box {}^3", resolution), |b| {
+      b.iter(|| {
+        mesh.vertex_accels(
+          black_box(&zeros),
+          black_box(&zeros),
+          black_box(&zeros),
+          black_box(9.8),
+        )
+      })
+    });
+  }
+}
+
+fn vertex_accels_ordering(c: &mut Criterion) {
+  // shuffled like the output of an external mesher
+  let (vertices, mut tets) = box_mesh(Vector3::repeat(1.0), [48; 3]);
+  let num_vertices = vertices.len();
+  let shuffle: Vec<_> = (0..num_vertices)
+    .map(|idx| (idx * 7919) % num_vertices)
+    .collect();
+  let mut shuffled = vertices.clone();
+  for (idx, new_idx) in shuffle.iter().enumerate() {
+    shuffled[*new_idx] = vertices[idx];
+  }
+  for tet in &mut tets {
+    for idx in tet.iter_mut() {
+      *idx = shuffle[*idx as usize] as u32;
+    }
+  }
+
+  let zeros = vec![Vector3::zeros(); num_vertices];
+
+  for ordering in &[
+    VertexOrdering::Original,
+    VertexOrdering::Rcm,
+    VertexOrdering::Morton,
+  ] {
+    let mesh = SimMesh::with_ordering(
+      (shuffled.clone(), tets.clone()),
+      MeshParams::default(),
+      *ordering,
+    )
+    .unwrap();
+
+    c.bench_function(
+      &format!("vertex accels shuffled box 48^3 {:?}", ordering),
+      |b| {
+        b.iter(|| {
+          mesh.vertex_accels(
+            black_box(&zeros),
+            black_box(&zeros),
+            black_box(&zeros),
+            black_box(9.8),
+          )
+        })
+      },
+    );
+  }
+}
+
+fn vertex_accels_parallel(c: &mut Criterion) {
+  for resolution in [3, 4, 6, 8, 24].iter() {
+    let mesh = box_mesh(Vector3::repeat(1.0), [*resolution; 3]);
+    let mut mesh = SimMesh::new(mesh, MeshParams::default()).unwrap();
+    let zeros = vec![Vector3::zeros(); mesh.num_vertices() as usize];
+
+    for (name, threshold) in &[("serial", usize::MAX), ("parallel", 0)] {
+      mesh.set_parallel_threshold(Some(*threshold));
+
+      c.bench_function(
+        &format!("vertex accels box {}^3 {}", resolution, name),
+        |b| {
+          b.iter(|| {
+            mesh.vertex_accels(
+              black_box(&zeros),
+              black_box(&zeros),
+              black_box(&zeros),
+              black_box(9.8),
+            )
+          })
+        },
+      );
+    }
+  }
+}
+
 fn integrator(c: &mut Criterion) {
   for integrator_type in [
     IntegratorType::Euler(EulerSettings {}),
     IntegratorType::Midpoint(MidpointSettings {}),
     IntegratorType::RK4(RK4Settings {}),
+    IntegratorType::RK45(RK45Settings {
+      abs_tol: 1e-6,
+      rel_tol: 1e-3,
+      min_step: 1e-7,
+    }),
   ]
   .iter()
   {
@@ -84,7 +193,16 @@ fn integrator(c: &mut Criterion) {
 
       let g = 9.8;
 
-      let model = SceneModel::new(vec![mesh], SceneModelParams { g }, -100.0);
+      // colliders are placed out of the way
+      let model = SceneModel::new(
+        vec![mesh],
+        SceneModelParams { g },
+        10000.0,
+        0.1,
+        -100.0,
+        1.0,
+        Vector3::new(0.0, -100.0, 0.0),
+      );
 
       let mut integrator = SwappableIntegrator::new(integrator_type.clone());
 
@@ -106,5 +224,12 @@ fn integrator(c: &mut Criterion) {
   }
 }
 
-criterion_group!(benches, vertex_accels, integrator);
+criterion_group!(
+  benches,
+  vertex_accels,
+  vertex_accels_generated,
+  vertex_accels_ordering,
+  vertex_accels_parallel,
+  integrator
+);
 criterion_main!(benches);
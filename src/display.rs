@@ -1,9 +1,8 @@
-use crate::{CameraInfo, Scene, SceneGenerator};
+use crate::{prepare_output_dir, CameraInfo, Scene, SceneGenerator};
 use indicatif::ProgressBar;
 use kiss3d::camera::FirstPerson;
 use kiss3d::light::Light;
 use kiss3d::window::Window;
-use std::fs::create_dir_all;
 use std::io;
 use std::path::Path;
 use std::time::Instant;
@@ -17,13 +16,8 @@ pub fn display_scene<S: SceneGenerator>(
   scene_gen: &mut S,
 ) -> io::Result<()> {
   if let Some(record_image_dir) = record_image_dir {
-    if record_image_dir.exists() {
-      if !record_image_dir.is_dir() {
-        eprintln!("Record image directory exists and isn't directory, exiting");
-        return Ok(());
-      }
-    } else {
-      create_dir_all(record_image_dir)?;
+    if !prepare_output_dir(record_image_dir, "Record image directory")? {
+      return Ok(());
     }
   }
 
@@ -60,6 +54,8 @@ pub fn display_scene<S: SceneGenerator>(
         .save(record_image_dir.join(format!("output_{}.png", iters)))?;
     }
 
+    scene.save_frame(iters)?;
+
     iters += 1;
 
     if let Some((frame_limit, p_bar)) = &mut frame_limit_bar {
@@ -76,5 +72,9 @@ pub fn display_scene<S: SceneGenerator>(
     p_bar.finish();
   }
 
+  if let Some(summary) = scene.summary() {
+    println!("{}", summary);
+  }
+
   Ok(())
 }
@@ -0,0 +1,63 @@
+use crate::{HeadlessScene, HeadlessSceneGenerator, Scene};
+use indicatif::ProgressBar;
+use std::io;
+use std::time::Instant;
+
+/// Runs a scene without a window until the frame limit or the simulated
+/// duration is reached. Output is left to `Scene::save_frame`.
+pub fn run_headless<S: HeadlessSceneGenerator>(
+  frame_limit: Option<usize>,
+  duration: Option<f32>,
+  force_sim_fps: Option<f32>,
+  scene_gen: &S,
+) -> io::Result<()> {
+  if frame_limit.is_none() && duration.is_none() {
+    eprintln!("Headless runs need a frame limit or a duration, exiting");
+    return Ok(());
+  }
+
+  let mut scene = scene_gen.init_headless();
+
+  let mut time_since_last = Instant::now();
+
+  let mut iters = 0;
+
+  let mut frame_limit_bar = frame_limit
+    .map(|frame_limit| (frame_limit, ProgressBar::new(frame_limit as u64)));
+
+  loop {
+    let delta_time = force_sim_fps
+      .map(|fps| 1.0 / fps)
+      .unwrap_or_else(|| time_since_last.elapsed().as_secs_f32());
+    time_since_last = Instant::now();
+
+    scene.save_frame(iters)?;
+
+    iters += 1;
+
+    if let Some((frame_limit, p_bar)) = &mut frame_limit_bar {
+      if iters >= *frame_limit {
+        break;
+      }
+      p_bar.inc(1);
+    }
+
+    if let Some(duration) = duration {
+      if scene.time() >= duration {
+        break;
+      }
+    }
+
+    scene.update(delta_time);
+  }
+
+  if let Some((_, p_bar)) = &mut frame_limit_bar {
+    p_bar.finish();
+  }
+
+  if let Some(summary) = scene.summary() {
+    println!("{}", summary);
+  }
+
+  Ok(())
+}
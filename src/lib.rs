@@ -1,11 +1,17 @@
 pub mod display;
+pub mod headless;
 pub mod mesh;
 pub mod ode;
 pub mod scene;
 pub mod simulated_scene;
+pub mod surface_export;
 pub mod utils;
+pub mod vtk_export;
 
 pub use display::display_scene;
-pub use mesh::{load_mesh, load_mesh_with_transform, LoadedMesh};
-pub use scene::{CameraInfo, Scene, SceneGenerator};
+pub use headless::run_headless;
+pub use mesh::{load_mesh, load_mesh_with_transform, LoadedMesh, MeshError};
+pub use scene::{
+  CameraInfo, HeadlessScene, HeadlessSceneGenerator, Scene, SceneGenerator,
+};
 pub use utils::*;
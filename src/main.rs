@@ -1,25 +1,73 @@
 use clap::Clap;
-use nalgebra::{Point3, Rotation3, Transform3, Vector3};
 use simulation::{
-  display_scene, load_mesh_with_transform,
+  display_scene, load_mesh,
+  mesh::{analyze_mesh, repair_mesh, save_mesh, VertexOrdering},
   ode::IntegratorType,
+  run_headless,
+  simulated_scene::scene_file::MeshDescription,
   simulated_scene::S as Scalar,
   simulated_scene::{
-    GlobalParams, IntegrationParams, MeshParams, SceneModelParams,
-    SimulatedSceneGenerator, StepParams,
+    Material, MaterialPreset, MeshParams, SceneDescription, SceneFileError,
   },
-  CameraInfo,
+  surface_export::SurfaceFormat,
 };
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+/// Flags override values from the scene file, anything given by neither uses
+/// the built in defaults
 #[derive(Clap)]
 #[clap(version = "1.0", author = "Ryan G.")]
 struct Opts {
-  mesh_file: String,
+  /// replaces the meshes of the scene file, .node/.ele files are read as
+  /// TetGen output, .msh files as Gmsh, .vtk files as legacy VTK and
+  /// .obj/.stl surfaces are filled with tets
+  mesh_file: Option<String>,
+
+  #[clap(long = "scene")]
+  /// toml scene description, the other flags override values from it
+  scene: Option<String>,
 
   #[clap(short = "h", long = "hide")]
   hide: bool,
 
+  #[clap(long = "parallel-threshold")]
+  /// tets a mesh needs before its forces are computed in parallel, without it
+  /// forces are only computed in parallel with more than one thread
+  parallel_threshold: Option<usize>,
+
+  #[clap(long = "vertex-ordering")]
+  /// original, rcm or morton, renumbers the vertices of every mesh
+  /// for cache locality, output files keep the original numbering
+  vertex_ordering: Option<VertexOrdering>,
+
+  #[clap(long = "headless")]
+  /// run without creating a window, the other output options still apply
+  headless: bool,
+
+  #[clap(short = "o", long = "output-dir")]
+  /// save the positions and velocities of each frame as csv
+  output_dir: Option<String>,
+
+  #[clap(long = "surface-dir")]
+  /// save the boundary surface of each mesh every frame
+  surface_dir: Option<String>,
+
+  #[clap(long = "surface-format")]
+  /// obj or ply
+  surface_format: Option<SurfaceFormat>,
+
+  #[clap(long = "surface-normals")]
+  /// include vertex normals in the surface files
+  surface_normals: bool,
+
+  #[clap(long = "vtk-dir")]
+  /// save the tetrahedral meshes of each frame as vtu with a pvd index
+  vtk_dir: Option<String>,
+
+  #[clap(long = "duration")]
+  /// simulated seconds to run for in headless mode
+  duration: Option<f32>,
+
   #[clap(short = "r", long = "record-image-dir")]
   record_image_dir: Option<String>,
 
@@ -29,88 +77,198 @@ struct Opts {
   #[clap(long = "force-sim-fps")]
   force_sim_fps: Option<f32>,
 
-  #[clap(long = "speed-up", default_value = "1.0")]
-  speed_up: Scalar,
+  #[clap(long = "speed-up")]
+  speed_up: Option<Scalar>,
 
-  #[clap(short = "t", long = "time-step", default_value = "0.0025")]
-  time_step: Scalar,
+  #[clap(short = "t", long = "time-step")]
+  time_step: Option<Scalar>,
 
-  #[clap(short = "l", long = "incompressibility", default_value = "100.0")]
+  #[clap(short = "l", long = "incompressibility")]
   /// lambda
-  incompressibility: Scalar,
+  incompressibility: Option<Scalar>,
 
-  #[clap(short = "m", long = "rigidity", default_value = "100.0")]
+  #[clap(short = "m", long = "rigidity")]
   /// mu
-  rigidity: Scalar,
+  rigidity: Option<Scalar>,
 
-  #[clap(
-    short = "p",
-    long = "viscous-incompressibility",
-    default_value = "2.0"
-  )]
+  #[clap(short = "p", long = "viscous-incompressibility")]
   /// phi
-  viscous_incompressibility: Scalar,
+  viscous_incompressibility: Option<Scalar>,
 
-  #[clap(short = "s", long = "viscous-rigidity", default_value = "5.0")]
+  #[clap(short = "s", long = "viscous-rigidity")]
   /// psi
-  viscous_rigidity: Scalar,
-
-  #[clap(short = "d", long = "density", default_value = "5.0")]
-  density: Scalar,
-
-  #[clap(
-    short = "g",
-    long = "gravity",
-    default_value = "9.8",
-    allow_hyphen_values = true
-  )]
-  g: Scalar,
-
-  #[clap(long = "penalty-force", default_value = "10000.0")]
-  penalty_force: Scalar,
-
-  #[clap(long = "floor-friction-coeff", default_value = "0.1")]
-  floor_friction_coeff: Scalar,
-
-  #[clap(long = "sphere-radius", default_value = "1.0")]
-  sphere_radius: Scalar,
-
-  #[clap(
-    long = "sphere-pos-x",
-    default_value = "0.0",
-    allow_hyphen_values = true
-  )]
-  sphere_pos_x: Scalar,
-
-  #[clap(
-    long = "sphere-pos-y",
-    default_value = "-3.5",
-    allow_hyphen_values = true
-  )]
-  sphere_pos_y: Scalar,
-
-  #[clap(
-    long = "sphere-pos-z",
-    default_value = "0.0",
-    allow_hyphen_values = true
-  )]
-  sphere_pos_z: Scalar,
-
-  #[clap(
-    long = "floor-pos",
-    default_value = "-3.0",
-    allow_hyphen_values = true
-  )]
-  floor_pos: Scalar,
+  viscous_rigidity: Option<Scalar>,
+
+  #[clap(long = "mass-damping")]
+  /// Rayleigh damping proportional to the mass (1/s)
+  mass_damping: Option<Scalar>,
+
+  #[clap(long = "stiffness-damping")]
+  /// Rayleigh damping proportional to the stiffness (s)
+  stiffness_damping: Option<Scalar>,
+
+  #[clap(long = "air-drag")]
+  /// quadratic drag per area of the surface
+  air_drag: Option<Scalar>,
+
+  #[clap(long = "material")]
+  /// st-venant-kirchhoff, neo-hookean or corotated
+  material: Option<Material>,
+
+  #[clap(long = "fiber-stiffness")]
+  /// added along the fibers given by the scene file
+  fiber_stiffness: Option<Scalar>,
+
+  #[clap(long = "yield-strain")]
+  /// deviatoric strain beyond which meshes deform plastically
+  yield_strain: Option<Scalar>,
+
+  #[clap(long = "creep-rate")]
+  /// fraction of the strain beyond the yield strain made permanent per
+  /// second, zero is elastic
+  creep_rate: Option<Scalar>,
+
+  #[clap(long = "max-plastic-strain")]
+  /// below 1
+  max_plastic_strain: Option<Scalar>,
+
+  #[clap(long = "toughness")]
+  /// principal stress at which tets separate, meshes don't fracture by
+  /// default
+  toughness: Option<Scalar>,
+
+  #[clap(short = "d", long = "density")]
+  density: Option<Scalar>,
+
+  #[clap(long = "preset")]
+  /// rubber, gel or foam, sets the moduli, damping and density in SI units,
+  /// which other flags and scene file params override
+  preset: Option<MaterialPreset>,
+
+  #[clap(long = "youngs-modulus")]
+  /// replaces the incompressibility and rigidity, along with the poisson
+  /// ratio
+  youngs_modulus: Option<Scalar>,
+
+  #[clap(long = "poisson-ratio", allow_hyphen_values = true)]
+  /// between -1 and 0.5
+  poisson_ratio: Option<Scalar>,
+
+  #[clap(long = "damping-ratio")]
+  /// replaces the viscous parameters, damping ratio at the damping frequency
+  damping_ratio: Option<Scalar>,
+
+  #[clap(long = "damping-frequency")]
+  /// in Hz, where the damping ratio applies
+  damping_frequency: Option<Scalar>,
+
+  #[clap(short = "g", long = "gravity", allow_hyphen_values = true)]
+  g: Option<Scalar>,
+
+  #[clap(long = "penalty-force")]
+  penalty_force: Option<Scalar>,
+
+  #[clap(long = "floor-friction-coeff")]
+  floor_friction_coeff: Option<Scalar>,
+
+  #[clap(long = "sphere-radius")]
+  sphere_radius: Option<Scalar>,
+
+  #[clap(long = "sphere-pos-x", allow_hyphen_values = true)]
+  sphere_pos_x: Option<Scalar>,
+
+  #[clap(long = "sphere-pos-y", allow_hyphen_values = true)]
+  sphere_pos_y: Option<Scalar>,
+
+  #[clap(long = "sphere-pos-z", allow_hyphen_values = true)]
+  sphere_pos_z: Option<Scalar>,
+
+  #[clap(long = "floor-pos", allow_hyphen_values = true)]
+  floor_pos: Option<Scalar>,
 
   #[clap(subcommand)]
-  integrator_type: IntegratorType,
+  command: Option<Command>,
+}
+
+#[derive(Clap)]
+enum Command {
+  /// reports the quality of a mesh instead of simulating
+  MeshInfo(MeshInfoOpts),
+  #[clap(flatten)]
+  Integrator(IntegratorType),
+}
+
+#[derive(Clap)]
+struct MeshInfoOpts {
+  /// read the same way as meshes given to the simulation
+  mesh_file: String,
+
+  #[clap(short = "d", long = "density")]
+  /// used for the total mass, the default mesh density if not given
+  density: Option<Scalar>,
+
+  #[clap(long = "repair")]
+  /// save the mesh with inverted tets reoriented and unreferenced vertices
+  /// removed
+  repair: Option<String>,
+}
+
+fn mesh_info(
+  MeshInfoOpts {
+    mesh_file,
+    density,
+    repair,
+  }: MeshInfoOpts,
+) -> std::io::Result<()> {
+  let (mesh, quality) = load_mesh(Path::new(&mesh_file))
+    .and_then(|mesh| analyze_mesh(&mesh).map(|quality| (mesh, quality)))
+    .unwrap_or_else(|error| {
+      eprintln!("couldn't load mesh {}: {}", mesh_file, error);
+      std::process::exit(1);
+    });
+  let density = density.unwrap_or(MeshParams::default().density);
+
+  println!("{}", quality);
+  println!("mass: {} (density {})", quality.mass(density), density);
+
+  if let Some(repair) = repair {
+    save_mesh(Path::new(&repair), &repair_mesh(mesh))?;
+    println!(
+      "saved {} with {} tets reoriented and {} vertices removed",
+      repair,
+      quality.inverted_tetras.len(),
+      quality.unreferenced_vertices.len()
+    );
+  }
+
+  Ok(())
+}
+
+fn override_with<T>(value: &mut T, flag: Option<T>) {
+  if let Some(flag) = flag {
+    *value = flag;
+  }
+}
+
+fn exit_with<T>(error: SceneFileError) -> T {
+  eprintln!("{}", error);
+  std::process::exit(1);
 }
 
 fn main() -> std::io::Result<()> {
   let Opts {
     mesh_file,
+    scene,
     hide,
+    parallel_threshold,
+    vertex_ordering,
+    headless,
+    output_dir,
+    surface_dir,
+    surface_format,
+    surface_normals,
+    vtk_dir,
+    duration,
     record_image_dir,
     frame_limit,
     force_sim_fps,
@@ -119,8 +277,22 @@ fn main() -> std::io::Result<()> {
     incompressibility,
     rigidity,
     viscous_rigidity,
+    mass_damping,
+    stiffness_damping,
+    air_drag,
     viscous_incompressibility,
+    material,
+    fiber_stiffness,
+    yield_strain,
+    creep_rate,
+    max_plastic_strain,
+    toughness,
     density,
+    preset,
+    youngs_modulus,
+    poisson_ratio,
+    damping_ratio,
+    damping_frequency,
     g,
     penalty_force,
     floor_friction_coeff,
@@ -129,53 +301,108 @@ fn main() -> std::io::Result<()> {
     sphere_pos_y,
     sphere_pos_z,
     floor_pos,
-    integrator_type,
+    command,
   } = Opts::parse();
 
-  let mesh = load_mesh_with_transform(
-    &Path::new(&mesh_file),
-    Some(&Transform3::from_matrix_unchecked(
-      Rotation3::new(Vector3::new(0.0, 1.0, 0.0)).to_homogeneous(),
-    )),
-  )?;
-
-  let mesh_params = MeshParams {
-    incompressibility,
-    rigidity,
-    viscous_incompressibility,
-    viscous_rigidity,
-    density,
+  let integrator_type = match command {
+    Some(Command::MeshInfo(opts)) => return mesh_info(opts),
+    Some(Command::Integrator(integrator_type)) => Some(integrator_type),
+    None => None,
   };
 
-  display_scene(
-    "simulation",
-    hide,
-    record_image_dir.as_ref().map(|v| Path::new(v)),
-    frame_limit,
-    force_sim_fps,
-    &mut SimulatedSceneGenerator::new(
-      CameraInfo {
-        eye: Point3::new(5.0, 0.0, 5.0),
-        at: Point3::new(0.0, -3.0, 0.0),
-      },
-      GlobalParams {
-        scene_model_params: SceneModelParams { g },
-        integration_params: IntegrationParams {
-          step_params: StepParams {
-            speed_up,
-            time_step,
-          },
-          integrator_type,
-        },
-      },
-      vec![(mesh, mesh_params)],
-      penalty_force,
-      floor_friction_coeff,
-      floor_pos,
-      sphere_radius,
-      Vector3::new(sphere_pos_x, sphere_pos_y, sphere_pos_z),
-    ),
-  )?;
+  let mut scene = scene
+    .map(|scene| {
+      SceneDescription::load(Path::new(&scene)).unwrap_or_else(exit_with)
+    })
+    .unwrap_or_default();
+
+  if let Some(mesh_file) = mesh_file {
+    scene.meshes = vec![MeshDescription {
+      rotation: [0.0, 1.0, 0.0],
+      ..MeshDescription::new(PathBuf::from(mesh_file))
+    }];
+  }
+
+  for mesh in &mut scene.meshes {
+    override_with(&mut mesh.ordering, vertex_ordering.map(Some));
+
+    let params = &mut mesh.params;
+    override_with(&mut params.incompressibility, incompressibility.map(Some));
+    override_with(&mut params.rigidity, rigidity.map(Some));
+    override_with(
+      &mut params.viscous_incompressibility,
+      viscous_incompressibility.map(Some),
+    );
+    override_with(&mut params.viscous_rigidity, viscous_rigidity.map(Some));
+    override_with(&mut params.mass_damping, mass_damping.map(Some));
+    override_with(&mut params.stiffness_damping, stiffness_damping.map(Some));
+    override_with(&mut params.air_drag, air_drag.map(Some));
+    override_with(&mut params.material, material.map(Some));
+    override_with(&mut params.fiber_stiffness, fiber_stiffness.map(Some));
+    override_with(&mut params.yield_strain, yield_strain.map(Some));
+    override_with(&mut params.creep_rate, creep_rate.map(Some));
+    override_with(&mut params.max_plastic_strain, max_plastic_strain.map(Some));
+    override_with(&mut params.toughness, toughness.map(Some));
+    override_with(&mut params.density, density.map(Some));
+
+    let moduli = &mut mesh.moduli;
+    override_with(&mut moduli.preset, preset.map(Some));
+    override_with(&mut moduli.youngs_modulus, youngs_modulus.map(Some));
+    override_with(&mut moduli.poisson_ratio, poisson_ratio.map(Some));
+    override_with(&mut moduli.damping_ratio, damping_ratio.map(Some));
+    override_with(&mut moduli.damping_frequency, damping_frequency.map(Some));
+  }
+
+  override_with(&mut scene.gravity, g);
+  override_with(&mut scene.time_step, time_step);
+  override_with(&mut scene.speed_up, speed_up);
+  override_with(&mut scene.integrator, integrator_type);
+  override_with(&mut scene.parallel_threshold, parallel_threshold.map(Some));
+
+  let colliders = &mut scene.colliders;
+  override_with(&mut colliders.penalty_force, penalty_force);
+  override_with(&mut colliders.floor.friction_coeff, floor_friction_coeff);
+  override_with(&mut colliders.floor.height, floor_pos);
+  override_with(&mut colliders.sphere.radius, sphere_radius);
+  for (pos, flag) in colliders.sphere.pos.iter_mut().zip(&[
+    sphere_pos_x,
+    sphere_pos_y,
+    sphere_pos_z,
+  ]) {
+    override_with(pos, *flag);
+  }
+
+  let output = &mut scene.output;
+  override_with(&mut output.state_dir, output_dir.map(|v| Some(v.into())));
+  override_with(&mut output.surface_dir, surface_dir.map(|v| Some(v.into())));
+  override_with(&mut output.surface_format, surface_format.map(Some));
+  output.surface_normals |= surface_normals;
+  override_with(&mut output.vtk_dir, vtk_dir.map(|v| Some(v.into())));
+
+  scene.validate().unwrap_or_else(exit_with);
+
+  if !scene.output.output_params().prepare()? {
+    return Ok(());
+  }
+
+  let mut scene_gen = scene.into_generator().unwrap_or_else(exit_with);
+
+  if headless {
+    if record_image_dir.is_some() {
+      eprintln!("Images can't be recorded in headless mode, ignoring");
+    }
+
+    run_headless(frame_limit, duration, force_sim_fps, &scene_gen)?;
+  } else {
+    display_scene(
+      "simulation",
+      hide,
+      record_image_dir.as_ref().map(|v| Path::new(v)),
+      frame_limit,
+      force_sim_fps,
+      &mut scene_gen,
+    )?;
+  }
 
   Ok(())
 }
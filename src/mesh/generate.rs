@@ -0,0 +1,308 @@
+use crate::mesh::{transform_vertex, LoadedMesh};
+use nalgebra::{Transform3, Vector2, Vector3};
+use serde::Deserialize;
+
+/// A box centered at the origin split into `resolution` cubes along each axis,
+/// each of which is split into 6 tets. Neighboring cubes are split the same
+/// way, so the tets are conforming.
+pub fn box_mesh(size: Vector3<f32>, resolution: [usize; 3]) -> LoadedMesh {
+  assert!(
+    resolution.iter().all(|r| *r > 0),
+    "resolution must be at least 1 along each axis"
+  );
+
+  let [n_x, n_y, n_z] = resolution;
+  let vertex_idx =
+    |x: usize, y: usize, z: usize| (x + (n_x + 1) * (y + (n_y + 1) * z)) as u32;
+
+  let mut vertices = Vec::with_capacity((n_x + 1) * (n_y + 1) * (n_z + 1));
+  for z in 0..=n_z {
+    for y in 0..=n_y {
+      for x in 0..=n_x {
+        let frac = Vector3::new(
+          x as f32 / n_x as f32,
+          y as f32 / n_y as f32,
+          z as f32 / n_z as f32,
+        );
+        vertices.push((frac - Vector3::repeat(0.5)).component_mul(&size));
+      }
+    }
+  }
+
+  // Each tet follows a path along the edges from the min to the max corner,
+  // one tet per ordering of the axes. Odd orderings are flipped so all tets
+  // have positive volume.
+  let orderings = [
+    ([0, 1, 2], false),
+    ([1, 2, 0], false),
+    ([2, 0, 1], false),
+    ([0, 2, 1], true),
+    ([1, 0, 2], true),
+    ([2, 1, 0], true),
+  ];
+
+  let mut tetras = Vec::with_capacity(6 * n_x * n_y * n_z);
+  for z in 0..n_z {
+    for y in 0..n_y {
+      for x in 0..n_x {
+        let corner = |offset: [usize; 3]| {
+          vertex_idx(x + offset[0], y + offset[1], z + offset[2])
+        };
+
+        for (axes, flip) in &orderings {
+          let mut offset = [0; 3];
+          let mut tetra = [corner(offset), 0, 0, 0];
+          for (axis, vertex_idx) in axes.iter().zip(&mut tetra[1..]) {
+            offset[*axis] = 1;
+            *vertex_idx = corner(offset);
+          }
+          if *flip {
+            tetra.swap(2, 3);
+          }
+
+          tetras.push(tetra);
+        }
+      }
+    }
+  }
+
+  (vertices, tetras)
+}
+
+/// A box along x with `segments` cells along the length and roughly cubic
+/// cells.
+pub fn beam_mesh(length: f32, thickness: f32, segments: usize) -> LoadedMesh {
+  let cross_segments =
+    ((segments as f32 * thickness / length).round() as usize).max(1);
+
+  box_mesh(
+    Vector3::new(length, thickness, thickness),
+    [segments, cross_segments, cross_segments],
+  )
+}
+
+// pushes the square (or cube) [-1, 1]^n out to the unit circle (or ball)
+fn to_round<D>(v: &mut nalgebra::VectorN<f32, D>)
+where
+  D: nalgebra::DimName,
+  nalgebra::DefaultAllocator: nalgebra::allocator::Allocator<f32, D>,
+{
+  let norm = v.norm();
+  if norm > 0.0 {
+    *v *= v.amax() / norm;
+  }
+}
+
+/// A ball centered at the origin from a cube with `resolution` cells along
+/// each axis which is pushed out radially.
+pub fn sphere_mesh(radius: f32, resolution: usize) -> LoadedMesh {
+  let (mut vertices, tetras) = box_mesh(Vector3::repeat(2.0), [resolution; 3]);
+
+  for vert in &mut vertices {
+    to_round(vert);
+    *vert *= radius;
+  }
+
+  (vertices, tetras)
+}
+
+/// A cylinder along y centered at the origin. The cross section is a square
+/// with `radial_resolution` cells along each side pushed out to a circle.
+pub fn cylinder_mesh(
+  radius: f32,
+  height: f32,
+  radial_resolution: usize,
+  height_resolution: usize,
+) -> LoadedMesh {
+  let (mut vertices, tetras) = box_mesh(
+    Vector3::new(2.0, height, 2.0),
+    [radial_resolution, height_resolution, radial_resolution],
+  );
+
+  for vert in &mut vertices {
+    let mut cross_section = Vector2::new(vert[0], vert[2]);
+    to_round(&mut cross_section);
+    cross_section *= radius;
+
+    vert[0] = cross_section[0];
+    vert[2] = cross_section[1];
+  }
+
+  (vertices, tetras)
+}
+
+/// Generated meshes as described in scene files.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "shape", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum MeshShape {
+  Box {
+    size: [f32; 3],
+    resolution: [usize; 3],
+  },
+  Beam {
+    length: f32,
+    thickness: f32,
+    segments: usize,
+  },
+  Sphere {
+    radius: f32,
+    resolution: usize,
+  },
+  Cylinder {
+    radius: f32,
+    height: f32,
+    radial_resolution: usize,
+    height_resolution: usize,
+  },
+}
+
+impl MeshShape {
+  /// name and value of each size, which must be positive
+  pub fn sizes(&self) -> Vec<(&'static str, f32)> {
+    match self {
+      MeshShape::Box { size, .. } => {
+        vec![
+          ("size[0]", size[0]),
+          ("size[1]", size[1]),
+          ("size[2]", size[2]),
+        ]
+      }
+      MeshShape::Beam {
+        length, thickness, ..
+      } => vec![("length", *length), ("thickness", *thickness)],
+      MeshShape::Sphere { radius, .. } => vec![("radius", *radius)],
+      MeshShape::Cylinder { radius, height, .. } => {
+        vec![("radius", *radius), ("height", *height)]
+      }
+    }
+  }
+
+  /// name and value of each resolution, which must be at least 1
+  pub fn resolutions(&self) -> Vec<(&'static str, usize)> {
+    match self {
+      MeshShape::Box { resolution, .. } => vec![
+        ("resolution[0]", resolution[0]),
+        ("resolution[1]", resolution[1]),
+        ("resolution[2]", resolution[2]),
+      ],
+      MeshShape::Beam { segments, .. } => vec![("segments", *segments)],
+      MeshShape::Sphere { resolution, .. } => {
+        vec![("resolution", *resolution)]
+      }
+      MeshShape::Cylinder {
+        radial_resolution,
+        height_resolution,
+        ..
+      } => vec![
+        ("radial_resolution", *radial_resolution),
+        ("height_resolution", *height_resolution),
+      ],
+    }
+  }
+
+  pub fn generate(&self, transform: Option<&Transform3<f32>>) -> LoadedMesh {
+    let (vertices, tetras) = match self {
+      MeshShape::Box { size, resolution } => {
+        box_mesh(Vector3::from(*size), *resolution)
+      }
+      MeshShape::Beam {
+        length,
+        thickness,
+        segments,
+      } => beam_mesh(*length, *thickness, *segments),
+      MeshShape::Sphere { radius, resolution } => {
+        sphere_mesh(*radius, *resolution)
+      }
+      MeshShape::Cylinder {
+        radius,
+        height,
+        radial_resolution,
+        height_resolution,
+      } => {
+        cylinder_mesh(*radius, *height, *radial_resolution, *height_resolution)
+      }
+    };
+
+    (
+      vertices
+        .into_iter()
+        .map(|vert| transform_vertex(vert, transform))
+        .collect(),
+      tetras,
+    )
+  }
+}
+
+#[cfg(test)]
+fn signed_volumes((vertices, tetras): &LoadedMesh) -> Vec<f32> {
+  tetras
+    .iter()
+    .map(|tetra| {
+      let edge = |idx: usize| {
+        vertices[tetra[idx] as usize] - vertices[tetra[0] as usize]
+      };
+      edge(1).cross(&edge(2)).dot(&edge(3)) / 6.0
+    })
+    .collect()
+}
+
+#[test]
+fn generated_volumes() {
+  use std::f32::consts::PI;
+
+  let check = |mesh: LoadedMesh, expected_volume: f32, tol: f32| {
+    crate::mesh::check_indices(&mesh).unwrap();
+    let volumes = signed_volumes(&mesh);
+    assert!(volumes.iter().all(|v| *v > 0.0));
+    let volume: f32 = volumes.iter().sum();
+    assert!(
+      (volume - expected_volume).abs() <= tol * expected_volume,
+      "{} vs {}",
+      volume,
+      expected_volume
+    );
+  };
+
+  let box_mesh = box_mesh(Vector3::new(1.0, 2.0, 3.0), [2, 3, 4]);
+  assert_eq!(box_mesh.0.len(), 3 * 4 * 5);
+  assert_eq!(box_mesh.1.len(), 6 * 2 * 3 * 4);
+  check(box_mesh, 6.0, 1e-5);
+  check(beam_mesh(4.0, 0.5, 16), 1.0, 1e-5);
+  check(sphere_mesh(2.0, 12), 4.0 / 3.0 * PI * 8.0, 0.05);
+  check(cylinder_mesh(0.5, 3.0, 12, 4), PI * 0.25 * 3.0, 0.05);
+}
+
+#[test]
+fn generated_boundary_is_closed() {
+  use std::collections::HashMap;
+
+  // every face is shared by two tets or is on the boundary, boundary edges
+  // are each shared by exactly two boundary faces
+  for (vertices, tetras) in &[
+    box_mesh(Vector3::repeat(1.0), [3, 2, 2]),
+    sphere_mesh(1.0, 4),
+    cylinder_mesh(1.0, 1.0, 3, 2),
+  ] {
+    let mut faces = HashMap::new();
+    for tetra in tetras {
+      for skip in 0..4 {
+        let mut face: Vec<_> = (0..4)
+          .filter(|idx| *idx != skip)
+          .map(|idx| tetra[idx])
+          .collect();
+        face.sort_unstable();
+        *faces.entry(face).or_insert(0) += 1;
+      }
+    }
+    assert!(faces.values().all(|count| *count <= 2));
+
+    let mut edges = HashMap::new();
+    for (face, _) in faces.iter().filter(|(_, count)| **count == 1) {
+      for (a, b) in &[(0, 1), (1, 2), (0, 2)] {
+        *edges.entry((face[*a], face[*b])).or_insert(0) += 1;
+      }
+    }
+    assert!(edges.values().all(|count| *count == 2));
+    assert!(!vertices.is_empty());
+  }
+}
@@ -0,0 +1,548 @@
+use crate::mesh::{
+  check_indices, parse_values, transform_vertex, LoadedMesh, MeshError,
+};
+use nalgebra::{Transform3, Vector3};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
+use std::path::Path;
+
+// gmsh element type of 4 node tets
+const GMSH_TETRA: u32 = 4;
+
+/// The tets of a Gmsh mesh along with their physical groups. Only vertices
+/// used by tets are kept.
+#[derive(Clone, Debug)]
+pub struct GmshMesh {
+  pub vertices: Vec<Vector3<f32>>,
+  pub tetras: Vec<[u32; 4]>,
+  /// physical group of each tet, 0 if it isn't in one
+  pub physical_tags: Vec<i32>,
+  /// names of the volume physical groups
+  pub physical_names: Vec<(i32, String)>,
+}
+
+impl GmshMesh {
+  pub fn into_loaded(self) -> LoadedMesh {
+    (self.vertices, self.tetras)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Version {
+  V2,
+  V4,
+}
+
+struct Lines<R> {
+  lines: std::iter::Enumerate<io::Lines<R>>,
+  line: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+  fn new(reader: R) -> Self {
+    Self {
+      lines: reader.lines().enumerate(),
+      line: 0,
+    }
+  }
+
+  fn next(&mut self) -> Result<Option<String>, MeshError> {
+    match self.lines.next() {
+      Some((line_idx, line)) => {
+        self.line = line_idx + 1;
+        Ok(Some(line?))
+      }
+      None => Ok(None),
+    }
+  }
+
+  fn error(&self, message: String) -> MeshError {
+    MeshError::Parse {
+      line: self.line,
+      message,
+    }
+  }
+
+  fn expect(&mut self, what: &str) -> Result<String, MeshError> {
+    match self.next()? {
+      Some(line) => Ok(line),
+      None => Err(self.error(format!("file ended, expected {}", what))),
+    }
+  }
+
+  fn tokens(&mut self, what: &str) -> Result<Vec<String>, MeshError> {
+    Ok(
+      self
+        .expect(what)?
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect(),
+    )
+  }
+
+  // a line of exactly out.len() values
+  fn values<T: std::str::FromStr>(
+    &mut self,
+    what: &str,
+    out: &mut [T],
+  ) -> Result<(), MeshError>
+  where
+    T::Err: std::fmt::Display,
+  {
+    let tokens = self.tokens(what)?;
+    parse_values(&tokens, self.line, out)
+  }
+
+  fn end_section(&mut self, name: &str) -> Result<(), MeshError> {
+    let end = format!("$End{}", name);
+    let line = self.expect(&end)?;
+    if line.trim() != end {
+      return Err(self.error(format!("expected {}", end)));
+    }
+
+    Ok(())
+  }
+
+  fn skip_section(&mut self, name: &str) -> Result<(), MeshError> {
+    let end = format!("$End{}", name);
+    while self.expect(&end)?.trim() != end {}
+
+    Ok(())
+  }
+}
+
+fn parse_format<R: BufRead>(
+  lines: &mut Lines<R>,
+) -> Result<Version, MeshError> {
+  let tokens = lines.tokens("mesh format")?;
+  let version = match tokens.first().map(String::as_str) {
+    Some(v) if v.starts_with("2.") => Version::V2,
+    Some("4.1") => Version::V4,
+    v => {
+      return Err(lines.error(format!(
+        "unsupported version {}, only 2.2 and 4.1 are supported",
+        v.unwrap_or("")
+      )))
+    }
+  };
+  if tokens.get(1).map(String::as_str) != Some("0") {
+    return Err(lines.error("only ascii files are supported".to_owned()));
+  }
+  lines.end_section("MeshFormat")?;
+
+  Ok(version)
+}
+
+fn parse_physical_names<R: BufRead>(
+  lines: &mut Lines<R>,
+) -> Result<Vec<(i32, String)>, MeshError> {
+  let mut count = [0usize];
+  lines.values("physical name count", &mut count)?;
+
+  let mut names = Vec::new();
+  for _ in 0..count[0] {
+    let line = lines.expect("physical name")?;
+    let mut tokens = line.splitn(3, char::is_whitespace);
+    let mut dim_tag = [0i32; 2];
+    parse_values(
+      &[tokens.next().unwrap_or(""), tokens.next().unwrap_or("")],
+      lines.line,
+      &mut dim_tag,
+    )?;
+    let name = tokens.next().unwrap_or("").trim().trim_matches('"');
+
+    if dim_tag[0] == 3 {
+      names.push((dim_tag[1], name.to_owned()));
+    }
+  }
+  lines.end_section("PhysicalNames")?;
+
+  Ok(names)
+}
+
+// first physical tag of each volume entity (v4 only)
+fn parse_entities<R: BufRead>(
+  lines: &mut Lines<R>,
+) -> Result<HashMap<i32, i32>, MeshError> {
+  let mut counts = [0usize; 4];
+  lines.values("entity counts", &mut counts)?;
+
+  for _ in 0..(counts[0] + counts[1] + counts[2]) {
+    lines.expect("entity")?;
+  }
+
+  let mut physical_tags = HashMap::new();
+  for _ in 0..counts[3] {
+    // tag, bounding box, physical tag count, physical tags, bounding surfaces
+    let tokens = lines.tokens("volume entity")?;
+    let mut values = [0.0f64; 8];
+    parse_values(&tokens[..8.min(tokens.len())], lines.line, &mut values)?;
+    let num_physical_tags = values[7] as usize;
+
+    if num_physical_tags > 0 {
+      let mut physical_tag = [0i32];
+      parse_values(
+        &tokens[8..9.min(tokens.len())],
+        lines.line,
+        &mut physical_tag,
+      )?;
+      physical_tags.insert(values[0] as i32, physical_tag[0]);
+    }
+  }
+  lines.end_section("Entities")?;
+
+  Ok(physical_tags)
+}
+
+fn parse_position<R: BufRead>(
+  lines: &Lines<R>,
+  tokens: &[String],
+) -> Result<Vector3<f32>, MeshError> {
+  let mut position = [0.0f32; 3];
+  parse_values(&tokens[..3.min(tokens.len())], lines.line, &mut position)?;
+
+  if !position.iter().all(|v| v.is_finite()) {
+    return Err(lines.error("vertex positions must be finite".to_owned()));
+  }
+
+  Ok(Vector3::from(position))
+}
+
+fn parse_nodes<R: BufRead>(
+  lines: &mut Lines<R>,
+  version: Version,
+) -> Result<Vec<(u64, Vector3<f32>)>, MeshError> {
+  let mut nodes = Vec::new();
+
+  match version {
+    Version::V2 => {
+      let mut count = [0usize];
+      lines.values("node count", &mut count)?;
+
+      for _ in 0..count[0] {
+        let tokens = lines.tokens("node")?;
+        let mut tag = [0u64];
+        parse_values(&tokens[..1.min(tokens.len())], lines.line, &mut tag)?;
+        nodes.push((tag[0], parse_position(lines, &tokens[1..])?));
+      }
+    }
+    Version::V4 => {
+      let mut counts = [0usize; 4];
+      lines.values("node counts", &mut counts)?;
+
+      for _ in 0..counts[0] {
+        // entity dim, entity tag, parametric, node count
+        let mut block = [0usize; 4];
+        lines.values("node block", &mut block)?;
+
+        let mut tags = Vec::with_capacity(block[3]);
+        for _ in 0..block[3] {
+          let mut tag = [0u64];
+          lines.values("node tag", &mut tag)?;
+          tags.push(tag[0]);
+        }
+        for tag in tags {
+          let tokens = lines.tokens("node coordinates")?;
+          nodes.push((tag, parse_position(lines, &tokens)?));
+        }
+      }
+    }
+  }
+  lines.end_section("Nodes")?;
+
+  Ok(nodes)
+}
+
+// tets as node tags along with their physical tags
+fn parse_elements<R: BufRead>(
+  lines: &mut Lines<R>,
+  version: Version,
+  volume_physical_tags: &HashMap<i32, i32>,
+) -> Result<Vec<([u64; 4], i32, usize)>, MeshError> {
+  let mut tetras = Vec::new();
+
+  match version {
+    Version::V2 => {
+      let mut count = [0usize];
+      lines.values("element count", &mut count)?;
+
+      for _ in 0..count[0] {
+        // number, type, tag count, tags, nodes
+        let tokens = lines.tokens("element")?;
+        let mut header = [0u32; 3];
+        parse_values(&tokens[..3.min(tokens.len())], lines.line, &mut header)?;
+        let [_, element_type, num_tags] = header;
+
+        if element_type == GMSH_TETRA {
+          let nodes_start = 3 + num_tags as usize;
+          let mut tags = vec![0i32; num_tags as usize];
+          parse_values(
+            &tokens[3..nodes_start.min(tokens.len())],
+            lines.line,
+            &mut tags,
+          )?;
+          let mut nodes = [0u64; 4];
+          parse_values(
+            &tokens[nodes_start.min(tokens.len())..],
+            lines.line,
+            &mut nodes,
+          )?;
+
+          tetras.push((nodes, tags.first().copied().unwrap_or(0), lines.line));
+        }
+      }
+    }
+    Version::V4 => {
+      let mut counts = [0usize; 4];
+      lines.values("element counts", &mut counts)?;
+
+      for _ in 0..counts[0] {
+        // entity dim, entity tag, element type, element count
+        let mut block = [0i32; 4];
+        lines.values("element block", &mut block)?;
+        let [_, entity_tag, element_type, count] = block;
+
+        for _ in 0..count {
+          if element_type as u32 == GMSH_TETRA {
+            let mut element = [0u64; 5];
+            lines.values("element", &mut element)?;
+            let mut nodes = [0u64; 4];
+            nodes.copy_from_slice(&element[1..]);
+            let physical_tag =
+              volume_physical_tags.get(&entity_tag).copied().unwrap_or(0);
+
+            tetras.push((nodes, physical_tag, lines.line));
+          } else {
+            lines.expect("element")?;
+          }
+        }
+      }
+    }
+  }
+  lines.end_section("Elements")?;
+
+  Ok(tetras)
+}
+
+/// Reads an ascii Gmsh file (version 2.2 or 4.1). Elements other than 4 node
+/// tets are ignored.
+pub fn parse_gmsh<R: BufRead>(
+  reader: R,
+  transform: Option<&Transform3<f32>>,
+) -> Result<GmshMesh, MeshError> {
+  let mut lines = Lines::new(reader);
+  let mut version = None;
+  let mut physical_names = Vec::new();
+  let mut volume_physical_tags = HashMap::new();
+  let mut nodes = Vec::new();
+  let mut elements = Vec::new();
+
+  while let Some(line) = lines.next()? {
+    let section = line.trim();
+
+    match (section, version) {
+      ("", _) => {}
+      ("$MeshFormat", _) => version = Some(parse_format(&mut lines)?),
+      (_, None) => {
+        return Err(lines.error("expected $MeshFormat first".to_owned()))
+      }
+      ("$PhysicalNames", _) => {
+        physical_names = parse_physical_names(&mut lines)?
+      }
+      ("$Entities", Some(Version::V4)) => {
+        volume_physical_tags = parse_entities(&mut lines)?
+      }
+      ("$Nodes", Some(version)) => nodes = parse_nodes(&mut lines, version)?,
+      ("$Elements", Some(version)) => {
+        elements = parse_elements(&mut lines, version, &volume_physical_tags)?
+      }
+      (section, _) if section.starts_with('$') => {
+        lines.skip_section(&section[1..])?
+      }
+      (section, _) => {
+        return Err(lines.error(format!("unexpected line '{}'", section)))
+      }
+    }
+  }
+
+  let node_idxs: HashMap<u64, usize> = nodes
+    .iter()
+    .enumerate()
+    .map(|(idx, (tag, _))| (*tag, idx))
+    .collect();
+
+  let mut used = vec![false; nodes.len()];
+  for (tetra_nodes, _, line) in &elements {
+    for tag in tetra_nodes {
+      let node_idx = *node_idxs.get(tag).ok_or_else(|| MeshError::Parse {
+        line: *line,
+        message: format!("unknown node {}", tag),
+      })?;
+      used[node_idx] = true;
+    }
+  }
+
+  // vertices are kept in node order, but only if a tet uses them
+  let mut vertex_idxs = vec![0; nodes.len()];
+  let mut vertices = Vec::new();
+  for (((_, position), vertex_idx), _) in nodes
+    .iter()
+    .zip(&mut vertex_idxs)
+    .zip(&used)
+    .filter(|(_, used)| **used)
+  {
+    *vertex_idx = vertices.len() as u32;
+    vertices.push(transform_vertex(*position, transform));
+  }
+
+  let (tetras, physical_tags) = elements
+    .iter()
+    .map(|(tetra_nodes, physical_tag, _)| {
+      let mut tetra = [0; 4];
+      for (tag, vertex_idx) in tetra_nodes.iter().zip(&mut tetra) {
+        *vertex_idx = vertex_idxs[node_idxs[tag]];
+      }
+
+      (tetra, *physical_tag)
+    })
+    .unzip();
+
+  let mesh = (vertices, tetras);
+  check_indices(&mesh)?;
+  let (vertices, tetras) = mesh;
+
+  Ok(GmshMesh {
+    vertices,
+    tetras,
+    physical_tags,
+    physical_names,
+  })
+}
+
+pub fn load_gmsh(
+  path: &Path,
+  transform: Option<&Transform3<f32>>,
+) -> Result<GmshMesh, MeshError> {
+  parse_gmsh(BufReader::new(File::open(path)?), transform)
+}
+
+#[cfg(test)]
+const GMSH_V2: &str = r#"$MeshFormat
+2.2 0 8
+$EndMeshFormat
+$PhysicalNames
+2
+2 1 "surface"
+3 2 "body part"
+$EndPhysicalNames
+$Nodes
+6
+1 0 0 0
+2 1 0 0
+3 0 1 0
+4 0 0 1
+5 5 5 5
+7 1 1 1
+$EndNodes
+$Elements
+4
+1 15 2 0 5 5
+2 2 2 1 1 1 2 3
+3 4 2 2 1 1 2 3 4
+4 4 2 2 1 2 3 4 7
+$EndElements
+$NodeData
+1
+"unused"
+$EndNodeData
+"#;
+
+#[cfg(test)]
+const GMSH_V4: &str = r#"$MeshFormat
+4.1 0 8
+$EndMeshFormat
+$PhysicalNames
+2
+2 1 "surface"
+3 2 "body part"
+$EndPhysicalNames
+$Entities
+1 0 1 1
+5 5 5 5 0
+1 0 0 0 1 1 0 1 1 0
+1 0 0 0 1 1 1 1 2 0
+$EndEntities
+$Nodes
+3 6 1 7
+0 5 0 1
+5
+5 5 5
+2 1 0 3
+1
+2
+3
+0 0 0
+1 0 0
+0 1 0
+3 1 0 2
+4
+7
+0 0 1
+1 1 1
+$EndNodes
+$Elements
+3 4 1 4
+0 5 15 1
+1 5
+2 1 2 1
+2 1 2 3
+3 1 4 2
+3 1 2 3 4
+4 2 3 4 7
+$EndElements
+"#;
+
+#[test]
+fn parses_v2_and_v4() {
+  let transform = Transform3::from_matrix_unchecked(
+    nalgebra::Translation3::new(0.0, 2.0, 0.0).to_homogeneous(),
+  );
+
+  for contents in &[GMSH_V2, GMSH_V4] {
+    let mesh = parse_gmsh(contents.as_bytes(), Some(&transform)).unwrap();
+
+    assert_eq!(mesh.vertices.len(), 5);
+    assert_eq!(mesh.vertices[0], Vector3::new(0.0, 2.0, 0.0));
+    assert_eq!(mesh.vertices[4], Vector3::new(1.0, 3.0, 1.0));
+    assert_eq!(mesh.tetras, vec![[0, 1, 2, 3], [1, 2, 3, 4]]);
+    assert_eq!(mesh.physical_tags, vec![2, 2]);
+    assert_eq!(mesh.physical_names, vec![(2, "body part".to_owned())]);
+  }
+}
+
+#[test]
+fn gmsh_parse_errors() {
+  let error_line = |contents: &str| match parse_gmsh(contents.as_bytes(), None)
+  {
+    Err(MeshError::Parse { line, .. }) => line,
+    other => panic!("unexpected {:?}", other),
+  };
+
+  assert_eq!(error_line("$MeshFormat\n4.0 0 8\n$EndMeshFormat\n"), 2);
+  assert_eq!(error_line("$MeshFormat\n2.2 1 8\n$EndMeshFormat\n"), 2);
+  assert_eq!(error_line("$Nodes\n0\n$EndNodes\n"), 1);
+  assert_eq!(error_line(&GMSH_V2.replace("1 2 3 4 7", "1 2 3 4 8")), 23);
+  assert_eq!(error_line(&GMSH_V4.replace("$EndNodes\n", "")), 32);
+
+  match parse_gmsh(
+    GMSH_V2
+      .replace("4 4 2 2 1", "4 2 2 2 1")
+      .replace("3 4 2", "3 2 2")
+      .as_bytes(),
+    None,
+  ) {
+    Err(MeshError::Empty) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+}
@@ -0,0 +1,484 @@
+pub mod generate;
+pub mod gmsh;
+pub mod quality;
+pub mod reorder;
+pub mod surface;
+pub mod tetgen;
+pub mod tetrahedralize;
+pub mod vtk;
+
+pub use generate::{
+  beam_mesh, box_mesh, cylinder_mesh, sphere_mesh, MeshShape,
+};
+pub use gmsh::{load_gmsh, GmshMesh};
+pub use quality::{analyze_mesh, repair_mesh, MeshQuality, TetraShapes};
+pub use reorder::{reorder_mesh, Reordering, VertexOrdering};
+pub use surface::{load_surface, Surface};
+pub use tetgen::{load_tetgen, TetGenMesh};
+pub use tetrahedralize::tetrahedralize;
+pub use vtk::{load_vtk, parse_vtk, parse_vtk_mesh, write_vtk, VtkMesh};
+
+use nalgebra::{Point3, Transform3, Vector3};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+pub type LoadedMesh = (Vec<Vector3<f32>>, Vec<[u32; 4]>);
+
+#[derive(Debug)]
+pub enum MeshError {
+  Io(io::Error),
+  /// line numbers start at 1
+  Parse {
+    line: usize,
+    message: String,
+  },
+  VertexIndexOutOfRange {
+    tetra: usize,
+    vertex: usize,
+    num_vertices: usize,
+  },
+  /// zero volume (or repeated vertices). Inverted (negative volume) tets
+  /// aren't errors, the forces only depend on the deformation of each tet
+  /// relative to its own rest shape, so either vertex order gives the same
+  /// tet
+  DegenerateTetra {
+    tetra: usize,
+  },
+  /// parameter indices given for a different number of tets than the mesh
+  /// has, see `SimMesh::with_tetra_params`
+  ParamIndexCount {
+    count: usize,
+    num_tetras: usize,
+  },
+  ParamIndexOutOfRange {
+    tetra: usize,
+    param: usize,
+    num_params: usize,
+  },
+  Empty,
+  /// for formats split over several files
+  InFile {
+    path: PathBuf,
+    error: Box<MeshError>,
+  },
+}
+
+impl fmt::Display for MeshError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MeshError::Io(error) => write!(f, "{}", error),
+      MeshError::Parse { line, message } => {
+        write!(f, "line {}: {}", line, message)
+      }
+      MeshError::VertexIndexOutOfRange {
+        tetra,
+        vertex,
+        num_vertices,
+      } => write!(
+        f,
+        "tetrahedron {} uses vertex {} but there are only {} vertices",
+        tetra, vertex, num_vertices
+      ),
+      MeshError::DegenerateTetra { tetra } => {
+        write!(f, "tetrahedron {} is degenerate (zero volume)", tetra)
+      }
+      MeshError::ParamIndexCount { count, num_tetras } => write!(
+        f,
+        "{} parameter indices given for {} tetrahedrons",
+        count, num_tetras
+      ),
+      MeshError::ParamIndexOutOfRange {
+        tetra,
+        param,
+        num_params,
+      } => write!(
+        f,
+        "tetrahedron {} uses parameters {} but there are only {} sets",
+        tetra, param, num_params
+      ),
+      MeshError::Empty => write!(f, "mesh has no tetrahedrons"),
+      MeshError::InFile { path, error } => {
+        write!(f, "{}: {}", path.display(), error)
+      }
+    }
+  }
+}
+
+impl Error for MeshError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      MeshError::Io(error) => Some(error),
+      MeshError::InFile { error, .. } => Some(error.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for MeshError {
+  fn from(error: io::Error) -> Self {
+    MeshError::Io(error)
+  }
+}
+
+/// Checks the mesh is non empty and every index refers to a vertex.
+pub fn check_indices((vertices, tetras): &LoadedMesh) -> Result<(), MeshError> {
+  if tetras.is_empty() {
+    return Err(MeshError::Empty);
+  }
+
+  for (tetra_idx, tetra) in tetras.iter().enumerate() {
+    for vertex_idx in tetra {
+      if *vertex_idx as usize >= vertices.len() {
+        return Err(MeshError::VertexIndexOutOfRange {
+          tetra: tetra_idx,
+          vertex: *vertex_idx as usize,
+          num_vertices: vertices.len(),
+        });
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Drops the vertices no tet uses, the other vertices keep their order.
+/// Indices must be in range.
+pub fn remove_unreferenced_vertices(
+  (vertices, tetras): LoadedMesh,
+) -> LoadedMesh {
+  let mut new_idxs = vec![None; vertices.len()];
+  for tetra in &tetras {
+    for vertex_idx in tetra {
+      new_idxs[*vertex_idx as usize] = Some(0);
+    }
+  }
+
+  let mut new_vertices = Vec::new();
+  for (vert, new_idx) in vertices.into_iter().zip(&mut new_idxs) {
+    if let Some(new_idx) = new_idx {
+      *new_idx = new_vertices.len() as u32;
+      new_vertices.push(vert);
+    }
+  }
+
+  let new_tetras = tetras
+    .iter()
+    .map(|tetra| {
+      let mut new_tetra = [0; 4];
+      for (vertex_idx, new_vertex_idx) in tetra.iter().zip(&mut new_tetra) {
+        *new_vertex_idx = new_idxs[*vertex_idx as usize].unwrap();
+      }
+      new_tetra
+    })
+    .collect();
+
+  (new_vertices, new_tetras)
+}
+
+pub(crate) fn parse_values<T: std::str::FromStr, V: AsRef<str>>(
+  values: &[V],
+  line: usize,
+  out: &mut [T],
+) -> Result<(), MeshError>
+where
+  T::Err: fmt::Display,
+{
+  let parse_error = |message| MeshError::Parse { line, message };
+
+  if values.len() != out.len() {
+    return Err(parse_error(format!(
+      "expected {} values, found {}",
+      out.len(),
+      values.len()
+    )));
+  }
+
+  for (value, out) in values.iter().zip(out.iter_mut()) {
+    let value = value.as_ref();
+    *out = value.parse().map_err(|err| {
+      parse_error(format!("invalid value '{}': {}", value, err))
+    })?;
+  }
+
+  Ok(())
+}
+
+// vertices are transformed as points so translations apply
+pub(crate) fn transform_vertex(
+  vert: Vector3<f32>,
+  transform: Option<&Transform3<f32>>,
+) -> Vector3<f32> {
+  match transform {
+    Some(transform) => (transform * Point3::from(vert)).coords,
+    None => vert,
+  }
+}
+
+/// Reads the `v x y z` / `t a b c d` format. Blank lines and lines starting
+/// with `#` are ignored, anything else is an error.
+pub fn parse_mesh<R: BufRead>(
+  reader: R,
+  transform: Option<&Transform3<f32>>,
+) -> Result<LoadedMesh, MeshError> {
+  let mut vertices = Vec::new();
+  let mut tetras = Vec::new();
+
+  for (line_idx, line) in reader.lines().enumerate() {
+    let line = line?;
+    let line_num = line_idx + 1;
+
+    let tokens: Vec<_> = line.split_whitespace().collect();
+
+    match tokens.first() {
+      None => {}
+      Some(token) if token.starts_with('#') => {}
+      Some(&"v") => {
+        let mut vert = [0.0f32; 3];
+        parse_values(&tokens[1..], line_num, &mut vert)?;
+
+        if !vert.iter().all(|v| v.is_finite()) {
+          return Err(MeshError::Parse {
+            line: line_num,
+            message: "vertex positions must be finite".to_owned(),
+          });
+        }
+
+        vertices.push(transform_vertex(Vector3::from(vert), transform));
+      }
+      Some(&"t") => {
+        let mut tetra = [0; 4];
+        parse_values(&tokens[1..], line_num, &mut tetra)?;
+
+        tetras.push(tetra);
+      }
+      Some(token) => {
+        return Err(MeshError::Parse {
+          line: line_num,
+          message: format!("unrecognized line type '{}'", token),
+        })
+      }
+    }
+  }
+
+  let mesh = (vertices, tetras);
+
+  check_indices(&mesh)?;
+
+  Ok(mesh)
+}
+
+/// Writes the `v`/`t` format read by `parse_mesh`.
+pub fn write_mesh<W: Write>(
+  writer: &mut W,
+  (vertices, tetras): &LoadedMesh,
+) -> io::Result<()> {
+  for vert in vertices {
+    writeln!(writer, "v {} {} {}", vert[0], vert[1], vert[2])?;
+  }
+  for tetra in tetras {
+    writeln!(
+      writer,
+      "t {} {} {} {}",
+      tetra[0], tetra[1], tetra[2], tetra[3]
+    )?;
+  }
+
+  Ok(())
+}
+
+/// `.vtk` files are written as legacy VTK and anything else in the `v`/`t`
+/// format.
+pub fn save_mesh(path: &Path, mesh: &LoadedMesh) -> io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  match path.extension().and_then(|ext| ext.to_str()) {
+    Some("vtk") => write_vtk(&mut writer, mesh)?,
+    _ => write_mesh(&mut writer, mesh)?,
+  }
+
+  writer.flush()
+}
+
+/// cells along the longest side when filling surfaces
+pub const DEFAULT_SURFACE_RESOLUTION: usize = 16;
+
+/// Fills a closed OBJ or STL surface with tets, see `tetrahedralize`.
+pub fn load_surface_as_mesh(
+  path: &Path,
+  resolution: usize,
+  transform: Option<&Transform3<f32>>,
+) -> Result<LoadedMesh, MeshError> {
+  let (vertices, tetras) = tetrahedralize(&load_surface(path)?, resolution)?;
+
+  Ok((
+    vertices
+      .into_iter()
+      .map(|vert| transform_vertex(vert, transform))
+      .collect(),
+    tetras,
+  ))
+}
+
+/// The loader is picked from the extension: `.node`/`.ele` are read as TetGen
+/// output, `.msh` as Gmsh, `.vtk` as legacy VTK, `.obj`/`.stl` surfaces are
+/// filled with tets and anything else is read as the `v`/`t` format.
+pub fn load_mesh_with_transform(
+  path: &Path,
+  transform: Option<&Transform3<f32>>,
+) -> Result<LoadedMesh, MeshError> {
+  match path.extension().and_then(|ext| ext.to_str()) {
+    Some("node") | Some("ele") => {
+      load_tetgen(path, transform).map(TetGenMesh::into_loaded)
+    }
+    Some("msh") => load_gmsh(path, transform).map(GmshMesh::into_loaded),
+    Some("vtk") => load_vtk(path, transform).map(VtkMesh::into_loaded),
+    Some("obj") | Some("stl") => {
+      load_surface_as_mesh(path, DEFAULT_SURFACE_RESOLUTION, transform)
+    }
+    _ => parse_mesh(BufReader::new(File::open(path)?), transform),
+  }
+}
+
+/// Like `load_mesh_with_transform`, along with the region of each tet for
+/// formats which have them: the region attribute of TetGen meshes and the
+/// physical group of Gmsh meshes.
+pub fn load_mesh_with_regions(
+  path: &Path,
+  transform: Option<&Transform3<f32>>,
+) -> Result<(LoadedMesh, Option<Vec<i32>>), MeshError> {
+  match path.extension().and_then(|ext| ext.to_str()) {
+    Some("node") | Some("ele") => {
+      let mesh = load_tetgen(path, transform)?;
+      let regions = mesh.regions.clone();
+
+      Ok((mesh.into_loaded(), regions))
+    }
+    Some("msh") => {
+      let mesh = load_gmsh(path, transform)?;
+      let regions = Some(mesh.physical_tags.clone());
+
+      Ok((mesh.into_loaded(), regions))
+    }
+    _ => Ok((load_mesh_with_transform(path, transform)?, None)),
+  }
+}
+
+pub fn load_mesh(path: &Path) -> Result<LoadedMesh, MeshError> {
+  load_mesh_with_transform(path, None)
+}
+
+#[test]
+fn parse_errors() {
+  let parse = |contents: &str| parse_mesh(contents.as_bytes(), None);
+
+  let (vertices, tetras) =
+    parse("# comment\nv 0 0 0\nv 1 0 0\n\nv 0 1 0\nv 0 0 1e0\nt 0 1 2 3\n")
+      .unwrap();
+  assert_eq!(vertices.len(), 4);
+  assert_eq!(tetras, vec![[0, 1, 2, 3]]);
+
+  match parse("v 0 0 0\nv 1 0 x\n") {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse("v 0 0 0\nt 0 0 0\n") {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse("v 0 0 0\nf 0 0 0\n") {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse("v 0 0 0\nt 0 0 0 1\n") {
+    Err(MeshError::VertexIndexOutOfRange {
+      tetra: 0,
+      vertex: 1,
+      num_vertices: 1,
+    }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse("v 0 0 0\n") {
+    Err(MeshError::Empty) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+}
+
+#[test]
+fn transform_translates() {
+  let transform = Transform3::from_matrix_unchecked(
+    nalgebra::Translation3::new(1.0, 2.0, 3.0).to_homogeneous()
+      * nalgebra::Matrix4::new_scaling(2.0),
+  );
+
+  let (vertices, _) = parse_mesh(
+    "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nt 0 1 2 3\n".as_bytes(),
+    Some(&transform),
+  )
+  .unwrap();
+  assert_eq!(vertices[0], Vector3::new(1.0, 2.0, 3.0));
+  assert_eq!(vertices[1], Vector3::new(3.0, 2.0, 3.0));
+}
+
+#[test]
+fn save_load_round_trip() {
+  let mesh = load_mesh(Path::new("meshes/ellipsoid.mesh")).unwrap();
+
+  for extension in &["mesh", "vtk"] {
+    let path = std::env::temp_dir().join(format!(
+      "save_load_round_trip_{}.{}",
+      std::process::id(),
+      extension
+    ));
+
+    save_mesh(&path, &mesh).unwrap();
+    let loaded = load_mesh(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), mesh);
+  }
+}
+
+#[test]
+fn loads_surfaces_as_tets() {
+  let path = std::env::temp_dir()
+    .join(format!("loads_surfaces_as_tets_{}.obj", std::process::id()));
+  // a tetrahedron
+  std::fs::write(
+    &path,
+    "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 3 2\nf 1 2 4\nf 1 4 3\n\
+     f 2 3 4\n",
+  )
+  .unwrap();
+  let loaded = load_mesh(&path);
+  let translated = load_mesh_with_transform(
+    &path,
+    Some(&nalgebra::convert(nalgebra::Translation3::new(
+      0.0, 2.0, 0.0,
+    ))),
+  );
+  std::fs::remove_file(&path).unwrap();
+
+  let (vertices, tetras) = loaded.unwrap();
+  check_indices(&(vertices.clone(), tetras.clone())).unwrap();
+  assert!(!tetras.is_empty());
+  assert!(vertices.iter().all(|v| v.min() > -0.1 && v.sum() < 1.1));
+
+  let (translated, _) = translated.unwrap();
+  assert_eq!(translated[0], vertices[0] + Vector3::new(0.0, 2.0, 0.0));
+}
+
+#[test]
+fn removes_unreferenced_vertices() {
+  let vertices = (0..6).map(|i| Vector3::new(i as f32, 0.0, 0.0)).collect();
+  let (vertices, tetras) =
+    remove_unreferenced_vertices((vertices, vec![[5, 1, 2, 4], [2, 1, 4, 5]]));
+
+  assert_eq!(
+    vertices.iter().map(|v| v[0]).collect::<Vec<_>>(),
+    vec![1.0, 2.0, 4.0, 5.0]
+  );
+  assert_eq!(tetras, vec![[3, 0, 1, 2], [1, 0, 2, 3]]);
+}
@@ -0,0 +1,359 @@
+use crate::mesh::{
+  check_indices, remove_unreferenced_vertices, LoadedMesh, MeshError,
+};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Quality measures of a tet mesh, see `analyze_mesh`.
+#[derive(Clone, Debug)]
+pub struct MeshQuality {
+  pub num_vertices: usize,
+  pub num_tetras: usize,
+  pub num_boundary_faces: usize,
+  /// sum of the unsigned tet volumes
+  pub volume: f64,
+  /// None if every tet is degenerate
+  pub shapes: Option<TetraShapes>,
+  /// oriented against the majority of the tets
+  pub inverted_tetras: Vec<usize>,
+  /// zero volume or repeated vertices, as rejected by `SimMesh::new`
+  pub degenerate_tetras: Vec<usize>,
+  pub unreferenced_vertices: Vec<u32>,
+  /// faces shared by more than two tets and boundary faces with an edge which
+  /// isn't shared by exactly two boundary faces, with sorted indices
+  pub non_manifold_faces: Vec<[u32; 3]>,
+}
+
+/// Shape measures over the tets which aren't degenerate.
+#[derive(Clone, Debug)]
+pub struct TetraShapes {
+  /// in radians
+  pub min_dihedral_angle: f64,
+  pub max_dihedral_angle: f64,
+  /// longest edge over inradius, scaled so a regular tet is 1
+  pub mean_aspect_ratio: f64,
+  pub max_aspect_ratio: f64,
+}
+
+impl MeshQuality {
+  pub fn mass(&self, density: f64) -> f64 {
+    self.volume * density
+  }
+}
+
+impl fmt::Display for MeshQuality {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(
+      f,
+      "vertices: {} ({} unreferenced)",
+      self.num_vertices,
+      self.unreferenced_vertices.len()
+    )?;
+    writeln!(
+      f,
+      "tets: {} ({} inverted, {} degenerate)",
+      self.num_tetras,
+      self.inverted_tetras.len(),
+      self.degenerate_tetras.len()
+    )?;
+    writeln!(
+      f,
+      "boundary faces: {} ({} non-manifold)",
+      self.num_boundary_faces,
+      self.non_manifold_faces.len()
+    )?;
+    writeln!(f, "volume: {}", self.volume)?;
+    match &self.shapes {
+      Some(shapes) => {
+        writeln!(
+          f,
+          "dihedral angles: {:.2} to {:.2} degrees",
+          shapes.min_dihedral_angle.to_degrees(),
+          shapes.max_dihedral_angle.to_degrees()
+        )?;
+        write!(
+          f,
+          "aspect ratios: {:.3} mean, {:.3} max",
+          shapes.mean_aspect_ratio, shapes.max_aspect_ratio
+        )
+      }
+      None => write!(f, "no valid tets for dihedral angles or aspect ratios"),
+    }
+  }
+}
+
+fn tetra_positions(
+  vertices: &[Vector3<f32>],
+  tetra: &[u32; 4],
+) -> [Vector3<f64>; 4] {
+  let get_vertex =
+    |idx: usize| nalgebra::convert(vertices[tetra[idx] as usize]);
+
+  [get_vertex(0), get_vertex(1), get_vertex(2), get_vertex(3)]
+}
+
+fn signed_volume(p: &[Vector3<f64>; 4]) -> f64 {
+  (p[1] - p[0]).cross(&(p[2] - p[0])).dot(&(p[3] - p[0])) / 6.0
+}
+
+fn max_edge(p: &[Vector3<f64>; 4]) -> f64 {
+  let mut max_edge: f64 = 0.0;
+  for i in 0..4 {
+    for j in i + 1..4 {
+      max_edge = max_edge.max((p[i] - p[j]).norm());
+    }
+  }
+
+  max_edge
+}
+
+// relative to the size of the tet, same as `SimMesh::new`
+fn is_degenerate(p: &[Vector3<f64>; 4]) -> bool {
+  signed_volume(p).abs() <= f64::EPSILON * max_edge(p).powi(3)
+}
+
+// tets which aren't degenerate and are oriented against the majority
+fn find_inverted(vertices: &[Vector3<f32>], tetras: &[[u32; 4]]) -> Vec<usize> {
+  let signs: Vec<_> = tetras
+    .iter()
+    .map(|tetra| {
+      let p = tetra_positions(vertices, tetra);
+      if is_degenerate(&p) {
+        0.0
+      } else {
+        signed_volume(&p).signum()
+      }
+    })
+    .collect();
+
+  let majority = if signs.iter().sum::<f64>() < 0.0 {
+    -1.0
+  } else {
+    1.0
+  };
+
+  signs
+    .iter()
+    .enumerate()
+    .filter(|(_, sign)| **sign == -majority)
+    .map(|(tetra_idx, _)| tetra_idx)
+    .collect()
+}
+
+fn sorted_face(tetra: &[u32; 4], skip: usize) -> [u32; 3] {
+  let mut face = [0; 3];
+  for (face_vertex, idx) in
+    face.iter_mut().zip((0..4).filter(|idx| *idx != skip))
+  {
+    *face_vertex = tetra[idx];
+  }
+  face.sort_unstable();
+
+  face
+}
+
+/// Measures the quality of each tet along with the topology of the mesh.
+/// Tets are accepted in either orientation, so tets are only reported as
+/// inverted if most of the mesh is oriented the other way.
+pub fn analyze_mesh(mesh: &LoadedMesh) -> Result<MeshQuality, MeshError> {
+  check_indices(mesh)?;
+
+  let (vertices, tetras) = mesh;
+
+  let mut volume = 0.0;
+  let mut min_dihedral_angle = PI;
+  let mut max_dihedral_angle: f64 = 0.0;
+  let mut total_aspect_ratio = 0.0;
+  let mut max_aspect_ratio: f64 = 0.0;
+  let mut degenerate_tetras = Vec::new();
+
+  for (tetra_idx, tetra) in tetras.iter().enumerate() {
+    let p = tetra_positions(vertices, tetra);
+    let tetra_volume = signed_volume(&p).abs();
+    volume += tetra_volume;
+
+    if is_degenerate(&p) {
+      degenerate_tetras.push(tetra_idx);
+      continue;
+    }
+
+    // outward normals of the faces opposite each vertex, scaled by twice the
+    // face area
+    let mut normals = [Vector3::zeros(); 4];
+    for (opposite, normal) in normals.iter_mut().enumerate() {
+      let face: Vec<_> = (0..4).filter(|idx| *idx != opposite).collect();
+      let (a, b, c) = (p[face[0]], p[face[1]], p[face[2]]);
+      *normal = (b - a).cross(&(c - a));
+      if normal.dot(&(p[opposite] - a)) > 0.0 {
+        *normal *= -1.0;
+      }
+    }
+
+    // the faces opposite two vertices meet along the edge between the other
+    // two
+    for i in 0..4 {
+      for j in i + 1..4 {
+        let (n_i, n_j) = (normals[i], normals[j]);
+        let angle = PI - n_i.cross(&n_j).norm().atan2(n_i.dot(&n_j));
+
+        min_dihedral_angle = min_dihedral_angle.min(angle);
+        max_dihedral_angle = max_dihedral_angle.max(angle);
+      }
+    }
+
+    // inradius is 3 V / A
+    let area: f64 = normals.iter().map(|n| n.norm() / 2.0).sum();
+    let aspect_ratio =
+      max_edge(&p) * area / (6.0 * 6.0f64.sqrt() * tetra_volume);
+
+    total_aspect_ratio += aspect_ratio;
+    max_aspect_ratio = max_aspect_ratio.max(aspect_ratio);
+  }
+
+  let mut referenced = vec![false; vertices.len()];
+  let mut face_counts = HashMap::new();
+  for tetra in tetras {
+    for skip in 0..4 {
+      referenced[tetra[skip] as usize] = true;
+      *face_counts.entry(sorted_face(tetra, skip)).or_insert(0) += 1;
+    }
+  }
+
+  let boundary_faces: Vec<_> = face_counts
+    .iter()
+    .filter(|(_, count)| **count == 1)
+    .map(|(face, _)| *face)
+    .collect();
+
+  let mut edge_counts = HashMap::new();
+  for face in &boundary_faces {
+    for (a, b) in &[(0, 1), (1, 2), (0, 2)] {
+      *edge_counts.entry((face[*a], face[*b])).or_insert(0) += 1;
+    }
+  }
+
+  let mut non_manifold_faces: Vec<_> = face_counts
+    .iter()
+    .filter(|(_, count)| **count > 2)
+    .map(|(face, _)| *face)
+    .chain(boundary_faces.iter().copied().filter(|face| {
+      [(0, 1), (1, 2), (0, 2)]
+        .iter()
+        .any(|(a, b)| edge_counts[&(face[*a], face[*b])] != 2)
+    }))
+    .collect();
+  non_manifold_faces.sort_unstable();
+
+  let num_measured = tetras.len() - degenerate_tetras.len();
+  let shapes = if num_measured > 0 {
+    Some(TetraShapes {
+      min_dihedral_angle,
+      max_dihedral_angle,
+      mean_aspect_ratio: total_aspect_ratio / num_measured as f64,
+      max_aspect_ratio,
+    })
+  } else {
+    None
+  };
+
+  Ok(MeshQuality {
+    num_vertices: vertices.len(),
+    num_tetras: tetras.len(),
+    num_boundary_faces: boundary_faces.len(),
+    volume,
+    shapes,
+    inverted_tetras: find_inverted(vertices, tetras),
+    degenerate_tetras,
+    unreferenced_vertices: referenced
+      .iter()
+      .enumerate()
+      .filter(|(_, referenced)| !**referenced)
+      .map(|(vertex_idx, _)| vertex_idx as u32)
+      .collect(),
+    non_manifold_faces,
+  })
+}
+
+/// Reorients inverted tets to match the rest of the mesh and drops
+/// unreferenced vertices. Degenerate tets and non-manifold faces are left
+/// alone. Indices must be in range.
+pub fn repair_mesh((vertices, mut tetras): LoadedMesh) -> LoadedMesh {
+  for tetra_idx in find_inverted(&vertices, &tetras) {
+    tetras[tetra_idx].swap(2, 3);
+  }
+
+  remove_unreferenced_vertices((vertices, tetras))
+}
+
+#[test]
+fn analyzes_box() {
+  let mesh = crate::mesh::box_mesh(Vector3::new(2.0, 1.0, 1.0), [2, 1, 1]);
+  let quality = analyze_mesh(&mesh).unwrap();
+
+  assert_eq!(quality.num_vertices, 12);
+  assert_eq!(quality.num_tetras, 12);
+  // two triangles per square
+  assert_eq!(quality.num_boundary_faces, 2 * 10);
+  assert!((quality.volume - 2.0).abs() < 1e-6);
+  assert!((quality.mass(3.0) - 6.0).abs() < 1e-5);
+
+  // the angles of the cube split into tets
+  let shapes = quality.shapes.unwrap();
+  assert!((shapes.min_dihedral_angle.to_degrees() - 45.0).abs() < 1e-4);
+  assert!((shapes.max_dihedral_angle.to_degrees() - 90.0).abs() < 1e-4);
+  assert!(shapes.mean_aspect_ratio > 1.0);
+  assert!(shapes.max_aspect_ratio >= shapes.mean_aspect_ratio);
+
+  assert!(quality.inverted_tetras.is_empty());
+  assert!(quality.degenerate_tetras.is_empty());
+  assert!(quality.unreferenced_vertices.is_empty());
+  assert!(quality.non_manifold_faces.is_empty());
+}
+
+#[test]
+fn finds_and_repairs_problems() {
+  let (mut vertices, mut tetras) =
+    crate::mesh::box_mesh(Vector3::repeat(1.0), [2, 2, 2]);
+  let num_tetras = tetras.len();
+
+  tetras[3].swap(0, 1);
+  tetras[7].swap(1, 3);
+  // a repeated vertex and a duplicate of a tet
+  tetras.push([0, 1, 1, 2]);
+  tetras.push(tetras[0]);
+  vertices.push(Vector3::repeat(5.0));
+
+  let mesh = (vertices, tetras);
+  let quality = analyze_mesh(&mesh).unwrap();
+
+  assert_eq!(quality.inverted_tetras, vec![3, 7]);
+  assert_eq!(quality.degenerate_tetras, vec![num_tetras]);
+  assert_eq!(quality.unreferenced_vertices, vec![27]);
+  // each face of the duplicated tet is now shared by too many tets or has
+  // become internal
+  assert!(!quality.non_manifold_faces.is_empty());
+
+  let repaired = repair_mesh(mesh);
+  let quality = analyze_mesh(&repaired).unwrap();
+  assert_eq!(repaired.0.len(), 27);
+  assert!(quality.inverted_tetras.is_empty());
+  assert!(quality.unreferenced_vertices.is_empty());
+  assert_eq!(quality.degenerate_tetras, vec![num_tetras]);
+}
+
+#[test]
+fn all_degenerate_has_no_shapes() {
+  let vertices = vec![
+    Vector3::new(0.0, 0.0, 0.0),
+    Vector3::new(1.0, 0.0, 0.0),
+    Vector3::new(0.0, 1.0, 0.0),
+    Vector3::new(1.0, 1.0, 0.0),
+  ];
+  let quality = analyze_mesh(&(vertices, vec![[0, 1, 2, 3]])).unwrap();
+
+  assert_eq!(quality.degenerate_tetras, vec![0]);
+  assert!(quality.shapes.is_none());
+  assert!(quality.to_string().contains("no valid tets"));
+}
@@ -0,0 +1,304 @@
+use crate::mesh::LoadedMesh;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// How vertices are numbered when building a `SimMesh`. Forces are scattered
+/// to the vertices of each tet in turn, so for large meshes a numbering where
+/// neighboring vertices are close in memory is much more cache friendly.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VertexOrdering {
+  /// as loaded
+  Original,
+  /// reverse Cuthill-McKee, breadth first along the edges of the mesh
+  Rcm,
+  /// along a Morton (z order) curve through the bounding box
+  Morton,
+}
+
+impl FromStr for VertexOrdering {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "original" => Ok(VertexOrdering::Original),
+      "rcm" => Ok(VertexOrdering::Rcm),
+      "morton" => Ok(VertexOrdering::Morton),
+      _ => Err(format!(
+        "unknown vertex ordering '{}' (expected original, rcm or morton)",
+        s
+      )),
+    }
+  }
+}
+
+/// Original index of each vertex and tet after `reorder_mesh`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reordering {
+  pub vertices: Vec<u32>,
+  pub tetras: Vec<u32>,
+}
+
+impl Reordering {
+  pub fn identity((vertices, tetras): &LoadedMesh) -> Self {
+    Self {
+      vertices: (0..vertices.len() as u32).collect(),
+      tetras: (0..tetras.len() as u32).collect(),
+    }
+  }
+
+  /// Puts values given in the new order back in the original order.
+  pub fn to_original<T: Clone>(
+    new_to_original: &[u32],
+    values: &[T],
+  ) -> Vec<T> {
+    let mut original = values.to_vec();
+    for (value, original_idx) in values.iter().zip(new_to_original) {
+      original[*original_idx as usize] = value.clone();
+    }
+
+    original
+  }
+}
+
+// neighbors of each vertex along the edges of the tets, sorted
+fn vertex_neighbors(num_vertices: usize, tetras: &[[u32; 4]]) -> Vec<Vec<u32>> {
+  let mut neighbors = vec![Vec::new(); num_vertices];
+  for tetra in tetras {
+    for a in tetra {
+      for b in tetra {
+        if a != b {
+          neighbors[*a as usize].push(*b);
+        }
+      }
+    }
+  }
+
+  for vertex_neighbors in &mut neighbors {
+    vertex_neighbors.sort_unstable();
+    vertex_neighbors.dedup();
+  }
+
+  neighbors
+}
+
+// breadth first from start, neighbors are visited by increasing degree
+fn cuthill_mckee_component(
+  neighbors: &[Vec<u32>],
+  start: u32,
+  visited: &mut [bool],
+  order: &mut Vec<u32>,
+) {
+  let mut queue = VecDeque::new();
+  visited[start as usize] = true;
+  queue.push_back(start);
+
+  let mut next = Vec::new();
+  while let Some(vertex_idx) = queue.pop_front() {
+    order.push(vertex_idx);
+
+    next.clear();
+    next.extend(
+      neighbors[vertex_idx as usize]
+        .iter()
+        .copied()
+        .filter(|idx| !visited[*idx as usize]),
+    );
+    next.sort_by_key(|idx| neighbors[*idx as usize].len());
+
+    for idx in &next {
+      visited[*idx as usize] = true;
+      queue.push_back(*idx);
+    }
+  }
+}
+
+// the last vertex reached by a breadth first search, which is far from start
+fn farthest_vertex(neighbors: &[Vec<u32>], start: u32) -> u32 {
+  let mut visited = vec![false; neighbors.len()];
+  let mut order = Vec::new();
+  cuthill_mckee_component(neighbors, start, &mut visited, &mut order);
+
+  *order.last().unwrap()
+}
+
+fn rcm_order(num_vertices: usize, tetras: &[[u32; 4]]) -> Vec<u32> {
+  let neighbors = vertex_neighbors(num_vertices, tetras);
+
+  let mut by_degree: Vec<u32> = (0..num_vertices as u32).collect();
+  by_degree.sort_by_key(|idx| neighbors[*idx as usize].len());
+
+  let mut visited = vec![false; num_vertices];
+  let mut order = Vec::with_capacity(num_vertices);
+  for start in by_degree {
+    if !visited[start as usize] {
+      // starting from a pseudo peripheral vertex keeps the levels narrow
+      let start = farthest_vertex(&neighbors, start);
+      cuthill_mckee_component(&neighbors, start, &mut visited, &mut order);
+    }
+  }
+
+  order.reverse();
+
+  order
+}
+
+// spreads the lower 21 bits out to every third bit
+fn spread_bits(value: u64) -> u64 {
+  let mut value = value & 0x1f_ffff;
+  value = (value | value << 32) & 0x1f_0000_0000_ffff;
+  value = (value | value << 16) & 0x1f_0000_ff00_00ff;
+  value = (value | value << 8) & 0x100f_00f0_0f00_f00f;
+  value = (value | value << 4) & 0x10c3_0c30_c30c_30c3;
+  value = (value | value << 2) & 0x1249_2492_4924_9249;
+
+  value
+}
+
+fn morton_order((vertices, _): &LoadedMesh) -> Vec<u32> {
+  let mut min = vertices[0];
+  let mut max = vertices[0];
+  for vert in vertices {
+    min = min.zip_map(vert, f32::min);
+    max = max.zip_map(vert, f32::max);
+  }
+  let extent = (max - min).max().max(f32::MIN_POSITIVE);
+
+  let codes: Vec<u64> = vertices
+    .iter()
+    .map(|vert| {
+      let cell = ((vert - min) / extent * ((1 << 21) - 1) as f32)
+        .map(|coord| coord as u64);
+
+      spread_bits(cell[0])
+        | spread_bits(cell[1]) << 1
+        | spread_bits(cell[2]) << 2
+    })
+    .collect();
+
+  let mut order: Vec<u32> = (0..vertices.len() as u32).collect();
+  order.sort_by_key(|idx| codes[*idx as usize]);
+
+  order
+}
+
+/// Renumbers the vertices in the given order, then sorts the tets by their
+/// lowest vertex in the new numbering so tets sharing vertices are also close
+/// together. Indices must be in range.
+pub fn reorder_mesh(
+  mesh: LoadedMesh,
+  ordering: VertexOrdering,
+) -> (LoadedMesh, Reordering) {
+  let new_to_original = match ordering {
+    VertexOrdering::Original => {
+      let reordering = Reordering::identity(&mesh);
+      return (mesh, reordering);
+    }
+    VertexOrdering::Rcm => rcm_order(mesh.0.len(), &mesh.1),
+    VertexOrdering::Morton if mesh.0.is_empty() => Vec::new(),
+    VertexOrdering::Morton => morton_order(&mesh),
+  };
+
+  let (vertices, tetras) = mesh;
+
+  let mut original_to_new = vec![0; vertices.len()];
+  for (new_idx, original_idx) in new_to_original.iter().enumerate() {
+    original_to_new[*original_idx as usize] = new_idx as u32;
+  }
+
+  let new_tetras: Vec<[u32; 4]> = tetras
+    .iter()
+    .map(|tetra| {
+      let mut new_tetra = [0; 4];
+      for (new_idx, idx) in new_tetra.iter_mut().zip(tetra) {
+        *new_idx = original_to_new[*idx as usize];
+      }
+
+      new_tetra
+    })
+    .collect();
+
+  let mut tetra_order: Vec<u32> = (0..tetras.len() as u32).collect();
+  tetra_order.sort_by_key(|idx| new_tetras[*idx as usize].iter().min());
+
+  (
+    (
+      new_to_original
+        .iter()
+        .map(|idx| vertices[*idx as usize])
+        .collect(),
+      tetra_order
+        .iter()
+        .map(|idx| new_tetras[*idx as usize])
+        .collect(),
+    ),
+    Reordering {
+      vertices: new_to_original,
+      tetras: tetra_order,
+    },
+  )
+}
+
+// mean spread of the vertex indices of each tet
+#[cfg(test)]
+fn mean_spread(tetras: &[[u32; 4]]) -> f64 {
+  tetras
+    .iter()
+    .map(|tetra| tetra.iter().max().unwrap() - tetra.iter().min().unwrap())
+    .sum::<u32>() as f64
+    / tetras.len() as f64
+}
+
+#[test]
+fn reorders_consistently() {
+  use crate::mesh::box_mesh;
+  use nalgebra::Vector3;
+
+  let (vertices, mut tetras) = box_mesh(Vector3::repeat(1.0), [6, 5, 4]);
+  // shuffle the vertices so there is something to improve
+  let shuffle: Vec<u32> = (0..vertices.len() as u32)
+    .map(|idx| (idx * 37) % vertices.len() as u32)
+    .collect();
+  let mut shuffled = vertices.clone();
+  for (idx, new_idx) in shuffle.iter().enumerate() {
+    shuffled[*new_idx as usize] = vertices[idx];
+  }
+  for tetra in &mut tetras {
+    for idx in tetra.iter_mut() {
+      *idx = shuffle[*idx as usize];
+    }
+  }
+  let mesh = (shuffled, tetras);
+
+  for ordering in &[
+    VertexOrdering::Original,
+    VertexOrdering::Rcm,
+    VertexOrdering::Morton,
+  ] {
+    let (reordered, reordering) = reorder_mesh(mesh.clone(), *ordering);
+
+    // the same mesh, just renumbered
+    assert_eq!(
+      Reordering::to_original(&reordering.vertices, &reordered.0),
+      mesh.0
+    );
+    for (tetra, original_idx) in reordered.1.iter().zip(&reordering.tetras) {
+      let original = mesh.1[*original_idx as usize];
+      for (idx, original_idx) in tetra.iter().zip(&original) {
+        assert_eq!(reordering.vertices[*idx as usize], *original_idx);
+      }
+    }
+
+    if *ordering != VertexOrdering::Original {
+      assert!(
+        mean_spread(&reordered.1) * 2.0 < mean_spread(&mesh.1),
+        "{:?}",
+        ordering
+      );
+    }
+  }
+
+  assert_eq!("RCM".parse(), Ok(VertexOrdering::Rcm));
+  assert!("hilbert".parse::<VertexOrdering>().is_err());
+}
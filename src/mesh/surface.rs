@@ -0,0 +1,266 @@
+use crate::mesh::{parse_values, MeshError};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{prelude::*, BufReader};
+use std::path::Path;
+
+/// Triangle surface, vertices and faces.
+pub type Surface = (Vec<Vector3<f32>>, Vec<[u32; 3]>);
+
+fn parse_vertex(
+  values: &[&str],
+  line: usize,
+) -> Result<Vector3<f32>, MeshError> {
+  let mut vert = [0.0f32; 3];
+  parse_values(values, line, &mut vert)?;
+
+  if !vert.iter().all(|v| v.is_finite()) {
+    return Err(MeshError::Parse {
+      line,
+      message: "vertex positions must be finite".to_owned(),
+    });
+  }
+
+  Ok(Vector3::from(vert))
+}
+
+/// Reads the vertices and faces of an OBJ file, polygons are split into fans
+/// of triangles. Everything else (normals, texture coordinates, groups...)
+/// is ignored.
+pub fn parse_obj<R: BufRead>(reader: R) -> Result<Surface, MeshError> {
+  let mut vertices = Vec::new();
+  let mut faces = Vec::new();
+
+  for (line_idx, line) in reader.lines().enumerate() {
+    let line = line?;
+    let line_num = line_idx + 1;
+    let parse_error = |message| MeshError::Parse {
+      line: line_num,
+      message,
+    };
+
+    let tokens: Vec<_> = line.split_whitespace().collect();
+
+    match tokens.first() {
+      Some(&"v") => {
+        // optional w or vertex colors follow
+        if tokens.len() < 4 {
+          return Err(parse_error("expected 3 coordinates".to_owned()));
+        }
+        vertices.push(parse_vertex(&tokens[1..4], line_num)?);
+      }
+      Some(&"f") => {
+        let polygon = tokens[1..]
+          .iter()
+          .map(|token| {
+            // v, v/vt, v//vn or v/vt/vn, negative indices are relative to the
+            // end
+            let index = token.split('/').next().unwrap_or("");
+            let mut number = [0i64];
+            parse_values(&[index], line_num, &mut number)?;
+
+            let index = match number[0] {
+              n if n > 0 => n - 1,
+              n if n < 0 => vertices.len() as i64 + n,
+              _ => -1,
+            };
+            if index < 0 || index >= vertices.len() as i64 {
+              return Err(parse_error(format!(
+                "invalid vertex number {}",
+                number[0]
+              )));
+            }
+
+            Ok(index as u32)
+          })
+          .collect::<Result<Vec<_>, _>>()?;
+
+        if polygon.len() < 3 {
+          return Err(parse_error("faces need at least 3 vertices".to_owned()));
+        }
+
+        for idx in 1..polygon.len() - 1 {
+          faces.push([polygon[0], polygon[idx], polygon[idx + 1]]);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  Ok((vertices, faces))
+}
+
+// STL repeats vertices for every face
+#[derive(Default)]
+struct VertexMerger {
+  vertices: Vec<Vector3<f32>>,
+  idxs: HashMap<[u32; 3], u32>,
+}
+
+impl VertexMerger {
+  fn add(&mut self, vert: Vector3<f32>) -> u32 {
+    let vertices = &mut self.vertices;
+    let key = [vert[0].to_bits(), vert[1].to_bits(), vert[2].to_bits()];
+
+    *self.idxs.entry(key).or_insert_with(|| {
+      vertices.push(vert);
+      (vertices.len() - 1) as u32
+    })
+  }
+}
+
+/// Reads an ascii STL file, identical vertices are merged.
+pub fn parse_ascii_stl<R: BufRead>(reader: R) -> Result<Surface, MeshError> {
+  let mut merger = VertexMerger::default();
+  let mut faces = Vec::new();
+  let mut face = Vec::new();
+
+  for (line_idx, line) in reader.lines().enumerate() {
+    let line = line?;
+    let line_num = line_idx + 1;
+
+    let tokens: Vec<_> = line.split_whitespace().collect();
+
+    match tokens.first() {
+      Some(&"vertex") => {
+        let vert = parse_vertex(&tokens[1..], line_num)?;
+        face.push(merger.add(vert));
+      }
+      Some(&"endloop") => {
+        if face.len() != 3 {
+          return Err(MeshError::Parse {
+            line: line_num,
+            message: format!("expected 3 vertices, found {}", face.len()),
+          });
+        }
+        faces.push([face[0], face[1], face[2]]);
+        face.clear();
+      }
+      _ => {}
+    }
+  }
+
+  Ok((merger.vertices, faces))
+}
+
+/// Reads a binary STL file, identical vertices are merged.
+pub fn parse_binary_stl(bytes: &[u8]) -> Result<Surface, MeshError> {
+  let read_u32 = |offset: usize| {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+  };
+
+  // parse errors are reported on line 1
+  let parse_error = |message: String| MeshError::Parse { line: 1, message };
+
+  if bytes.len() < 84 {
+    return Err(parse_error("file is too short for binary stl".to_owned()));
+  }
+  let num_faces = read_u32(80) as usize;
+  if bytes.len() != 84 + 50 * num_faces {
+    return Err(parse_error(format!(
+      "expected {} bytes for {} triangles, found {}",
+      84 + 50 * num_faces,
+      num_faces,
+      bytes.len()
+    )));
+  }
+
+  let mut merger = VertexMerger::default();
+  let mut faces = Vec::with_capacity(num_faces);
+
+  for face_idx in 0..num_faces {
+    // normal, 3 vertices, attribute byte count
+    let start = 84 + 50 * face_idx + 12;
+    let mut face = [0; 3];
+
+    for (vertex, face_vertex) in face.iter_mut().enumerate() {
+      let mut vert = Vector3::zeros();
+      for (coord, value) in vert.iter_mut().enumerate() {
+        *value = f32::from_bits(read_u32(start + 12 * vertex + 4 * coord));
+      }
+
+      if !vert.iter().all(|v| v.is_finite()) {
+        return Err(parse_error(format!(
+          "triangle {} has non finite positions",
+          face_idx
+        )));
+      }
+
+      *face_vertex = merger.add(vert);
+    }
+
+    faces.push(face);
+  }
+
+  Ok((merger.vertices, faces))
+}
+
+/// Binary files are recognized by their size, as some binary files also
+/// start with `solid`.
+pub fn parse_stl(bytes: &[u8]) -> Result<Surface, MeshError> {
+  let is_binary = bytes.len() >= 84 && {
+    let mut num_faces = [0; 4];
+    num_faces.copy_from_slice(&bytes[80..84]);
+    bytes.len() == 84 + 50 * u32::from_le_bytes(num_faces) as usize
+  };
+
+  if is_binary {
+    parse_binary_stl(bytes)
+  } else {
+    parse_ascii_stl(bytes)
+  }
+}
+
+/// Loads `.stl` files as STL and anything else as OBJ.
+pub fn load_surface(path: &Path) -> Result<Surface, MeshError> {
+  match path.extension().and_then(|ext| ext.to_str()) {
+    Some("stl") => parse_stl(&fs::read(path)?),
+    _ => parse_obj(BufReader::new(fs::File::open(path)?)),
+  }
+}
+
+#[test]
+fn parses_obj() {
+  let contents = "# comment\no quad\nv 0 0 0\nv 1 0 0 1.0\nv 1 1 0\n\
+                  v 0 1 0 0.5 0.5 0.5\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\n\
+                  f -4/1 -2 -1\n";
+  let (vertices, faces) = parse_obj(contents.as_bytes()).unwrap();
+
+  assert_eq!(vertices.len(), 4);
+  assert_eq!(faces, vec![[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+
+  match parse_obj("v 0 0 0\nf 1 2 3\n".as_bytes()) {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+}
+
+#[test]
+fn parses_stl() {
+  let ascii = "solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\n\
+               vertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\n\
+               facet normal 0 0 1\nouter loop\nvertex 1 0 0\nvertex 1 1 0\n\
+               vertex 0 1 0\nendloop\nendfacet\nendsolid test\n";
+  let from_ascii = parse_stl(ascii.as_bytes()).unwrap();
+  assert_eq!(from_ascii.0.len(), 4);
+  assert_eq!(from_ascii.1, vec![[0, 1, 2], [1, 3, 2]]);
+
+  // binary files can also start with solid
+  let mut binary = b"solid".to_vec();
+  binary.resize(80, 0);
+  binary.extend(&2u32.to_le_bytes());
+  for face in &from_ascii.1 {
+    binary.extend(&[0; 12]);
+    for vertex_idx in face {
+      for value in from_ascii.0[*vertex_idx as usize].iter() {
+        binary.extend(&value.to_le_bytes());
+      }
+    }
+    binary.extend(&[0; 2]);
+  }
+  let from_binary = parse_stl(&binary).unwrap();
+  assert_eq!(from_binary, from_ascii);
+}
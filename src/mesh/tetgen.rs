@@ -0,0 +1,439 @@
+use crate::mesh::{
+  check_indices, parse_values, transform_vertex, LoadedMesh, MeshError,
+};
+use nalgebra::{Transform3, Vector3};
+use std::fs::File;
+use std::io::{prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+
+/// A tetrahedral mesh as output by TetGen, along with the optional region
+/// and boundary information.
+#[derive(Clone, Debug)]
+pub struct TetGenMesh {
+  pub vertices: Vec<Vector3<f32>>,
+  pub tetras: Vec<[u32; 4]>,
+  /// first attribute of each tet, which is the region number with `-A`
+  pub regions: Option<Vec<i32>>,
+  /// boundary faces from the `.face` file, empty if there isn't one
+  pub faces: Vec<[u32; 3]>,
+  pub face_markers: Option<Vec<i32>>,
+}
+
+/// tets along with their regions
+pub type TetGenTetras = (Vec<[u32; 4]>, Option<Vec<i32>>);
+/// faces along with their boundary markers
+pub type TetGenFaces = (Vec<[u32; 3]>, Option<Vec<i32>>);
+
+impl TetGenMesh {
+  pub fn into_loaded(self) -> LoadedMesh {
+    (self.vertices, self.tetras)
+  }
+}
+
+struct Entry {
+  line: usize,
+  number: i64,
+  values: Vec<String>,
+}
+
+impl Entry {
+  fn check_len(&self, len: usize) -> Result<(), MeshError> {
+    if self.values.len() != len {
+      return Err(MeshError::Parse {
+        line: self.line,
+        message: format!(
+          "expected {} values after the number, found {}",
+          len,
+          self.values.len()
+        ),
+      });
+    }
+
+    Ok(())
+  }
+
+  // converts a vertex number to an index
+  fn index(&self, value: usize, first_number: i64) -> Result<u32, MeshError> {
+    let mut number = [0i64];
+    parse_values(&self.values[value..value + 1], self.line, &mut number)?;
+
+    let index = number[0] - first_number;
+    if index < 0 || index > u32::MAX as i64 {
+      return Err(MeshError::Parse {
+        line: self.line,
+        message: format!("invalid vertex number {}", number[0]),
+      });
+    }
+
+    Ok(index as u32)
+  }
+
+  fn integer_attribute(&self, value: usize) -> Result<i32, MeshError> {
+    let mut attribute = [0.0f64];
+    parse_values(&self.values[value..value + 1], self.line, &mut attribute)?;
+
+    if attribute[0].fract() != 0.0 || attribute[0].abs() > i32::MAX as f64 {
+      return Err(MeshError::Parse {
+        line: self.line,
+        message: format!("expected an integer, found {}", attribute[0]),
+      });
+    }
+
+    Ok(attribute[0] as i32)
+  }
+}
+
+// Every TetGen file is a header line followed by one numbered line per entry.
+// Everything after a `#` is a comment.
+fn read_section<R: BufRead>(
+  reader: R,
+  header: &mut [usize],
+) -> Result<(usize, Vec<Entry>), MeshError> {
+  let mut lines = reader.lines().enumerate().filter_map(|(line_idx, line)| {
+    line
+      .map(|line| {
+        let tokens: Vec<_> = line
+          .split('#')
+          .next()
+          .unwrap_or("")
+          .split_whitespace()
+          .map(str::to_owned)
+          .collect();
+
+        if tokens.is_empty() {
+          None
+        } else {
+          Some((line_idx + 1, tokens))
+        }
+      })
+      .map_err(MeshError::from)
+      .transpose()
+  });
+
+  let (header_line, header_tokens) =
+    lines.next().transpose()?.ok_or_else(|| MeshError::Parse {
+      line: 1,
+      message: "missing header".to_owned(),
+    })?;
+  parse_values(&header_tokens, header_line, header)?;
+
+  let entries = lines
+    .map(|line| {
+      let (line, tokens) = line?;
+      let mut number = [0];
+      parse_values(&tokens[..1], line, &mut number)?;
+
+      Ok(Entry {
+        line,
+        number: number[0],
+        values: tokens[1..].to_vec(),
+      })
+    })
+    .collect::<Result<Vec<_>, MeshError>>()?;
+
+  if entries.len() != header[0] {
+    return Err(MeshError::Parse {
+      line: header_line,
+      message: format!(
+        "header gives {} entries, found {}",
+        header[0],
+        entries.len()
+      ),
+    });
+  }
+
+  Ok((header_line, entries))
+}
+
+fn check_flag(
+  flag: usize,
+  header_line: usize,
+  name: &str,
+) -> Result<(), MeshError> {
+  if flag > 1 {
+    return Err(MeshError::Parse {
+      line: header_line,
+      message: format!("{} must be 0 or 1, found {}", name, flag),
+    });
+  }
+
+  Ok(())
+}
+
+/// Reads a `.node` file. Also returns the number of the first vertex (0 or
+/// 1), which the other files index from.
+pub fn parse_node<R: BufRead>(
+  reader: R,
+  transform: Option<&Transform3<f32>>,
+) -> Result<(Vec<Vector3<f32>>, i64), MeshError> {
+  let mut header = [0; 4];
+  let (header_line, entries) = read_section(reader, &mut header)?;
+  let [_, dim, num_attributes, num_markers] = header;
+
+  if dim != 3 {
+    return Err(MeshError::Parse {
+      line: header_line,
+      message: format!("only 3d meshes are supported, found dimension {}", dim),
+    });
+  }
+  check_flag(num_markers, header_line, "boundary marker count")?;
+
+  let first_number = entries.first().map(|entry| entry.number).unwrap_or(0);
+
+  entries
+    .iter()
+    .enumerate()
+    .map(|(idx, entry)| {
+      if (idx == 0 && first_number != 0 && first_number != 1)
+        || entry.number != first_number + idx as i64
+      {
+        return Err(MeshError::Parse {
+          line: entry.line,
+          message: "vertices must be numbered consecutively from 0 or 1"
+            .to_owned(),
+        });
+      }
+      entry.check_len(3 + num_attributes + num_markers)?;
+
+      let mut vert = [0.0f32; 3];
+      parse_values(&entry.values[..3], entry.line, &mut vert)?;
+
+      if !vert.iter().all(|v| v.is_finite()) {
+        return Err(MeshError::Parse {
+          line: entry.line,
+          message: "vertex positions must be finite".to_owned(),
+        });
+      }
+
+      Ok(transform_vertex(Vector3::from(vert), transform))
+    })
+    .collect::<Result<_, _>>()
+    .map(|vertices| (vertices, first_number))
+}
+
+/// Reads a `.ele` file, second order (10 node) tets are reduced to their
+/// corners. Also returns the regions if the tets have attributes.
+pub fn parse_ele<R: BufRead>(
+  reader: R,
+  first_number: i64,
+) -> Result<TetGenTetras, MeshError> {
+  let mut header = [0; 3];
+  let (header_line, entries) = read_section(reader, &mut header)?;
+  let [_, nodes_per_tetra, num_attributes] = header;
+
+  if nodes_per_tetra != 4 && nodes_per_tetra != 10 {
+    return Err(MeshError::Parse {
+      line: header_line,
+      message: format!(
+        "tets must have 4 or 10 nodes, found {}",
+        nodes_per_tetra
+      ),
+    });
+  }
+
+  let mut tetras = Vec::with_capacity(entries.len());
+  let mut regions = Vec::new();
+
+  for entry in &entries {
+    entry.check_len(nodes_per_tetra + num_attributes)?;
+
+    let mut tetra = [0; 4];
+    for (value, vertex_idx) in tetra.iter_mut().enumerate() {
+      *vertex_idx = entry.index(value, first_number)?;
+    }
+    tetras.push(tetra);
+
+    if num_attributes > 0 {
+      regions.push(entry.integer_attribute(nodes_per_tetra)?);
+    }
+  }
+
+  Ok((
+    tetras,
+    if num_attributes > 0 {
+      Some(regions)
+    } else {
+      None
+    },
+  ))
+}
+
+/// Reads a `.face` file along with the boundary markers if there are any.
+pub fn parse_face<R: BufRead>(
+  reader: R,
+  first_number: i64,
+  num_vertices: usize,
+) -> Result<TetGenFaces, MeshError> {
+  let mut header = [0; 2];
+  let (header_line, entries) = read_section(reader, &mut header)?;
+  let [_, num_markers] = header;
+  check_flag(num_markers, header_line, "boundary marker count")?;
+
+  let mut faces = Vec::with_capacity(entries.len());
+  let mut markers = Vec::new();
+
+  for entry in &entries {
+    entry.check_len(3 + num_markers)?;
+
+    let mut face = [0; 3];
+    for (value, vertex_idx) in face.iter_mut().enumerate() {
+      *vertex_idx = entry.index(value, first_number)?;
+
+      if *vertex_idx as usize >= num_vertices {
+        return Err(MeshError::Parse {
+          line: entry.line,
+          message: format!(
+            "face uses vertex {} but there are only {} vertices",
+            vertex_idx, num_vertices
+          ),
+        });
+      }
+    }
+    faces.push(face);
+
+    if num_markers > 0 {
+      markers.push(entry.integer_attribute(3)?);
+    }
+  }
+
+  Ok((faces, if num_markers > 0 { Some(markers) } else { None }))
+}
+
+// errors name the file since there are several
+fn read_file<T>(
+  path: PathBuf,
+  parse: impl FnOnce(BufReader<File>) -> Result<T, MeshError>,
+) -> Result<T, MeshError> {
+  File::open(&path)
+    .map_err(MeshError::from)
+    .and_then(|file| parse(BufReader::new(file)))
+    .map_err(|error| MeshError::InFile {
+      path,
+      error: Box::new(error),
+    })
+}
+
+/// Loads `<name>.node` and `<name>.ele` (the path can have either extension)
+/// along with `<name>.face` if it exists.
+pub fn load_tetgen(
+  path: &Path,
+  transform: Option<&Transform3<f32>>,
+) -> Result<TetGenMesh, MeshError> {
+  let (vertices, first_number) =
+    read_file(path.with_extension("node"), |reader| {
+      parse_node(reader, transform)
+    })?;
+
+  let (tetras, regions) = read_file(path.with_extension("ele"), |reader| {
+    parse_ele(reader, first_number)
+  })?;
+
+  let face_path = path.with_extension("face");
+  let (faces, face_markers) = if face_path.exists() {
+    read_file(face_path, |reader| {
+      parse_face(reader, first_number, vertices.len())
+    })?
+  } else {
+    (Vec::new(), None)
+  };
+
+  let mesh = (vertices, tetras);
+  check_indices(&mesh)?;
+  let (vertices, tetras) = mesh;
+
+  Ok(TetGenMesh {
+    vertices,
+    tetras,
+    regions,
+    faces,
+    face_markers,
+  })
+}
+
+#[test]
+fn parses_either_numbering() {
+  let node_0 = "4 3 0 0\n0 0 0 0\n1 1 0 0\n2 0 1 0\n3 0 0 1\n";
+  let node_1 = "# comment\n4  3  1  1\n1 0 0 0 7 1\n2 1 0 0 7 1 # comment\n\
+                3 0 1 0 7 1\n4 0 0 1 7 0\n";
+
+  let (vertices_0, first_0) = parse_node(node_0.as_bytes(), None).unwrap();
+  let (vertices_1, first_1) = parse_node(node_1.as_bytes(), None).unwrap();
+  assert_eq!((first_0, first_1), (0, 1));
+  assert_eq!(vertices_0, vertices_1);
+
+  let (tetras_0, regions_0) =
+    parse_ele("1 4 0\n0 0 1 2 3\n".as_bytes(), first_0).unwrap();
+  let (tetras_1, regions_1) =
+    parse_ele("1 10 1\n1 1 2 3 4 5 6 7 8 9 10 -2\n".as_bytes(), first_1)
+      .unwrap();
+  assert_eq!(tetras_0, vec![[0, 1, 2, 3]]);
+  assert_eq!(tetras_0, tetras_1);
+  assert_eq!(regions_0, None);
+  assert_eq!(regions_1, Some(vec![-2]));
+
+  let (faces, markers) =
+    parse_face("2 1\n1 1 2 3 5\n2 1 2 4 0\n".as_bytes(), first_1, 4).unwrap();
+  assert_eq!(faces, vec![[0, 1, 2], [0, 1, 3]]);
+  assert_eq!(markers, Some(vec![5, 0]));
+}
+
+#[test]
+fn tetgen_parse_errors() {
+  let node_error = |contents: &str| match parse_node(contents.as_bytes(), None)
+  {
+    Err(MeshError::Parse { line, .. }) => line,
+    other => panic!("unexpected {:?}", other),
+  };
+
+  // numbered from 2
+  assert_eq!(node_error("1 3 0 0\n2 0 0 0\n"), 2);
+  // skipped number
+  assert_eq!(node_error("2 3 0 0\n0 0 0 0\n2 0 0 0\n"), 3);
+  // missing attribute
+  assert_eq!(node_error("1 3 1 0\n0 0 0 0\n"), 2);
+  // wrong count
+  assert_eq!(node_error("2 3 0 0\n0 0 0 0\n"), 1);
+  assert_eq!(node_error("1 2 0 0\n0 0 0\n"), 1);
+
+  match parse_ele("1 4 1\n1 1 2 3 4 0.5\n".as_bytes(), 1) {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse_ele("1 4 0\n1 0 1 2 3\n".as_bytes(), 1) {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse_face("1 0\n1 1 2 5\n".as_bytes(), 1, 4) {
+    Err(MeshError::Parse { line: 2, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+}
+
+#[test]
+fn loads_by_extension() {
+  let mesh = crate::load_mesh(Path::new("meshes/cube.mesh")).unwrap();
+
+  for path in &["meshes/cube.node", "meshes/cube.ele"] {
+    assert_eq!(crate::load_mesh(Path::new(path)).unwrap(), mesh);
+  }
+
+  let tetgen_mesh = load_tetgen(Path::new("meshes/cube.ele"), None).unwrap();
+  assert_eq!(tetgen_mesh.regions, Some(vec![1; mesh.1.len()]));
+  assert_eq!(tetgen_mesh.faces.len(), 12);
+
+  let with_regions =
+    crate::mesh::load_mesh_with_regions(Path::new("meshes/cube.node"), None)
+      .unwrap();
+  assert_eq!(with_regions, (mesh.clone(), tetgen_mesh.regions));
+  let without_regions =
+    crate::mesh::load_mesh_with_regions(Path::new("meshes/cube.mesh"), None)
+      .unwrap();
+  assert_eq!(without_regions, (mesh, None));
+
+  match load_tetgen(Path::new("meshes/missing.node"), None) {
+    Err(MeshError::InFile { path, .. }) => {
+      assert_eq!(path, Path::new("meshes/missing.node"))
+    }
+    other => panic!("unexpected {:?}", other),
+  }
+}
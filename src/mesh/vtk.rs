@@ -0,0 +1,466 @@
+use crate::mesh::{
+  check_indices, parse_values, remove_unreferenced_vertices, transform_vertex,
+  LoadedMesh, MeshError,
+};
+use nalgebra::{Transform3, Vector3};
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader};
+use std::path::Path;
+
+// vtk cell type id, shared with the XML format of `vtk_export`
+pub(crate) const VTK_TETRA: u32 = 10;
+
+/// Writes the vertex indices of each tet on its own line after `prefix`. Used
+/// by both the XML and the legacy format, where each cell starts with its
+/// number of points.
+pub(crate) fn write_tetra_cells<W: Write>(
+  writer: &mut W,
+  tetras: &[[u32; 4]],
+  prefix: &str,
+) -> io::Result<()> {
+  for tetra in tetras {
+    writeln!(
+      writer,
+      "{}{} {} {} {}",
+      prefix, tetra[0], tetra[1], tetra[2], tetra[3]
+    )?;
+  }
+
+  Ok(())
+}
+
+pub(crate) fn write_tetra_types<W: Write>(
+  writer: &mut W,
+  count: usize,
+) -> io::Result<()> {
+  for _ in 0..count {
+    writeln!(writer, "{}", VTK_TETRA)?;
+  }
+
+  Ok(())
+}
+
+/// A legacy VTK unstructured grid along with the vector cell data of its tets.
+#[derive(Clone, Debug)]
+pub struct VtkMesh {
+  pub vertices: Vec<Vector3<f32>>,
+  pub tetras: Vec<[u32; 4]>,
+  /// name and value for each tet of every `VECTORS` array in the cell data,
+  /// transformed along with the mesh (without the translation)
+  pub cell_vectors: Vec<(String, Vec<Vector3<f32>>)>,
+}
+
+impl VtkMesh {
+  pub fn into_loaded(self) -> LoadedMesh {
+    (self.vertices, self.tetras)
+  }
+
+  pub fn cell_vectors(&self, name: &str) -> Option<&[Vector3<f32>]> {
+    self
+      .cell_vectors
+      .iter()
+      .find(|(vectors_name, _)| vectors_name == name)
+      .map(|(_, vectors)| &vectors[..])
+  }
+}
+
+/// Writes a legacy ascii VTK unstructured grid of tets.
+pub fn write_vtk<W: Write>(
+  writer: &mut W,
+  (vertices, tetras): &LoadedMesh,
+) -> io::Result<()> {
+  writeln!(writer, "# vtk DataFile Version 3.0")?;
+  writeln!(writer, "tetrahedral mesh")?;
+  writeln!(writer, "ASCII")?;
+  writeln!(writer, "DATASET UNSTRUCTURED_GRID")?;
+
+  writeln!(writer, "POINTS {} float", vertices.len())?;
+  for vert in vertices {
+    writeln!(writer, "{} {} {}", vert[0], vert[1], vert[2])?;
+  }
+
+  writeln!(writer, "CELLS {} {}", tetras.len(), 5 * tetras.len())?;
+  write_tetra_cells(writer, tetras, "4 ")?;
+
+  writeln!(writer, "CELL_TYPES {}", tetras.len())?;
+  write_tetra_types(writer, tetras.len())
+}
+
+// whitespace separated tokens along with their line numbers
+struct Tokens<R> {
+  lines: std::iter::Enumerate<io::Lines<R>>,
+  tokens: std::vec::IntoIter<String>,
+  line: usize,
+}
+
+impl<R: BufRead> Tokens<R> {
+  fn next(&mut self) -> Result<Option<String>, MeshError> {
+    loop {
+      if let Some(token) = self.tokens.next() {
+        return Ok(Some(token));
+      }
+
+      match self.lines.next() {
+        Some((line_idx, line)) => {
+          self.line = line_idx + 1;
+          self.tokens = line?
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+            .into_iter();
+        }
+        None => return Ok(None),
+      }
+    }
+  }
+
+  fn error(&self, message: String) -> MeshError {
+    MeshError::Parse {
+      line: self.line,
+      message,
+    }
+  }
+
+  fn values<T: std::str::FromStr>(
+    &mut self,
+    what: &str,
+    out: &mut [T],
+  ) -> Result<(), MeshError>
+  where
+    T::Err: std::fmt::Display,
+  {
+    for out in out {
+      let token = self
+        .next()?
+        .ok_or_else(|| self.error(format!("file ended, expected {}", what)))?;
+      parse_values(&[token], self.line, std::slice::from_mut(out))?;
+    }
+
+    Ok(())
+  }
+
+  fn skip(&mut self, what: &str, count: usize) -> Result<(), MeshError> {
+    for _ in 0..count {
+      self
+        .next()?
+        .ok_or_else(|| self.error(format!("file ended, expected {}", what)))?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Reads a legacy ascii VTK unstructured grid. Cells other than tets are
+/// ignored along with any point or cell data.
+pub fn parse_vtk<R: BufRead>(
+  reader: R,
+  transform: Option<&Transform3<f32>>,
+) -> Result<LoadedMesh, MeshError> {
+  parse_vtk_mesh(reader, transform).map(VtkMesh::into_loaded)
+}
+
+pub fn load_vtk(
+  path: &Path,
+  transform: Option<&Transform3<f32>>,
+) -> Result<VtkMesh, MeshError> {
+  parse_vtk_mesh(BufReader::new(File::open(path)?), transform)
+}
+
+/// Like `parse_vtk`, but keeps the `VECTORS` of the cell data. Other data is
+/// skipped, and data of a kind this doesn't know ends the file.
+pub fn parse_vtk_mesh<R: BufRead>(
+  reader: R,
+  transform: Option<&Transform3<f32>>,
+) -> Result<VtkMesh, MeshError> {
+  let mut lines = reader.lines().enumerate();
+  let mut header = Vec::new();
+  for _ in 0..3 {
+    match lines.next() {
+      Some((_, line)) => header.push(line?),
+      None => {
+        return Err(MeshError::Parse {
+          line: header.len() + 1,
+          message: "file ended in the header".to_owned(),
+        })
+      }
+    }
+  }
+
+  if !header[0].starts_with("# vtk DataFile") {
+    return Err(MeshError::Parse {
+      line: 1,
+      message: "not a legacy vtk file".to_owned(),
+    });
+  }
+  if header[2].trim() != "ASCII" {
+    return Err(MeshError::Parse {
+      line: 3,
+      message: "only ascii files are supported".to_owned(),
+    });
+  }
+
+  let mut tokens = Tokens {
+    lines,
+    tokens: Vec::new().into_iter(),
+    line: 3,
+  };
+
+  let mut dataset = [String::new(), String::new()];
+  tokens.values("dataset", &mut dataset)?;
+  if dataset[0] != "DATASET" || dataset[1] != "UNSTRUCTURED_GRID" {
+    return Err(
+      tokens.error("only unstructured grids are supported".to_owned()),
+    );
+  }
+
+  let mut vertices = Vec::new();
+  let mut cells = Vec::new();
+  let mut cell_types = Vec::new();
+  let mut cell_vectors = Vec::new();
+  // whether the current data is cell data, and the number of values
+  let mut data = None;
+
+  while let Some(keyword) = tokens.next()? {
+    match (keyword.as_str(), data) {
+      ("POINTS", _) => {
+        let mut count = [0usize];
+        tokens.values("point count", &mut count)?;
+        let mut data_type = [String::new()];
+        tokens.values("point type", &mut data_type)?;
+
+        for _ in 0..count[0] {
+          let mut vert = [0.0f32; 3];
+          tokens.values("point", &mut vert)?;
+
+          if !vert.iter().all(|v| v.is_finite()) {
+            return Err(
+              tokens.error("vertex positions must be finite".to_owned()),
+            );
+          }
+
+          vertices.push(transform_vertex(Vector3::from(vert), transform));
+        }
+      }
+      ("CELLS", _) => {
+        let mut counts = [0usize; 2];
+        tokens.values("cell counts", &mut counts)?;
+
+        for _ in 0..counts[0] {
+          let mut len = [0usize];
+          tokens.values("cell", &mut len)?;
+          let mut cell = vec![0u32; len[0]];
+          tokens.values("cell", &mut cell)?;
+          cells.push((cell, tokens.line));
+        }
+      }
+      ("CELL_TYPES", _) => {
+        let mut count = [0usize];
+        tokens.values("cell type count", &mut count)?;
+
+        cell_types = vec![0u32; count[0]];
+        tokens.values("cell type", &mut cell_types)?;
+      }
+      ("POINT_DATA", _) | ("CELL_DATA", _) => {
+        let mut count = [0usize];
+        tokens.values("data count", &mut count)?;
+
+        let expected = match keyword.as_str() {
+          "CELL_DATA" => cells.len(),
+          _ => vertices.len(),
+        };
+        if count[0] != expected {
+          return Err(tokens.error(format!(
+            "data for {} values, expected {}",
+            count[0], expected
+          )));
+        }
+
+        data = Some((keyword == "CELL_DATA", count[0]));
+      }
+      ("VECTORS", Some((is_cell_data, count))) => {
+        let mut header = [String::new(), String::new()];
+        tokens.values("vectors name and type", &mut header)?;
+
+        let mut vectors = Vec::with_capacity(count);
+        for _ in 0..count {
+          let mut vector = [0.0f32; 3];
+          tokens.values("vector", &mut vector)?;
+
+          if !vector.iter().all(|v| v.is_finite()) {
+            return Err(tokens.error("vectors must be finite".to_owned()));
+          }
+
+          let vector = Vector3::from(vector);
+          vectors.push(match transform {
+            Some(transform) => transform.transform_vector(&vector),
+            None => vector,
+          });
+        }
+
+        if is_cell_data {
+          let [name, _] = header;
+          cell_vectors.push((name, vectors));
+        }
+      }
+      ("SCALARS", Some((_, count))) => {
+        let mut header = [String::new(), String::new()];
+        tokens.values("scalars name and type", &mut header)?;
+
+        // the number of components is optional
+        let mut components = 1;
+        let mut table = [String::new()];
+        tokens.values("lookup table", &mut table)?;
+        if table[0] != "LOOKUP_TABLE" {
+          parse_values(
+            &table,
+            tokens.line,
+            std::slice::from_mut(&mut components),
+          )?;
+          tokens.values("lookup table", &mut table)?;
+        }
+        tokens.values("lookup table name", &mut table)?;
+
+        tokens.skip("scalar", count * components)?;
+      }
+      ("NORMALS", Some((_, count))) | ("TENSORS", Some((_, count))) => {
+        tokens.skip("name and type", 2)?;
+
+        let components = if keyword == "NORMALS" { 3 } else { 9 };
+        tokens.skip("value", count * components)?;
+      }
+      ("FIELD", Some(_)) => {
+        let mut name = [String::new()];
+        tokens.values("field name", &mut name)?;
+        let mut num_arrays = [0usize];
+        tokens.values("field array count", &mut num_arrays)?;
+
+        for _ in 0..num_arrays[0] {
+          tokens.skip("array name", 1)?;
+          let mut shape = [0usize; 2];
+          tokens.values("array components and tuples", &mut shape)?;
+          tokens.skip("array type", 1)?;
+
+          tokens.skip("array value", shape[0] * shape[1])?;
+        }
+      }
+      // the rest of the file is data
+      (_, Some(_)) => break,
+      (keyword, None) => {
+        return Err(tokens.error(format!("unsupported section {}", keyword)))
+      }
+    }
+  }
+
+  if cells.len() != cell_types.len() {
+    return Err(tokens.error(format!(
+      "{} cells but {} cell types",
+      cells.len(),
+      cell_types.len()
+    )));
+  }
+
+  let mut tetras = Vec::new();
+  for ((cell, line), cell_type) in cells.iter().zip(&cell_types) {
+    if *cell_type == VTK_TETRA {
+      if cell.len() != 4 {
+        return Err(MeshError::Parse {
+          line: *line,
+          message: format!("tet has {} points", cell.len()),
+        });
+      }
+
+      tetras.push([cell[0], cell[1], cell[2], cell[3]]);
+    }
+  }
+
+  let mesh = (vertices, tetras);
+  check_indices(&mesh)?;
+  let (vertices, tetras) = remove_unreferenced_vertices(mesh);
+
+  // only the values of the tets are kept
+  let cell_vectors = cell_vectors
+    .into_iter()
+    .map(|(name, vectors)| {
+      let tetra_vectors = vectors
+        .into_iter()
+        .zip(&cell_types)
+        .filter(|(_, cell_type)| **cell_type == VTK_TETRA)
+        .map(|(vector, _)| vector)
+        .collect();
+
+      (name, tetra_vectors)
+    })
+    .collect();
+
+  Ok(VtkMesh {
+    vertices,
+    tetras,
+    cell_vectors,
+  })
+}
+
+#[test]
+fn vtk_skips_other_cells() {
+  let contents = "# vtk DataFile Version 3.0\ntitle\nASCII\n\
+                  DATASET UNSTRUCTURED_GRID\nPOINTS 6 double\n\
+                  9 9 9 0 0 0 1 0 0\n0 1 0 0 0 1 1 1 1\n\
+                  CELLS 3 12\n3 1 2 3\n4 1 2 3 4\n4 2 3 4 5\n\
+                  CELL_TYPES 3\n5 10 10\nCELL_DATA 3\n";
+
+  let (vertices, tetras) = parse_vtk(contents.as_bytes(), None).unwrap();
+  assert_eq!(vertices.len(), 5);
+  assert_eq!(vertices[0], Vector3::zeros());
+  assert_eq!(tetras, vec![[0, 1, 2, 3], [1, 2, 3, 4]]);
+
+  match parse_vtk(contents.replace("5 10 10", "5 10 7").as_bytes(), None) {
+    Ok((_, tetras)) => assert_eq!(tetras.len(), 1),
+    other => panic!("unexpected {:?}", other),
+  }
+  match parse_vtk(
+    contents.replace("\n4 1 2 3 4\n", "\n3 1 2 3\n").as_bytes(),
+    None,
+  ) {
+    Err(MeshError::Parse { line: 10, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+}
+
+#[test]
+fn vtk_reads_cell_vectors() {
+  let contents = "# vtk DataFile Version 3.0\ntitle\nASCII\n\
+                  DATASET UNSTRUCTURED_GRID\nPOINTS 5 float\n\
+                  0 0 0 1 0 0 0 1 0\n0 0 1 1 1 1\n\
+                  CELLS 3 12\n4 0 1 2 3\n3 1 2 3\n4 1 2 3 4\n\
+                  CELL_TYPES 3\n10 5 10\n\
+                  POINT_DATA 5\nSCALARS temperature float\n\
+                  LOOKUP_TABLE default\n1 2 3 4 5\n\
+                  VECTORS velocity float\n0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n\
+                  CELL_DATA 3\nSCALARS region int 2\nLOOKUP_TABLE default\n\
+                  1 1 2 2 3 3\nFIELD data 1\nweight 1 3 float\n1 2 3\n\
+                  VECTORS fiber float\n1 0 0\n9 9 9\n0 2 0\n\
+                  NORMALS normal float\n0 0 1 0 0 1 0 0 1\n";
+
+  let transform = Transform3::from_matrix_unchecked(
+    nalgebra::Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 3.0, 1.0)),
+  );
+  let mesh = parse_vtk_mesh(contents.as_bytes(), Some(&transform)).unwrap();
+  assert_eq!(mesh.tetras, vec![[0, 1, 2, 3], [1, 2, 3, 4]]);
+  assert_eq!(mesh.cell_vectors.len(), 1);
+  // only the tets, scaled along with the mesh
+  assert_eq!(
+    mesh.cell_vectors("fiber").unwrap(),
+    &[Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 6.0, 0.0)][..]
+  );
+  assert!(mesh.cell_vectors("velocity").is_none());
+
+  match parse_vtk_mesh(
+    contents.replace("CELL_DATA 3", "CELL_DATA 2").as_bytes(),
+    None,
+  ) {
+    Err(MeshError::Parse { line: 20, .. }) => {}
+    other => panic!("unexpected {:?}", other),
+  }
+  // unknown data ends the file
+  let contents = contents.replace("NORMALS", "COLOR_SCALARS");
+  assert!(parse_vtk_mesh(contents.as_bytes(), None).is_ok());
+}
@@ -0,0 +1,440 @@
+use crate::ode::{
+  state_ops::{add_scaled, dot, fill, norm},
+  Integrator, Model, ModelState, StepStats,
+};
+use clap::Clap;
+use serde::Deserialize;
+
+#[cfg(test)]
+use crate::{
+  assert_float_eq,
+  ode::test_models::{CubicDecay, Decay},
+};
+
+#[derive(Clap, Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImplicitEulerSettings {
+  #[clap(long = "newton-tol", default_value = "1e-6")]
+  /// newton iteration stops when the residual norm is below this (relative
+  /// to the state norm)
+  pub newton_tol: f32,
+
+  #[clap(long = "max-newton-iters", default_value = "10")]
+  pub max_newton_iters: usize,
+
+  #[clap(long = "linear-tol", default_value = "1e-4")]
+  /// relative residual for the linear solve in each newton iteration
+  pub linear_tol: f32,
+
+  #[clap(long = "max-linear-iters", default_value = "100")]
+  pub max_linear_iters: usize,
+
+  #[clap(long = "max-halvings", default_value = "6")]
+  /// steps where newton fails are retried as two half steps, up to this
+  /// many times, after which the last iterate is kept and counted as failed
+  pub max_halvings: usize,
+}
+
+// the defaults are only given to clap, scene files without the keys get the
+// same values
+impl Default for ImplicitEulerSettings {
+  fn default() -> Self {
+    Self::parse_from(std::iter::once("implicit-euler"))
+  }
+}
+
+/// Backward Euler. Each step solves x_new = x + h f(x_new) with newton
+/// iterations, where the linear systems (I - h J) dx = r are solved with
+/// matrix free BiCGSTAB using `Model::jacobian_vector_product`. Steps where
+/// newton fails to converge are split in half and counted as rejected.
+pub struct ImplicitEuler<M: Model>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  settings: ImplicitEulerSettings,
+  next_state: M::State,
+  dxdt: M::State,
+  residual: M::State,
+  delta: M::State,
+  trial_state: M::State,
+  trial_dxdt: M::State,
+  trial_residual: M::State,
+  linear: LinearWorkspace<M::State>,
+  stats: StepStats,
+}
+
+struct LinearWorkspace<State> {
+  r: State,
+  r_hat: State,
+  p: State,
+  v: State,
+  s: State,
+  t: State,
+  jv: State,
+}
+
+struct LinearOperator<'m, M: Model>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  model: &'m M,
+  state: &'m M::State,
+  dxdt: &'m M::State,
+  time: M::S,
+  time_step: M::S,
+}
+
+impl<'m, M: Model> LinearOperator<'m, M>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  // out = (I - h J) v
+  fn apply(&self, v: &M::State, out: &mut M::State, jv: &mut M::State) {
+    self
+      .model
+      .jacobian_vector_product(self.state, self.dxdt, v, jv, &self.time);
+
+    for ((out, v), jv) in out.into_iter().zip(v).zip(&*jv) {
+      *out = *v - self.time_step * *jv;
+    }
+  }
+}
+
+impl<State: Clone> LinearWorkspace<State> {
+  fn zeros_as<S>(&mut self, state: &State)
+  where
+    State: ModelState<S>,
+    for<'a> &'a State: IntoIterator<Item = &'a S>,
+    for<'a> &'a mut State: IntoIterator<Item = &'a mut S>,
+  {
+    for buffer in &mut [
+      &mut self.r,
+      &mut self.r_hat,
+      &mut self.p,
+      &mut self.v,
+      &mut self.s,
+      &mut self.t,
+      &mut self.jv,
+    ] {
+      buffer.zeros_as(state);
+    }
+  }
+
+  // BiCGSTAB on op(x) = b, starting from x = 0
+  fn solve<M: Model<State = State>>(
+    &mut self,
+    op: &LinearOperator<M>,
+    b: &State,
+    x: &mut State,
+    tol: M::S,
+    max_iters: usize,
+  ) where
+    for<'a> &'a State: IntoIterator<Item = &'a M::S>,
+    for<'a> &'a mut State: IntoIterator<Item = &'a mut M::S>,
+  {
+    let zero: M::S = 0.0.into();
+    let one: M::S = 1.0.into();
+
+    fill(x, zero);
+    fill(&mut self.p, zero);
+    fill(&mut self.v, zero);
+    self.r.clone_from(b);
+    self.r_hat.clone_from(b);
+
+    let threshold = tol * norm(b);
+    let mut rho = one;
+    let mut alpha = one;
+    let mut omega = one;
+
+    for _ in 0..max_iters {
+      if norm(&self.r) <= threshold {
+        break;
+      }
+
+      let rho_new = dot(&self.r_hat, &self.r);
+      if rho_new == zero {
+        break;
+      }
+
+      let beta = (rho_new / rho) * (alpha / omega);
+      for ((p, r), v) in (&mut self.p).into_iter().zip(&self.r).zip(&self.v) {
+        *p = *r + beta * (*p - omega * *v);
+      }
+
+      op.apply(&self.p, &mut self.v, &mut self.jv);
+
+      let r_hat_v = dot(&self.r_hat, &self.v);
+      if r_hat_v == zero {
+        break;
+      }
+      alpha = rho_new / r_hat_v;
+
+      self.s.clone_from(&self.r);
+      add_scaled(&mut self.s, -alpha, &self.v);
+      add_scaled(x, alpha, &self.p);
+
+      if norm(&self.s) <= threshold {
+        break;
+      }
+
+      op.apply(&self.s, &mut self.t, &mut self.jv);
+
+      let t_t = dot(&self.t, &self.t);
+      if t_t == zero {
+        break;
+      }
+      omega = dot(&self.t, &self.s) / t_t;
+
+      add_scaled(x, omega, &self.s);
+      self.r.clone_from(&self.s);
+      add_scaled(&mut self.r, -omega, &self.t);
+
+      rho = rho_new;
+    }
+  }
+}
+
+// residual = x + h f(x_new) - x_new, returns the norm of the residual
+fn compute_residual<M: Model>(
+  model: &M,
+  state: &M::State,
+  next_state: &M::State,
+  dxdt: &mut M::State,
+  residual: &mut M::State,
+  next_time: &M::S,
+  time_step: &M::S,
+) -> M::S
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  model.derivative(next_state, dxdt, next_time);
+
+  for (((residual, state), next_state), dxdt) in
+    residual.into_iter().zip(state).zip(next_state).zip(&*dxdt)
+  {
+    *residual = *state + *time_step * *dxdt - *next_state;
+  }
+
+  norm(residual)
+}
+
+const MAX_LINE_SEARCH_ITERS: usize = 8;
+
+impl<M: Model> ImplicitEuler<M>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  // leaves the solution (or the best iterate on failure) in next_state
+  fn newton_solve(
+    &mut self,
+    model: &M,
+    state: &M::State,
+    time: &M::S,
+    time_step: &M::S,
+  ) -> bool {
+    let next_time = *time + *time_step;
+    let newton_tol =
+      M::S::from(self.settings.newton_tol) * (M::S::from(1.0) + norm(state));
+
+    self.next_state.clone_from(state);
+    let mut residual_norm = compute_residual(
+      model,
+      state,
+      &self.next_state,
+      &mut self.dxdt,
+      &mut self.residual,
+      &next_time,
+      time_step,
+    );
+
+    for _ in 0..self.settings.max_newton_iters {
+      if residual_norm <= newton_tol {
+        return true;
+      }
+
+      let op = LinearOperator {
+        model,
+        state: &self.next_state,
+        dxdt: &self.dxdt,
+        time: next_time,
+        time_step: *time_step,
+      };
+
+      self.linear.solve(
+        &op,
+        &self.residual,
+        &mut self.delta,
+        self.settings.linear_tol.into(),
+        self.settings.max_linear_iters,
+      );
+
+      // backtrack until the residual decreases, the penalty forces are not
+      // smooth so full newton steps can overshoot badly
+      let mut step_size: M::S = 1.0.into();
+      let mut improved = false;
+      for _ in 0..MAX_LINE_SEARCH_ITERS {
+        self.trial_state.clone_from(&self.next_state);
+        add_scaled(&mut self.trial_state, step_size, &self.delta);
+
+        let trial_norm = compute_residual(
+          model,
+          state,
+          &self.trial_state,
+          &mut self.trial_dxdt,
+          &mut self.trial_residual,
+          &next_time,
+          time_step,
+        );
+
+        if trial_norm < residual_norm {
+          std::mem::swap(&mut self.next_state, &mut self.trial_state);
+          std::mem::swap(&mut self.dxdt, &mut self.trial_dxdt);
+          std::mem::swap(&mut self.residual, &mut self.trial_residual);
+          residual_norm = trial_norm;
+          improved = true;
+          break;
+        }
+
+        step_size *= 0.5.into();
+      }
+
+      if !improved {
+        return false;
+      }
+    }
+
+    residual_norm <= newton_tol
+  }
+
+  fn substep(
+    &mut self,
+    model: &M,
+    state: &mut M::State,
+    time: M::S,
+    time_step: M::S,
+    halvings: usize,
+  ) {
+    if self.newton_solve(model, state, &time, &time_step) {
+      self.stats.accepted += 1;
+      state.clone_from(&self.next_state);
+    } else if halvings >= self.settings.max_halvings {
+      // keep the best iterate rather than stopping the simulation
+      self.stats.failed += 1;
+      state.clone_from(&self.next_state);
+    } else {
+      self.stats.rejected += 1;
+
+      let half_time_step = time_step * 0.5.into();
+      self.substep(model, state, time, half_time_step, halvings + 1);
+      self.substep(
+        model,
+        state,
+        time + half_time_step,
+        half_time_step,
+        halvings + 1,
+      );
+    }
+  }
+}
+
+impl<M: Model> Integrator<M> for ImplicitEuler<M>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  type Settings = ImplicitEulerSettings;
+
+  fn new(settings: Self::Settings) -> Self {
+    Self {
+      settings,
+      next_state: M::State::new(),
+      dxdt: M::State::new(),
+      residual: M::State::new(),
+      delta: M::State::new(),
+      trial_state: M::State::new(),
+      trial_dxdt: M::State::new(),
+      trial_residual: M::State::new(),
+      linear: LinearWorkspace {
+        r: M::State::new(),
+        r_hat: M::State::new(),
+        p: M::State::new(),
+        v: M::State::new(),
+        s: M::State::new(),
+        t: M::State::new(),
+        jv: M::State::new(),
+      },
+      stats: StepStats::default(),
+    }
+  }
+
+  fn step_internal(
+    &mut self,
+    model: &M,
+    state: &mut M::State,
+    time: &M::S,
+    time_step: &M::S,
+  ) {
+    self.dxdt.zeros_as(state);
+    self.residual.zeros_as(state);
+    self.delta.zeros_as(state);
+    self.trial_dxdt.zeros_as(state);
+    self.trial_residual.zeros_as(state);
+    self.linear.zeros_as(state);
+
+    self.substep(model, state, *time, *time_step, 0);
+  }
+
+  fn step_stats(&self) -> Option<StepStats> {
+    Some(self.stats)
+  }
+}
+
+#[test]
+fn stiff_decay_large_step() {
+  let rate = 100.0;
+  let time_step = 0.1;
+  let model = Decay { rate };
+  let mut integrator = ImplicitEuler::new(ImplicitEulerSettings {
+    newton_tol: 1e-10,
+    max_newton_iters: 10,
+    linear_tol: 1e-10,
+    max_linear_iters: 10,
+    max_halvings: 0,
+  });
+
+  let mut state = vec![1.0, -2.0];
+  let mut time = 0.0;
+  integrator.n_steps(&model, &mut state, &mut time, &time_step, 2);
+
+  // backward euler on a linear problem: x_n = x_0 / (1 + rate h)^n
+  let expected = (1.0f64 + rate * time_step).powi(-2);
+  assert_float_eq!(state[0], expected);
+  assert_float_eq!(state[1], -2.0 * expected);
+}
+
+#[test]
+fn unconverged_steps_are_failed() {
+  let model = CubicDecay { rate: 1e6 };
+  let mut integrator = ImplicitEuler::new(ImplicitEulerSettings {
+    max_newton_iters: 2,
+    max_halvings: 0,
+    ..ImplicitEulerSettings::default()
+  });
+
+  let mut state = vec![1.0];
+  let mut time = 0.0;
+  integrator.n_steps(&model, &mut state, &mut time, &1.0, 2);
+
+  let stats = integrator.step_stats().unwrap();
+  assert_eq!(stats.accepted, 0);
+  assert_eq!(stats.rejected, 0);
+  assert_eq!(stats.failed, 2);
+
+  // the best iterate is still kept
+  assert!(state[0] > 0.0 && state[0] < 1.0);
+}
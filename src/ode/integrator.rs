@@ -1,5 +1,15 @@
 use crate::ode::Model;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepStats {
+  pub accepted: usize,
+  pub rejected: usize,
+  /// steps which couldn't meet the tolerance: implicit Euler keeps its last
+  /// iterate after running out of retries and RK45 gives up on the rest of
+  /// the time step once its error isn't finite
+  pub failed: usize,
+}
+
 pub trait Integrator<M: Model>
 where
   for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
@@ -17,6 +27,16 @@ where
     time_step: &M::S,
   );
 
+  /// Called when the model changed between steps in a way the state doesn't
+  /// show, so integrators reusing derivatives from the last step recompute
+  /// them
+  fn reset(&mut self) {}
+
+  /// only adaptive integrators track accepted/rejected steps
+  fn step_stats(&self) -> Option<StepStats> {
+    None
+  }
+
   fn step(
     &mut self,
     model: &M,
@@ -1,15 +1,29 @@
 pub mod euler;
+pub mod implicit_euler;
 pub mod integrator;
 pub mod midpoint;
 pub mod model;
 pub mod null_settings;
 pub mod rk4;
+pub mod rk45;
+mod state_ops;
 pub mod swappable_integrator;
+pub mod symplectic_euler;
+#[cfg(test)]
+mod test_models;
+pub mod velocity_verlet;
 
 pub use euler::{Euler, EulerSettings};
-pub use integrator::Integrator;
+pub use implicit_euler::{ImplicitEuler, ImplicitEulerSettings};
+pub use integrator::{Integrator, StepStats};
 pub use midpoint::{Midpoint, MidpointSettings};
-pub use model::{Model, ModelState};
+pub use model::{
+  finite_difference_jacobian_vector_product, Model, ModelState,
+  SecondOrderModel, DEFAULT_FD_STEP,
+};
 pub use null_settings::NullSettings;
 pub use rk4::{RK4Settings, RK4};
+pub use rk45::{RK45Settings, RK45};
 pub use swappable_integrator::{IntegratorType, SwappableIntegrator};
+pub use symplectic_euler::{SymplecticEuler, SymplecticEulerSettings};
+pub use velocity_verlet::{VelocityVerlet, VelocityVerletSettings};
@@ -1,3 +1,4 @@
+use crate::ode::state_ops::{add_scaled, fill, norm};
 use alga::general::RealField;
 
 pub trait ModelState<S>
@@ -10,6 +11,16 @@ where
   fn zeros_as(&mut self, other: &Self);
 }
 
+impl<S: RealField> ModelState<S> for Vec<S> {
+  fn new() -> Self {
+    Vec::new()
+  }
+
+  fn zeros_as(&mut self, other: &Self) {
+    self.resize(other.len(), S::zero());
+  }
+}
+
 pub trait Model
 where
   for<'a> &'a Self::State: IntoIterator<Item = &'a Self::S>,
@@ -20,4 +31,77 @@ where
   type State: ModelState<Self::S>;
 
   fn derivative(&self, x: &Self::State, dxdt: &mut Self::State, t: &Self::S);
+
+  /// jv = J v, where J is the jacobian of `derivative` at x and dxdt is the
+  /// derivative at x. Defaults to a finite difference approximation.
+  fn jacobian_vector_product(
+    &self,
+    x: &Self::State,
+    dxdt: &Self::State,
+    v: &Self::State,
+    jv: &mut Self::State,
+    t: &Self::S,
+  ) where
+    Self: Sized,
+  {
+    finite_difference_jacobian_vector_product(
+      self,
+      x,
+      dxdt,
+      v,
+      jv,
+      t,
+      DEFAULT_FD_STEP.into(),
+    );
+  }
+}
+
+/// Models of the form x'' = a(x, x'), where the state is split into positions
+/// and velocities and `derivative` writes the accelerations into the
+/// velocity part of dxdt.
+pub trait SecondOrderModel: Model
+where
+  for<'a> &'a Self::State: IntoIterator<Item = &'a Self::S>,
+  for<'a> &'a mut Self::State: IntoIterator<Item = &'a mut Self::S>,
+{
+  /// positions of x += scale * velocities of x
+  fn drift(x: &mut Self::State, scale: Self::S);
+
+  /// velocities of x += scale * velocities of dxdt (the accelerations)
+  fn kick(x: &mut Self::State, scale: Self::S, dxdt: &Self::State);
+}
+
+pub const DEFAULT_FD_STEP: f32 = 1e-7;
+
+/// Forward difference approximation of J v. The perturbation is fd_step
+/// relative to the size of x.
+pub fn finite_difference_jacobian_vector_product<M: Model>(
+  model: &M,
+  x: &M::State,
+  dxdt: &M::State,
+  v: &M::State,
+  jv: &mut M::State,
+  t: &M::S,
+  fd_step: M::S,
+) where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  jv.zeros_as(x);
+
+  let v_norm = norm(v);
+  if v_norm == 0.0.into() {
+    fill(jv, 0.0.into());
+    return;
+  }
+
+  let epsilon = fd_step * (M::S::from(1.0) + norm(x)) / v_norm;
+
+  let mut perturbed = x.clone();
+  add_scaled(&mut perturbed, epsilon, v);
+  model.derivative(&perturbed, jv, t);
+
+  for (jv, dxdt) in jv.into_iter().zip(dxdt) {
+    *jv = (*jv - *dxdt) / epsilon;
+  }
 }
@@ -1,4 +1,6 @@
 use clap::Clap;
+use serde::Deserialize;
 
-#[derive(Clap, Clone, Debug)]
+#[derive(Clap, Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct NullSettings {}
@@ -0,0 +1,321 @@
+use crate::ode::{Integrator, Model, ModelState, StepStats};
+use alga::general::{ComplexField, RealField};
+use clap::Clap;
+use serde::Deserialize;
+
+#[cfg(test)]
+use crate::{
+  assert_float_eq,
+  ode::test_models::{Blowup, Decay},
+};
+
+#[derive(Clap, Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RK45Settings {
+  #[clap(long = "abs-tol", default_value = "1e-6")]
+  /// absolute error tolerance per state component
+  pub abs_tol: f32,
+
+  #[clap(long = "rel-tol", default_value = "1e-3")]
+  /// relative error tolerance per state component
+  pub rel_tol: f32,
+
+  #[clap(long = "min-step", default_value = "1e-7")]
+  /// steps at or below this size are accepted regardless of error, must be
+  /// positive
+  pub min_step: f32,
+}
+
+// the defaults are only given to clap, scene files without the keys get the
+// same values
+impl Default for RK45Settings {
+  fn default() -> Self {
+    Self::parse_from(std::iter::once("rk45"))
+  }
+}
+
+// Dormand-Prince tableau, stored as (numerator, denominator) so the
+// coefficients are exact in the model scalar type
+const C: [(f32, f32); 7] = [
+  (0.0, 1.0),
+  (1.0, 5.0),
+  (3.0, 10.0),
+  (4.0, 5.0),
+  (8.0, 9.0),
+  (1.0, 1.0),
+  (1.0, 1.0),
+];
+
+const A: [&[(f32, f32)]; 6] = [
+  &[(1.0, 5.0)],
+  &[(3.0, 40.0), (9.0, 40.0)],
+  &[(44.0, 45.0), (-56.0, 15.0), (32.0, 9.0)],
+  &[
+    (19372.0, 6561.0),
+    (-25360.0, 2187.0),
+    (64448.0, 6561.0),
+    (-212.0, 729.0),
+  ],
+  &[
+    (9017.0, 3168.0),
+    (-355.0, 33.0),
+    (46732.0, 5247.0),
+    (49.0, 176.0),
+    (-5103.0, 18656.0),
+  ],
+  // also the 5th order solution weights (first same as last)
+  &[
+    (35.0, 384.0),
+    (0.0, 1.0),
+    (500.0, 1113.0),
+    (125.0, 192.0),
+    (-2187.0, 6784.0),
+    (11.0, 84.0),
+  ],
+];
+
+// difference between 5th and 4th order solution weights
+const E: [(f32, f32); 7] = [
+  (71.0, 57600.0),
+  (0.0, 1.0),
+  (-71.0, 16695.0),
+  (71.0, 1920.0),
+  (-17253.0, 339200.0),
+  (22.0, 525.0),
+  (-1.0, 40.0),
+];
+
+const SAFETY: f32 = 0.9;
+const MIN_FACTOR: f32 = 0.2;
+const MAX_FACTOR: f32 = 5.0;
+
+/// Adaptive Dormand-Prince integrator. Each call to `step_internal` covers
+/// the full time step with as many internal steps as the tolerances require.
+pub struct RK45<M: Model>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  settings: RK45Settings,
+  k: [M::State; 7],
+  intermediate_state: M::State,
+  error: M::State,
+  // carried between calls so steady runs don't restart from time_step
+  last_step: Option<M::S>,
+  stats: StepStats,
+}
+
+fn coeff<S: From<f32> + std::ops::Div<Output = S>>(
+  (numerator, denominator): (f32, f32),
+) -> S {
+  S::from(numerator) / S::from(denominator)
+}
+
+impl<M: Model> Integrator<M> for RK45<M>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  type Settings = RK45Settings;
+
+  fn new(settings: Self::Settings) -> Self {
+    Self {
+      settings,
+      k: [
+        M::State::new(),
+        M::State::new(),
+        M::State::new(),
+        M::State::new(),
+        M::State::new(),
+        M::State::new(),
+        M::State::new(),
+      ],
+      intermediate_state: M::State::new(),
+      error: M::State::new(),
+      last_step: None,
+      stats: StepStats::default(),
+    }
+  }
+
+  fn step_internal(
+    &mut self,
+    model: &M,
+    state: &mut M::State,
+    time: &M::S,
+    time_step: &M::S,
+  ) {
+    let zero: M::S = 0.0.into();
+    let one: M::S = 1.0.into();
+    let abs_tol: M::S = self.settings.abs_tol.into();
+    let rel_tol: M::S = self.settings.rel_tol.into();
+    let min_step: M::S = self.settings.min_step.into();
+
+    for k in &mut self.k {
+      k.zeros_as(state);
+    }
+    self.intermediate_state.zeros_as(state);
+    self.error.zeros_as(state);
+
+    let end_time = *time + *time_step;
+    let mut current_time = *time;
+    let mut step = self
+      .last_step
+      .map(|last_step| last_step.min(*time_step))
+      .unwrap_or(*time_step);
+
+    model.derivative(state, &mut self.k[0], &current_time);
+
+    while current_time < end_time {
+      let remaining = end_time - current_time;
+      let is_last = step >= remaining;
+      let this_step = if is_last { remaining } else { step };
+
+      for stage in 1..7 {
+        let (evaluated, rest) = self.k.split_at_mut(stage);
+
+        self.intermediate_state.clone_from(state);
+        for (k, a) in evaluated.iter().zip(A[stage - 1].iter()) {
+          let weight = this_step * coeff(*a);
+          for (intermediate, dxdt) in
+            (&mut self.intermediate_state).into_iter().zip(k)
+          {
+            *intermediate += weight * *dxdt;
+          }
+        }
+
+        model.derivative(
+          &self.intermediate_state,
+          &mut rest[0],
+          &(current_time + coeff::<M::S>(C[stage]) * this_step),
+        );
+      }
+
+      for error in &mut self.error {
+        *error = zero;
+      }
+      for (k, e) in self.k.iter().zip(E.iter()) {
+        let weight = this_step * coeff(*e);
+        for (error, dxdt) in (&mut self.error).into_iter().zip(k) {
+          *error += weight * *dxdt;
+        }
+      }
+
+      let mut sum_sq = zero;
+      let mut count = 0;
+      for ((error, old), new) in (&self.error)
+        .into_iter()
+        .zip(&*state)
+        .zip(&self.intermediate_state)
+      {
+        let scale = abs_tol + rel_tol * old.abs().max(new.abs());
+        let scaled = *error / scale;
+        sum_sq += scaled * scaled;
+        count += 1;
+      }
+      let error_norm = if count == 0 {
+        zero
+      } else {
+        (sum_sq / (count as f32).into()).sqrt()
+      };
+
+      // smaller steps can't make a NaN or infinite state finite again, so
+      // the rest of the time step is given up on
+      if !error_norm.is_finite() {
+        self.stats.failed += 1;
+        break;
+      }
+
+      let factor = if error_norm == zero {
+        MAX_FACTOR.into()
+      } else {
+        (M::S::from(SAFETY) * error_norm.powf((-0.2).into()))
+          .max(MIN_FACTOR.into())
+          .min(MAX_FACTOR.into())
+      };
+
+      if error_norm <= one || this_step <= min_step {
+        self.stats.accepted += 1;
+
+        state.clone_from(&self.intermediate_state);
+        current_time = if is_last {
+          end_time
+        } else {
+          current_time + this_step
+        };
+
+        // the last stage was evaluated at the new state
+        self.k.swap(0, 6);
+
+        // a step truncated to hit end_time says little about the next size
+        if !is_last || factor < one {
+          step = (this_step * factor).max(min_step);
+        }
+      } else {
+        self.stats.rejected += 1;
+
+        step = (this_step * factor.min(one)).max(min_step);
+      }
+    }
+
+    self.last_step = Some(step);
+  }
+
+  fn step_stats(&self) -> Option<StepStats> {
+    Some(self.stats)
+  }
+}
+
+#[test]
+fn decay_adapts_step() {
+  let model = Decay { rate: 2.0 };
+  let mut integrator = RK45::new(RK45Settings {
+    abs_tol: 1e-8,
+    rel_tol: 1e-6,
+    min_step: 1e-7,
+  });
+
+  let mut state = vec![1.0, 0.5];
+  let mut time = 0.0;
+  integrator.step(&model, &mut state, &mut time, &2.0);
+
+  assert_float_eq!(time, 2.0);
+  assert_float_eq!(state[0], (-4.0f64).exp());
+  assert_float_eq!(state[1], 0.5 * (-4.0f64).exp());
+
+  let stats = integrator.step_stats().unwrap();
+  assert!(stats.accepted > 1);
+  assert!(stats.rejected > 0);
+}
+
+#[test]
+fn min_step_bounds_the_step_count() {
+  let model = Decay { rate: 2.0 };
+  // unreachable tolerance, every step is taken at the minimum
+  let mut integrator = RK45::new(RK45Settings {
+    abs_tol: 1e-30,
+    rel_tol: 0.0,
+    min_step: 1e-3,
+  });
+
+  let mut state = vec![1.0];
+  let mut time = 0.0;
+  integrator.step(&model, &mut state, &mut time, &1.0);
+
+  assert_float_eq!(state[0], (-2.0f64).exp());
+  let stats = integrator.step_stats().unwrap();
+  assert!(stats.accepted <= 1001, "{:?}", stats);
+}
+
+#[test]
+fn nan_derivative_fails_the_step() {
+  let model = Blowup { limit: 2.0 };
+  let mut integrator = RK45::new(RK45Settings::default());
+
+  let mut state = vec![1.0];
+  let mut time = 0.0;
+  integrator.step(&model, &mut state, &mut time, &5.0);
+
+  assert_float_eq!(time, 5.0);
+  assert!(state[0].is_finite() && state[0] <= 2.0, "{}", state[0]);
+  assert_eq!(integrator.step_stats().unwrap().failed, 1);
+}
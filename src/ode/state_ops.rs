@@ -0,0 +1,42 @@
+use alga::general::RealField;
+
+// vector operations on model states, which are only accessible through
+// their iterators
+
+pub fn dot<S: RealField, T>(a: &T, b: &T) -> S
+where
+  for<'a> &'a T: IntoIterator<Item = &'a S>,
+{
+  let mut sum = S::zero();
+  for (a, b) in a.into_iter().zip(b) {
+    sum += *a * *b;
+  }
+  sum
+}
+
+pub fn norm<S: RealField, T>(a: &T) -> S
+where
+  for<'a> &'a T: IntoIterator<Item = &'a S>,
+{
+  dot(a, a).sqrt()
+}
+
+pub fn fill<S: RealField, T>(target: &mut T, value: S)
+where
+  for<'a> &'a mut T: IntoIterator<Item = &'a mut S>,
+{
+  for target in target {
+    *target = value;
+  }
+}
+
+/// target += scale * other
+pub fn add_scaled<S: RealField, T>(target: &mut T, scale: S, other: &T)
+where
+  for<'a> &'a T: IntoIterator<Item = &'a S>,
+  for<'a> &'a mut T: IntoIterator<Item = &'a mut S>,
+{
+  for (target, other) in target.into_iter().zip(other) {
+    *target += scale * *other;
+  }
+}
@@ -1,18 +1,30 @@
 use crate::ode::{
-  Euler, EulerSettings, Integrator, Midpoint, MidpointSettings, Model,
-  RK4Settings, RK4,
+  Euler, EulerSettings, ImplicitEuler, ImplicitEulerSettings, Integrator,
+  Midpoint, MidpointSettings, RK45Settings, RK4Settings, SecondOrderModel,
+  StepStats, SymplecticEuler, SymplecticEulerSettings, VelocityVerlet,
+  VelocityVerletSettings, RK4, RK45,
 };
 use clap::Clap;
+use serde::Deserialize;
 
-#[derive(Clap, Clone, Debug)]
+// scene files use the same names as the subcommands
+#[derive(Clap, Clone, Debug, Deserialize)]
 #[clap(about = "integrator type and args")]
+#[serde(tag = "type", rename_all = "kebab-case")]
 pub enum IntegratorType {
   Euler(EulerSettings),
   Midpoint(MidpointSettings),
+  #[serde(rename = "rk4")]
   RK4(RK4Settings),
+  #[serde(rename = "rk45")]
+  RK45(RK45Settings),
+  ImplicitEuler(ImplicitEulerSettings),
+  SymplecticEuler(SymplecticEulerSettings),
+  VelocityVerlet(VelocityVerletSettings),
 }
 
-pub enum SwappableIntegrator<M: Model>
+// symplectic integrators need the position/velocity split
+pub enum SwappableIntegrator<M: SecondOrderModel>
 where
   for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
   for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
@@ -20,16 +32,20 @@ where
   Euler(Euler<M>),
   Midpoint(Midpoint<M>),
   RK4(RK4<M>),
+  RK45(RK45<M>),
+  ImplicitEuler(ImplicitEuler<M>),
+  SymplecticEuler(SymplecticEuler<M>),
+  VelocityVerlet(VelocityVerlet<M>),
 }
 
-// impl<M: Model> SwappableIntegrator<M>
+// impl<M: SecondOrderModel> SwappableIntegrator<M>
 // where
 //   for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
 //   for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
 // {
 // }
 
-impl<M: Model> Integrator<M> for SwappableIntegrator<M>
+impl<M: SecondOrderModel> Integrator<M> for SwappableIntegrator<M>
 where
   for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
   for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
@@ -43,6 +59,16 @@ where
         Self::Midpoint(Midpoint::new(settings))
       }
       IntegratorType::RK4(settings) => Self::RK4(RK4::new(settings)),
+      IntegratorType::RK45(settings) => Self::RK45(RK45::new(settings)),
+      IntegratorType::ImplicitEuler(settings) => {
+        Self::ImplicitEuler(ImplicitEuler::new(settings))
+      }
+      IntegratorType::SymplecticEuler(settings) => {
+        Self::SymplecticEuler(SymplecticEuler::new(settings))
+      }
+      IntegratorType::VelocityVerlet(settings) => {
+        Self::VelocityVerlet(VelocityVerlet::new(settings))
+      }
     }
   }
 
@@ -62,6 +88,40 @@ where
         method.step_internal(model, state, time, time_step)
       }
       Self::RK4(method) => method.step_internal(model, state, time, time_step),
+      Self::RK45(method) => method.step_internal(model, state, time, time_step),
+      Self::ImplicitEuler(method) => {
+        method.step_internal(model, state, time, time_step)
+      }
+      Self::SymplecticEuler(method) => {
+        method.step_internal(model, state, time, time_step)
+      }
+      Self::VelocityVerlet(method) => {
+        method.step_internal(model, state, time, time_step)
+      }
+    }
+  }
+
+  fn reset(&mut self) {
+    match self {
+      Self::Euler(method) => method.reset(),
+      Self::Midpoint(method) => method.reset(),
+      Self::RK4(method) => method.reset(),
+      Self::RK45(method) => method.reset(),
+      Self::ImplicitEuler(method) => method.reset(),
+      Self::SymplecticEuler(method) => method.reset(),
+      Self::VelocityVerlet(method) => method.reset(),
+    }
+  }
+
+  fn step_stats(&self) -> Option<StepStats> {
+    match self {
+      Self::Euler(method) => method.step_stats(),
+      Self::Midpoint(method) => method.step_stats(),
+      Self::RK4(method) => method.step_stats(),
+      Self::RK45(method) => method.step_stats(),
+      Self::ImplicitEuler(method) => method.step_stats(),
+      Self::SymplecticEuler(method) => method.step_stats(),
+      Self::VelocityVerlet(method) => method.step_stats(),
     }
   }
 }
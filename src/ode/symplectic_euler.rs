@@ -0,0 +1,55 @@
+use crate::ode::{Integrator, ModelState, NullSettings, SecondOrderModel};
+
+pub type SymplecticEulerSettings = NullSettings;
+
+/// Semi-implicit Euler: velocities are updated first and the new velocities
+/// are used to update positions.
+pub struct SymplecticEuler<M: SecondOrderModel>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  dxdt: M::State,
+}
+
+impl<M: SecondOrderModel> Integrator<M> for SymplecticEuler<M>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  type Settings = SymplecticEulerSettings;
+
+  fn new(_: Self::Settings) -> Self {
+    Self {
+      dxdt: M::State::new(),
+    }
+  }
+
+  fn step_internal(
+    &mut self,
+    model: &M,
+    state: &mut M::State,
+    time: &M::S,
+    time_step: &M::S,
+  ) {
+    self.dxdt.zeros_as(state);
+
+    model.derivative(state, &mut self.dxdt, time);
+
+    M::kick(state, *time_step, &self.dxdt);
+    M::drift(state, *time_step);
+  }
+}
+
+#[test]
+fn energy_drift_below_midpoint() {
+  use crate::ode::{test_models::oscillator_energy_error, Midpoint};
+
+  let symplectic_error =
+    oscillator_energy_error(&mut SymplecticEuler::new(NullSettings {}));
+  let midpoint_error =
+    oscillator_energy_error(&mut Midpoint::new(NullSettings {}));
+
+  assert!(symplectic_error < 0.1);
+  assert!(symplectic_error * 10.0 < midpoint_error);
+}
@@ -0,0 +1,112 @@
+use crate::ode::{Integrator, Model, SecondOrderModel};
+
+pub struct Decay {
+  pub rate: f64,
+}
+
+impl Model for Decay {
+  type S = f64;
+  type State = Vec<f64>;
+
+  fn derivative(&self, x: &Vec<f64>, dxdt: &mut Vec<f64>, _: &f64) {
+    for (x, dxdt) in x.iter().zip(dxdt.iter_mut()) {
+      *dxdt = -self.rate * x;
+    }
+  }
+}
+
+/// dx/dt = -rate x^3, newton needs many iterations for large rate * h
+pub struct CubicDecay {
+  pub rate: f64,
+}
+
+impl Model for CubicDecay {
+  type S = f64;
+  type State = Vec<f64>;
+
+  fn derivative(&self, x: &Vec<f64>, dxdt: &mut Vec<f64>, _: &f64) {
+    for (x, dxdt) in x.iter().zip(dxdt.iter_mut()) {
+      *dxdt = -self.rate * x * x * x;
+    }
+  }
+}
+
+/// dx/dt = x, which turns into NaN once x is above `limit`
+pub struct Blowup {
+  pub limit: f64,
+}
+
+impl Model for Blowup {
+  type S = f64;
+  type State = Vec<f64>;
+
+  fn derivative(&self, x: &Vec<f64>, dxdt: &mut Vec<f64>, _: &f64) {
+    for (x, dxdt) in x.iter().zip(dxdt.iter_mut()) {
+      *dxdt = if *x > self.limit { f64::NAN } else { *x };
+    }
+  }
+}
+
+/// Undamped harmonic oscillators, the state is all positions followed by all
+/// velocities.
+pub struct Oscillator {
+  pub stiffness: f64,
+}
+
+impl Oscillator {
+  pub fn energy(&self, x: &[f64]) -> f64 {
+    let (positions, velocities) = x.split_at(x.len() / 2);
+    0.5
+      * (positions
+        .iter()
+        .map(|x| self.stiffness * x * x)
+        .sum::<f64>()
+        + velocities.iter().map(|v| v * v).sum::<f64>())
+  }
+}
+
+impl Model for Oscillator {
+  type S = f64;
+  type State = Vec<f64>;
+
+  fn derivative(&self, x: &Vec<f64>, dxdt: &mut Vec<f64>, _: &f64) {
+    let half = x.len() / 2;
+    let (positions, velocities) = x.split_at(half);
+    let (d_positions, d_velocities) = dxdt.split_at_mut(half);
+    d_positions.copy_from_slice(velocities);
+    for (d_velocity, position) in d_velocities.iter_mut().zip(positions) {
+      *d_velocity = -self.stiffness * position;
+    }
+  }
+}
+
+impl SecondOrderModel for Oscillator {
+  fn drift(x: &mut Vec<f64>, scale: f64) {
+    let half = x.len() / 2;
+    let (positions, velocities) = x.split_at_mut(half);
+    for (position, velocity) in positions.iter_mut().zip(velocities.iter()) {
+      *position += scale * velocity;
+    }
+  }
+
+  fn kick(x: &mut Vec<f64>, scale: f64, dxdt: &Vec<f64>) {
+    let half = x.len() / 2;
+    for (velocity, accel) in x[half..].iter_mut().zip(&dxdt[half..]) {
+      *velocity += scale * accel;
+    }
+  }
+}
+
+/// relative energy drift after integrating oscillators for many periods
+pub fn oscillator_energy_error<I: Integrator<Oscillator>>(
+  integrator: &mut I,
+) -> f64 {
+  let model = Oscillator { stiffness: 4.0 };
+  let mut state = vec![1.0, 0.0, -0.5, 0.0, 1.0, 2.0];
+  let initial_energy = model.energy(&state);
+
+  let mut time = 0.0;
+  integrator.n_steps(&model, &mut state, &mut time, &0.05, 20000);
+
+  ((model.energy(&state) - initial_energy) / initial_energy).abs()
+}
@@ -0,0 +1,99 @@
+use crate::ode::{Integrator, ModelState, NullSettings, SecondOrderModel};
+
+pub type VelocityVerletSettings = NullSettings;
+
+/// Velocity Verlet in kick-drift-kick form. The acceleration at the end of
+/// a step is reused at the start of the next, so each step costs one
+/// derivative evaluation. With velocity dependent forces the end of step
+/// acceleration uses the half step velocities.
+pub struct VelocityVerlet<M: SecondOrderModel>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  dxdt: M::State,
+  // number of state values dxdt was computed for, None after a reset. The
+  // length also catches states replaced between steps
+  dxdt_len: Option<usize>,
+}
+
+impl<M: SecondOrderModel> Integrator<M> for VelocityVerlet<M>
+where
+  for<'a> &'a M::State: IntoIterator<Item = &'a M::S>,
+  for<'a> &'a mut M::State: IntoIterator<Item = &'a mut M::S>,
+{
+  type Settings = VelocityVerletSettings;
+
+  fn new(_: Self::Settings) -> Self {
+    Self {
+      dxdt: M::State::new(),
+      dxdt_len: None,
+    }
+  }
+
+  fn step_internal(
+    &mut self,
+    model: &M,
+    state: &mut M::State,
+    time: &M::S,
+    time_step: &M::S,
+  ) {
+    let half_time_step = *time_step * 0.5.into();
+    let len = state.into_iter().count();
+
+    if self.dxdt_len != Some(len) {
+      self.dxdt.zeros_as(state);
+      model.derivative(state, &mut self.dxdt, time);
+      self.dxdt_len = Some(len);
+    }
+
+    M::kick(state, half_time_step, &self.dxdt);
+    M::drift(state, *time_step);
+
+    model.derivative(state, &mut self.dxdt, &(*time + *time_step));
+
+    M::kick(state, half_time_step, &self.dxdt);
+  }
+
+  fn reset(&mut self) {
+    self.dxdt_len = None;
+  }
+}
+
+#[test]
+fn reset_recomputes_derivative() {
+  use crate::ode::test_models::Oscillator;
+
+  let stiff = Oscillator { stiffness: 4.0 };
+  let soft = Oscillator { stiffness: 1.0 };
+  let mut time = 0.0;
+
+  let mut state = vec![1.0, 0.5];
+  let mut integrator = VelocityVerlet::new(NullSettings {});
+  integrator.step(&stiff, &mut state, &mut time, &0.1);
+  integrator.reset();
+
+  let mut expected = state.clone();
+  VelocityVerlet::new(NullSettings {}).step(
+    &soft,
+    &mut expected,
+    &mut time.clone(),
+    &0.1,
+  );
+  integrator.step(&soft, &mut state, &mut time, &0.1);
+
+  assert_eq!(state, expected);
+}
+
+#[test]
+fn energy_drift_below_midpoint() {
+  use crate::ode::{test_models::oscillator_energy_error, Midpoint};
+
+  let verlet_error =
+    oscillator_energy_error(&mut VelocityVerlet::new(NullSettings {}));
+  let midpoint_error =
+    oscillator_energy_error(&mut Midpoint::new(NullSettings {}));
+
+  assert!(verlet_error < 0.01);
+  assert!(verlet_error * 100.0 < midpoint_error);
+}
@@ -1,5 +1,6 @@
 use kiss3d::scene::SceneNode;
 use nalgebra::Point3;
+use std::io;
 
 // TODO: have up?
 #[derive(Clone, Debug)]
@@ -18,4 +19,25 @@ pub trait SceneGenerator {
 
 pub trait Scene {
   fn update(&mut self, delta_secs: f32);
+
+  /// called before each update, frame counts from 0
+  fn save_frame(&mut self, _frame: usize) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// printed once the scene stops running
+  fn summary(&self) -> Option<String> {
+    None
+  }
+}
+
+pub trait HeadlessSceneGenerator {
+  type S: HeadlessScene;
+
+  fn init_headless(&self) -> Self::S;
+}
+
+pub trait HeadlessScene: Scene {
+  /// simulated seconds since the start
+  fn time(&self) -> f32;
 }
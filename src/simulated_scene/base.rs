@@ -1,14 +1,18 @@
 use crate::{
-  ode::{Integrator, IntegratorType, SwappableIntegrator},
+  ode::IntegratorType,
+  prepare_output_dir,
   simulated_scene::{
-    MeshParams, SceneModel, SceneModelParams, SceneModelState, SimMesh, S,
+    SceneModel, SceneModelParams, SceneSimulation, SimMesh, S,
   },
-  CameraInfo, LoadedMesh, Scene, SceneGenerator,
+  surface_export::SurfaceFormat,
+  CameraInfo, HeadlessSceneGenerator, Scene, SceneGenerator,
 };
 use kiss3d::resource::Mesh as Kiss3dMesh;
 use kiss3d::scene::SceneNode;
 use nalgebra::{Point3, Translation3, Vector3};
 use std::cell::RefCell;
+use std::io;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 #[derive(Clone, Debug)]
@@ -23,16 +27,61 @@ pub struct IntegrationParams {
   pub step_params: StepParams,
 }
 
+#[derive(Clone, Debug)]
+pub struct SurfaceOutput {
+  pub dir: PathBuf,
+  pub format: SurfaceFormat,
+  pub normals: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OutputParams {
+  /// csv of every vertex position and velocity per frame
+  pub state_dir: Option<PathBuf>,
+  /// boundary surface of each mesh per frame
+  pub surface: Option<SurfaceOutput>,
+  /// tetrahedral meshes with velocities and strain/stress per frame (.vtu)
+  pub vtk_dir: Option<PathBuf>,
+}
+
+impl OutputParams {
+  /// Creates the output directories. Returns false if one of them can't be
+  /// used.
+  pub fn prepare(&self) -> io::Result<bool> {
+    if let Some(state_dir) = &self.state_dir {
+      if !prepare_output_dir(state_dir, "Output directory")? {
+        return Ok(false);
+      }
+    }
+
+    if let Some(surface) = &self.surface {
+      if !prepare_output_dir(&surface.dir, "Surface directory")? {
+        return Ok(false);
+      }
+    }
+
+    if let Some(vtk_dir) = &self.vtk_dir {
+      if !prepare_output_dir(vtk_dir, "VTK directory")? {
+        return Ok(false);
+      }
+    }
+
+    Ok(true)
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct GlobalParams {
   pub scene_model_params: SceneModelParams,
   pub integration_params: IntegrationParams,
+  pub output_params: OutputParams,
 }
 
 // TODO: make configurable
 pub struct SimulatedSceneGenerator {
   camera_info: CameraInfo,
   integration_params: IntegrationParams,
+  output_params: OutputParams,
   scene_model: SceneModel,
 }
 
@@ -40,7 +89,7 @@ impl SimulatedSceneGenerator {
   pub fn new(
     camera_info: CameraInfo,
     global_params: GlobalParams,
-    meshs: Vec<(LoadedMesh, MeshParams)>,
+    meshs: Vec<SimMesh>,
     penalty_force: S,
     floor_friction_coeff: S,
     floor_pos: S,
@@ -50,16 +99,15 @@ impl SimulatedSceneGenerator {
     let GlobalParams {
       scene_model_params,
       integration_params,
+      output_params,
     } = global_params;
 
     Self {
       camera_info,
       integration_params,
+      output_params,
       scene_model: SceneModel::new(
-        meshs
-          .into_iter()
-          .map(|(mesh, params)| SimMesh::new(mesh, params))
-          .collect(),
+        meshs,
         scene_model_params,
         penalty_force,
         floor_friction_coeff,
@@ -69,45 +117,161 @@ impl SimulatedSceneGenerator {
       ),
     }
   }
-}
 
-pub struct SimulatedScene {
-  meshes: Vec<Rc<RefCell<Kiss3dMesh>>>,
-  scene_model: SceneModel,
-  scene_state: SceneModelState,
-  step_params: StepParams,
-  integrator: SwappableIntegrator<SceneModel>,
+  pub fn init_simulation(&self) -> SceneSimulation {
+    SceneSimulation::new(
+      self.scene_model.clone(),
+      self.integration_params.step_params.clone(),
+      self.integration_params.integrator_type.clone(),
+      self.output_params.clone(),
+    )
+  }
 }
 
-impl Scene for SimulatedScene {
-  fn update(&mut self, delta_secs: f32) {
-    let delta_secs = (delta_secs as S) * self.step_params.speed_up;
-    let steps = (delta_secs / self.step_params.time_step).ceil() as usize;
-
-    self.integrator.n_steps(
-      &self.scene_model,
-      &mut self.scene_state,
-      &mut 0.0,
-      &(delta_secs / steps as S),
-      steps,
-    );
-
-    for ((mesh, sim_mesh), mesh_interval) in self
-      .meshes
+// kiss3d meshes have u16 indices
+const KISS3D_MAX_VERTICES: usize = u16::MAX as usize + 1;
+
+type Kiss3dSurface = (Vec<Point3<f32>>, Vec<Point3<u16>>);
+
+/// Splits a surface into pieces with at most max_vertices vertices each.
+/// Vertices shared between pieces are duplicated.
+fn split_surface(
+  positions: Vec<Point3<f32>>,
+  faces: &[Point3<u32>],
+  max_vertices: usize,
+) -> Vec<Kiss3dSurface> {
+  if positions.len() <= max_vertices {
+    let faces = faces
+      .iter()
+      .map(|face| Point3::new(face[0] as u16, face[1] as u16, face[2] as u16))
+      .collect();
+
+    return vec![(positions, faces)];
+  }
+
+  let mut pieces = Vec::new();
+  let mut piece: Kiss3dSurface = (Vec::new(), Vec::new());
+  // index in the current piece along with which piece that is
+  let mut local_idxs: Vec<Option<(usize, u16)>> = vec![None; positions.len()];
+
+  for face in faces {
+    let piece_idx = pieces.len();
+    let new_vertices = face
       .iter()
-      .zip(self.scene_model.meshs().iter())
-      .zip(self.scene_model.mesh_intervals().iter())
+      .filter(|v| {
+        local_idxs[**v as usize].map(|(idx, _)| idx) != Some(piece_idx)
+      })
+      .count();
+
+    if piece.0.len() + new_vertices > max_vertices {
+      pieces.push(std::mem::take(&mut piece));
+    }
+
+    let piece_idx = pieces.len();
+    let mut local_face = Point3::new(0, 0, 0);
+    for (vertex_idx, local_vertex_idx) in face.iter().zip(local_face.iter_mut())
     {
-      let (positions, faces) = sim_mesh.boundary_vertices_faces(
-        &self.scene_state.positions
-          [(mesh_interval[0] as usize)..(mesh_interval[1] as usize)],
-      );
+      let vertex_idx = *vertex_idx as usize;
+
+      *local_vertex_idx = match local_idxs[vertex_idx] {
+        Some((idx, local_idx)) if idx == piece_idx => local_idx,
+        _ => {
+          let local_idx = piece.0.len() as u16;
+          piece.0.push(positions[vertex_idx]);
+          local_idxs[vertex_idx] = Some((piece_idx, local_idx));
+
+          local_idx
+        }
+      };
+    }
+
+    piece.1.push(local_face);
+  }
+
+  pieces.push(piece);
+
+  pieces
+}
+
+// the pieces of one mesh's surface, more are added as needed
+struct RenderedMesh {
+  group: SceneNode,
+  pieces: Vec<Rc<RefCell<Kiss3dMesh>>>,
+}
 
+impl RenderedMesh {
+  fn add_piece(&mut self) {
+    let mesh = Rc::new(RefCell::new(Kiss3dMesh::new(
+      Vec::new(),
+      Vec::new(),
+      None,
+      None,
+      true,
+    )));
+
+    let mut mesh_scene_node = self
+      .group
+      .add_mesh(mesh.clone(), Vector3::new(1.0, 1.0, 1.0));
+
+    mesh_scene_node.enable_backface_culling(false);
+    mesh_scene_node.set_color(1.0, 0.0, 0.0);
+    mesh_scene_node.set_lines_color(Some(Point3::new(0.0, 0.0, 0.0)));
+    mesh_scene_node.set_lines_width(3.0);
+
+    self.pieces.push(mesh);
+  }
+
+  fn update(&mut self, positions: Vec<Point3<f32>>, faces: &[Point3<u32>]) {
+    let pieces = split_surface(positions, faces, KISS3D_MAX_VERTICES);
+
+    while self.pieces.len() < pieces.len() {
+      self.add_piece();
+    }
+
+    // left over pieces are emptied
+    for (mesh, (positions, faces)) in self.pieces.iter().zip(
+      pieces
+        .into_iter()
+        .chain(std::iter::repeat_with(|| (Vec::new(), Vec::new()))),
+    ) {
       mesh.replace(Kiss3dMesh::new(positions, faces, None, None, true));
     }
   }
 }
 
+pub struct SimulatedScene {
+  meshes: Vec<RenderedMesh>,
+  simulation: SceneSimulation,
+}
+
+impl SimulatedScene {
+  fn update_meshes(&mut self) {
+    for (mesh, (sim_mesh, positions, _)) in
+      self.meshes.iter_mut().zip(self.simulation.meshs_states())
+    {
+      let (positions, faces) = sim_mesh.boundary_vertices_faces(positions);
+
+      mesh.update(positions, &faces);
+    }
+  }
+}
+
+impl Scene for SimulatedScene {
+  fn update(&mut self, delta_secs: f32) {
+    self.simulation.update(delta_secs);
+
+    self.update_meshes();
+  }
+
+  fn save_frame(&mut self, frame: usize) -> io::Result<()> {
+    self.simulation.save_frame(frame)
+  }
+
+  fn summary(&self) -> Option<String> {
+    self.simulation.summary()
+  }
+}
+
 impl SceneGenerator for SimulatedSceneGenerator {
   type S = SimulatedScene;
 
@@ -133,32 +297,12 @@ impl SceneGenerator for SimulatedSceneGenerator {
 
     SimulatedScene {
       meshes: (0..self.scene_model.meshs().len())
-        .map(|_| {
-          let mesh = Rc::new(RefCell::new(Kiss3dMesh::new(
-            Vec::new(),
-            Vec::new(),
-            None,
-            None,
-            true,
-          )));
-
-          let mut mesh_scene_node =
-            node.add_mesh(mesh.clone(), Vector3::new(1.0, 1.0, 1.0));
-
-          mesh_scene_node.enable_backface_culling(false);
-          mesh_scene_node.set_color(1.0, 0.0, 0.0);
-          mesh_scene_node.set_lines_color(Some(Point3::new(0.0, 0.0, 0.0)));
-          mesh_scene_node.set_lines_width(3.0);
-
-          mesh
+        .map(|_| RenderedMesh {
+          group: node.add_group(),
+          pieces: Vec::new(),
         })
         .collect(),
-      scene_model: self.scene_model.clone(),
-      scene_state: self.scene_model.initial_state(),
-      step_params: self.integration_params.step_params.clone(),
-      integrator: SwappableIntegrator::new(
-        self.integration_params.integrator_type.clone(),
-      ),
+      simulation: self.init_simulation(),
     }
   }
 
@@ -166,3 +310,48 @@ impl SceneGenerator for SimulatedSceneGenerator {
     self.camera_info.clone()
   }
 }
+
+impl HeadlessSceneGenerator for SimulatedSceneGenerator {
+  type S = SceneSimulation;
+
+  fn init_headless(&self) -> Self::S {
+    self.init_simulation()
+  }
+}
+
+#[test]
+fn split_surface_pieces() {
+  // strip of triangles along x
+  let n = 20;
+  let positions: Vec<_> = (0..2 * n)
+    .map(|i| Point3::new((i / 2) as f32, (i % 2) as f32, 0.0))
+    .collect();
+  let faces: Vec<_> = (0..2 * n as u32 - 2)
+    .map(|i| Point3::new(i, i + 1, i + 2))
+    .collect();
+
+  let whole = split_surface(positions.clone(), &faces, positions.len());
+  assert_eq!(whole.len(), 1);
+  assert_eq!(whole[0].1.len(), faces.len());
+
+  let pieces = split_surface(positions.clone(), &faces, 7);
+  assert!(pieces.len() > 1);
+  assert_eq!(
+    pieces.iter().map(|(_, faces)| faces.len()).sum::<usize>(),
+    faces.len()
+  );
+
+  let mut face_iter = faces.iter();
+  for (piece_positions, piece_faces) in &pieces {
+    assert!(piece_positions.len() <= 7);
+    for piece_face in piece_faces {
+      let face = face_iter.next().unwrap();
+      for (local, global) in piece_face.iter().zip(face.iter()) {
+        assert_eq!(
+          piece_positions[*local as usize],
+          positions[*global as usize]
+        );
+      }
+    }
+  }
+}
@@ -0,0 +1,508 @@
+use crate::simulated_scene::S;
+use nalgebra::{Matrix3, Vector3};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+/// Elastic response of a mesh. All models take the same incompressibility
+/// (lambda) and rigidity (mu) and agree for small deformations.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[serde(rename_all = "kebab-case")]
+pub enum Material {
+  /// St. Venant-Kirchhoff, linear in the Green strain. Cheap, but collapses
+  /// when compressed far enough.
+  StVenantKirchhoff,
+  /// Stable Neo-Hookean (Smith et al. 2018), which resists compression and
+  /// stays well behaved for inverted tets.
+  NeoHookean,
+  /// Corotated linear elasticity, linear elasticity in a frame rotating with
+  /// the tet.
+  Corotated,
+}
+
+impl FromStr for Material {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "st-venant-kirchhoff" | "stvk" => Ok(Material::StVenantKirchhoff),
+      "neo-hookean" => Ok(Material::NeoHookean),
+      "corotated" => Ok(Material::Corotated),
+      _ => Err(format!(
+        "unknown material '{}' (expected st-venant-kirchhoff, neo-hookean or \
+         corotated)",
+        s
+      )),
+    }
+  }
+}
+
+// 2nd Piola-Kirchhoff stress for a strain, also used for the viscous stress
+pub(crate) fn strain_to_stress(
+  strain: Matrix3<S>,
+  incompressibility: S,
+  rigidity: S,
+) -> Matrix3<S> {
+  incompressibility * Matrix3::identity() * strain.trace()
+    + 2.0 * rigidity * strain
+}
+
+// derivative of the determinant, the columns are the cross products of the
+// other columns
+fn cofactor(mat: &Matrix3<S>) -> Matrix3<S> {
+  let col = |idx: usize| mat.column(idx).into_owned();
+
+  Matrix3::from_columns(&[
+    col(1).cross(&col(2)),
+    col(2).cross(&col(0)),
+    col(0).cross(&col(1)),
+  ])
+}
+
+fn cofactor_differential(mat: &Matrix3<S>, d_mat: &Matrix3<S>) -> Matrix3<S> {
+  let col = |idx: usize| mat.column(idx).into_owned();
+  let d_col = |idx: usize| d_mat.column(idx).into_owned();
+  let d_cross =
+    |a: usize, b: usize| d_col(a).cross(&col(b)) + col(a).cross(&d_col(b));
+
+  Matrix3::from_columns(&[d_cross(1, 2), d_cross(2, 0), d_cross(0, 1)])
+}
+
+const POLAR_ITERATIONS: usize = 20;
+
+// orthogonal factor of the polar decomposition by scaled Newton iteration,
+// which unlike the SVD stays accurate for nearly repeated singular values.
+// None for singular matrices.
+fn orthogonal_factor(mat: &Matrix3<S>) -> Option<Matrix3<S>> {
+  let mut orthogonal = *mat;
+  for _ in 0..POLAR_ITERATIONS {
+    let inverse_t = orthogonal.try_inverse()?.transpose();
+    let scale = (inverse_t.norm() / orthogonal.norm()).sqrt();
+    let next = 0.5 * (scale * orthogonal + inverse_t / scale);
+    let change = (next - orthogonal).norm();
+    orthogonal = next;
+
+    if change <= 1e-14 {
+      break;
+    }
+  }
+
+  if orthogonal.iter().all(|v| v.is_finite()) {
+    Some(orthogonal)
+  } else {
+    None
+  }
+}
+
+// rotation and symmetric stretch of the polar decomposition, reflections are
+// moved into the smallest stretch so inverted tets still give a rotation
+fn rotation_stretch(deformation_grad: &Matrix3<S>) -> (Matrix3<S>, Matrix3<S>) {
+  let orthogonal = orthogonal_factor(deformation_grad).or_else(|| {
+    // singular, only the svd can be used. The iteration limit avoids looping
+    // forever on non finite values
+    let svd = deformation_grad.try_svd(true, true, S::EPSILON, 1000)?;
+    Some(svd.u? * svd.v_t?)
+  });
+  let orthogonal = match orthogonal {
+    Some(orthogonal) => orthogonal,
+    None => return (Matrix3::identity(), *deformation_grad),
+  };
+
+  let stretch = orthogonal.transpose() * deformation_grad;
+  if orthogonal.determinant() >= 0.0 {
+    return (orthogonal, stretch);
+  }
+
+  // reflect along the eigenvector of the smallest stretch
+  let eigen = stretch.symmetric_eigen();
+  let (smallest, _) = eigen.eigenvalues.iter().enumerate().fold(
+    (0, S::INFINITY),
+    |min, (idx, value)| {
+      if *value < min.1 {
+        (idx, *value)
+      } else {
+        min
+      }
+    },
+  );
+  let axis = eigen.eigenvectors.column(smallest);
+  let reflection = Matrix3::identity() - 2.0 * axis * axis.transpose();
+
+  (orthogonal * reflection, reflection * stretch)
+}
+
+impl Material {
+  /// First Piola-Kirchhoff stress for a deformation gradient. Stresses are
+  /// scaled like the St. Venant-Kirchhoff model, which uses `F^T F - I` as
+  /// its strain (twice the Green strain).
+  pub fn stress(
+    &self,
+    deformation_grad: &Matrix3<S>,
+    incompressibility: S,
+    rigidity: S,
+  ) -> Matrix3<S> {
+    let f = deformation_grad;
+    let (lambda, mu) = (2.0 * incompressibility, 2.0 * rigidity);
+
+    match self {
+      Material::StVenantKirchhoff => {
+        let strain = f.transpose() * f - Matrix3::identity();
+        f * strain_to_stress(strain, incompressibility, rigidity)
+      }
+      Material::NeoHookean => {
+        // Lame parameters matched to linear elasticity, as in the paper
+        let (lambda, mu) = (lambda + 5.0 / 6.0 * mu, 4.0 / 3.0 * mu);
+        let i_c = f.norm_squared();
+
+        mu * (1.0 - 1.0 / (i_c + 1.0)) * f
+          + (lambda * (f.determinant() - 1.0) - 0.75 * mu) * cofactor(f)
+      }
+      Material::Corotated => {
+        let (rotation, stretch) = rotation_stretch(f);
+
+        2.0 * mu * (f - rotation) + lambda * (stretch.trace() - 3.0) * rotation
+      }
+    }
+  }
+
+  /// Directional derivative of `stress` along `d_deformation_grad`.
+  pub fn stress_differential(
+    &self,
+    deformation_grad: &Matrix3<S>,
+    d_deformation_grad: &Matrix3<S>,
+    incompressibility: S,
+    rigidity: S,
+  ) -> Matrix3<S> {
+    let (f, d_f) = (deformation_grad, d_deformation_grad);
+    let (lambda, mu) = (2.0 * incompressibility, 2.0 * rigidity);
+
+    match self {
+      Material::StVenantKirchhoff => {
+        let strain = f.transpose() * f - Matrix3::identity();
+        let d_strain = d_f.transpose() * f + f.transpose() * d_f;
+
+        d_f * strain_to_stress(strain, incompressibility, rigidity)
+          + f * strain_to_stress(d_strain, incompressibility, rigidity)
+      }
+      Material::NeoHookean => {
+        let (lambda, mu) = (lambda + 5.0 / 6.0 * mu, 4.0 / 3.0 * mu);
+        let i_c = f.norm_squared();
+        let d_i_c = 2.0 * f.dot(d_f);
+        let cofactor = cofactor(f);
+        let d_det = cofactor.dot(d_f);
+
+        mu * (1.0 - 1.0 / (i_c + 1.0)) * d_f
+          + mu * d_i_c / (i_c + 1.0).powi(2) * f
+          + lambda * d_det * cofactor
+          + (lambda * (f.determinant() - 1.0) - 0.75 * mu)
+            * cofactor_differential(f, d_f)
+      }
+      Material::Corotated => {
+        let (rotation, stretch) = rotation_stretch(f);
+
+        // R^T dR is skew, its axis w solves (tr(S) I - S) w = axis of
+        // (R^T dF - dF^T R)
+        let skew = rotation.transpose() * d_f;
+        let skew = skew - skew.transpose();
+        let axis = Vector3::new(skew[(2, 1)], skew[(0, 2)], skew[(1, 0)]);
+        let d_rotation = (Matrix3::identity() * stretch.trace() - stretch)
+          .try_inverse()
+          .map(|inv| rotation * (inv * axis).cross_matrix())
+          .unwrap_or_else(Matrix3::zeros);
+        let d_stretch_trace =
+          (d_rotation.transpose() * f + rotation.transpose() * d_f).trace();
+
+        2.0 * mu * (d_f - d_rotation)
+          + lambda * d_stretch_trace * rotation
+          + lambda * (stretch.trace() - 3.0) * d_rotation
+      }
+    }
+  }
+}
+
+/// Stress of fibers along the unit vector `direction` (in the rest
+/// configuration), added to the stress of the material to make it
+/// transversely isotropic. The energy is `stiffness / 8 * (|F a|^2 - 1)^2`,
+/// so for small strains `stiffness` is the Young's modulus the fibers add
+/// along their direction. The fibers resist compression as well as tension.
+pub fn fiber_stress(
+  deformation_grad: &Matrix3<S>,
+  direction: &Vector3<S>,
+  stiffness: S,
+) -> Matrix3<S> {
+  let stretched = deformation_grad * direction;
+
+  0.5
+    * stiffness
+    * (stretched.norm_squared() - 1.0)
+    * stretched
+    * direction.transpose()
+}
+
+/// Directional derivative of `fiber_stress` along `d_deformation_grad`.
+pub fn fiber_stress_differential(
+  deformation_grad: &Matrix3<S>,
+  d_deformation_grad: &Matrix3<S>,
+  direction: &Vector3<S>,
+  stiffness: S,
+) -> Matrix3<S> {
+  let stretched = deformation_grad * direction;
+  let d_stretched = d_deformation_grad * direction;
+
+  0.5
+    * stiffness
+    * ((stretched.norm_squared() - 1.0) * d_stretched
+      + 2.0 * stretched.dot(&d_stretched) * stretched)
+    * direction.transpose()
+}
+
+/// Plastic strain after one step of plastic flow (as in O'Brien et al. 2002
+/// and Muller and Gross 2004). The rest shape of a tet is deformed by
+/// `I + plastic_strain`, `elastic_deformation_grad` is the deformation
+/// gradient with that removed. Once the deviatoric part of the elastic (Biot)
+/// strain exceeds `yield_strain`, `creep` (clamped to 1) is the fraction of
+/// the excess absorbed into the plastic strain, whose norm is limited to
+/// `max_plastic_strain`. Only the deviatoric part flows, so the volume of the
+/// rest shape is kept.
+pub fn flow_plastic_strain(
+  elastic_deformation_grad: &Matrix3<S>,
+  plastic_strain: &Matrix3<S>,
+  yield_strain: S,
+  creep: S,
+  max_plastic_strain: S,
+) -> Matrix3<S> {
+  let (_, stretch) = rotation_stretch(elastic_deformation_grad);
+  let strain = 0.5 * (stretch + stretch.transpose()) - Matrix3::identity();
+  let deviatoric = strain - Matrix3::identity() * (strain.trace() / 3.0);
+
+  let deviatoric_norm = deviatoric.norm();
+  if deviatoric_norm <= yield_strain {
+    return *plastic_strain;
+  }
+
+  let flowed = plastic_strain
+    + creep.min(1.0) * (1.0 - yield_strain / deviatoric_norm) * deviatoric;
+  let flowed_norm = flowed.norm();
+  if flowed_norm > max_plastic_strain {
+    flowed * (max_plastic_strain / flowed_norm)
+  } else {
+    flowed
+  }
+}
+
+#[cfg(test)]
+fn random_deformation_grad(seed: u64) -> Matrix3<S> {
+  // small deterministic generator, good enough to spread the entries
+  let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+  Matrix3::identity()
+    + Matrix3::from_fn(|_, _| {
+      state = state
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+      ((state >> 11) as S / (1u64 << 53) as S - 0.5) * 0.8
+    })
+}
+
+#[test]
+fn materials_agree_for_small_strains() {
+  let (lambda, mu) = (3.0, 2.0);
+  let strain = Matrix3::new(1.0, 0.5, 0.0, 0.5, -2.0, 0.3, 0.0, 0.3, 0.5);
+  let f = Matrix3::identity() + 1e-6 * strain;
+
+  let expected = Material::StVenantKirchhoff.stress(&f, lambda, mu);
+  assert!(expected.norm() > 1e-6);
+  for material in &[Material::NeoHookean, Material::Corotated] {
+    let stress = material.stress(&f, lambda, mu);
+    assert!(
+      (stress - expected).norm() < 1e-4 * expected.norm(),
+      "{:?}: {} vs {}",
+      material,
+      stress,
+      expected
+    );
+  }
+}
+
+#[test]
+fn stress_differentials_match_finite_difference() {
+  let epsilon = 1e-6;
+
+  for material in &[
+    Material::StVenantKirchhoff,
+    Material::NeoHookean,
+    Material::Corotated,
+  ] {
+    for seed in 0..20 {
+      let f = random_deformation_grad(seed);
+      let d_f = random_deformation_grad(seed + 100) - Matrix3::identity();
+
+      let d_stress = material.stress_differential(&f, &d_f, 3.0, 2.0);
+      let finite_difference = (material.stress(&(f + epsilon * d_f), 3.0, 2.0)
+        - material.stress(&(f - epsilon * d_f), 3.0, 2.0))
+        / (2.0 * epsilon);
+
+      assert!(
+        (d_stress - finite_difference).norm()
+          < 1e-5 * (1.0 + finite_difference.norm()),
+        "{:?} {}: {} vs {}",
+        material,
+        seed,
+        d_stress,
+        finite_difference
+      );
+    }
+  }
+}
+
+#[test]
+fn fiber_stress_acts_along_fibers() {
+  let direction = Vector3::new(1.0, 2.0, 2.0) / 3.0;
+  let epsilon = 1e-6;
+
+  for seed in 0..20 {
+    let f = random_deformation_grad(seed);
+    let d_f = random_deformation_grad(seed + 100) - Matrix3::identity();
+
+    let d_stress = fiber_stress_differential(&f, &d_f, &direction, 5.0);
+    let finite_difference =
+      (fiber_stress(&(f + epsilon * d_f), &direction, 5.0)
+        - fiber_stress(&(f - epsilon * d_f), &direction, 5.0))
+        / (2.0 * epsilon);
+
+    assert!(
+      (d_stress - finite_difference).norm()
+        < 1e-5 * (1.0 + finite_difference.norm()),
+      "{}: {} vs {}",
+      seed,
+      d_stress,
+      finite_difference
+    );
+  }
+
+  // small stretch along the fibers, the traction on a face across them is
+  // the stiffness times the strain
+  let strain = 1e-6;
+  let f = Matrix3::identity() + strain * direction * direction.transpose();
+  let traction = fiber_stress(&f, &direction, 5.0) * direction;
+  assert!((traction - 5.0 * strain * direction).norm() < 1e-10);
+
+  // stretching across the fibers doesn't load them
+  let across = Vector3::new(2.0, -1.0, 0.0).normalize();
+  let f = Matrix3::identity() + strain * across * across.transpose();
+  assert!(fiber_stress(&f, &direction, 5.0).norm() < 1e-15);
+}
+
+#[test]
+fn plastic_flow_absorbs_excess_strain() {
+  let elastic_deviatoric_norm =
+    |f: &Matrix3<S>, plastic_strain: &Matrix3<S>| {
+      let elastic = f
+        * (Matrix3::identity() + plastic_strain)
+          .try_inverse()
+          .unwrap();
+      let (_, stretch) = rotation_stretch(&elastic);
+      let strain = stretch - Matrix3::identity();
+
+      (strain - Matrix3::identity() * (strain.trace() / 3.0)).norm()
+    };
+
+  // stretched along x while keeping the volume
+  let stretch: S = 1.2;
+  let f = Matrix3::from_diagonal(&Vector3::new(
+    stretch,
+    stretch.sqrt().recip(),
+    stretch.sqrt().recip(),
+  ));
+  let zeros = Matrix3::zeros();
+  assert_eq!(flow_plastic_strain(&f, &zeros, 0.5, 1.0, 0.5), zeros);
+
+  let plastic_strain = flow_plastic_strain(&f, &zeros, 0.05, 1.0, 0.5);
+  assert!(plastic_strain[(0, 0)] > 0.0);
+  assert!(plastic_strain.trace().abs() < 1e-12);
+  assert!((plastic_strain - plastic_strain.transpose()).norm() < 1e-12);
+  // back to about the yield strain
+  assert!(elastic_deviatoric_norm(&f, &zeros) > 0.2);
+  assert!(
+    (elastic_deviatoric_norm(&f, &plastic_strain) - 0.05).abs() < 0.02,
+    "{}",
+    elastic_deviatoric_norm(&f, &plastic_strain)
+  );
+
+  // half of the excess at half the creep
+  let half = flow_plastic_strain(&f, &zeros, 0.05, 0.5, 0.5);
+  assert!((half * 2.0 - plastic_strain).norm() < 1e-12);
+  // limited to the max plastic strain
+  let limited = flow_plastic_strain(&f, &zeros, 0.05, 1.0, 0.01);
+  assert!((limited.norm() - 0.01).abs() < 1e-12);
+  // the same for rotated deformations
+  let rotation = nalgebra::Rotation3::new(Vector3::new(0.3, -0.2, 0.5));
+  assert!(
+    (flow_plastic_strain(&(rotation * f), &zeros, 0.05, 1.0, 0.5)
+      - plastic_strain)
+      .norm()
+      < 1e-9
+  );
+
+  // compression alone doesn't flow
+  let f = Matrix3::identity() * 0.5;
+  assert_eq!(flow_plastic_strain(&f, &zeros, 0.01, 1.0, 0.5), zeros);
+}
+
+#[test]
+fn polar_decomposition_is_accurate() {
+  // nearly repeated singular values, where the svd loses accuracy
+  let nearly_identity =
+    Matrix3::new(0.99999, 2.4e-5, 0.0, 0.0, 0.999, 0.0, 0.0, 2.4e-5, 0.99999);
+  let inverted = Matrix3::from_diagonal(&Vector3::new(1.0, 0.9, -0.2))
+    * random_deformation_grad(3);
+  let flat = Matrix3::from_diagonal(&Vector3::new(1.0, 2.0, 0.0));
+
+  for f in &[nearly_identity, inverted, flat, random_deformation_grad(7)] {
+    let (rotation, stretch) = rotation_stretch(f);
+
+    assert!((rotation * stretch - f).norm() < 1e-12, "{}", f);
+    assert!(
+      (rotation.transpose() * rotation - Matrix3::identity()).norm() < 1e-12
+    );
+    assert!((rotation.determinant() - 1.0).abs() < 1e-12);
+    assert!(
+      (stretch - stretch.transpose()).norm() < 1e-12,
+      "{}",
+      stretch
+    );
+  }
+}
+
+#[test]
+fn neo_hookean_resists_inversion() {
+  // flattened then inverted along z
+  for scale in &[0.5, 0.1, -0.2] {
+    let f = Matrix3::from_diagonal(&Vector3::new(1.0, 1.0, *scale));
+
+    for material in &[Material::NeoHookean, Material::Corotated] {
+      // pushes back out along z
+      assert!(
+        material.stress(&f, 1.0, 1.0)[(2, 2)] < 0.0,
+        "{:?}",
+        material
+      );
+    }
+  }
+
+  // St. Venant-Kirchhoff stops pushing back once flat enough
+  let f = Matrix3::from_diagonal(&Vector3::new(1.0, 1.0, 0.0));
+  assert_eq!(
+    Material::StVenantKirchhoff.stress(&f, 1.0, 1.0)[(2, 2)],
+    0.0
+  );
+}
+
+#[test]
+fn parses_material_names() {
+  assert_eq!("StVK".parse(), Ok(Material::StVenantKirchhoff));
+  assert_eq!("neo-hookean".parse(), Ok(Material::NeoHookean));
+  assert!("linear".parse::<Material>().is_err());
+}
@@ -1,12 +1,22 @@
 pub type S = f64;
 
 pub mod base;
+pub mod material;
+pub mod moduli;
+pub mod scene_file;
 pub mod scene_model;
+pub mod scene_simulation;
 pub mod sim_mesh;
 
 pub use base::{
-  GlobalParams, IntegrationParams, SimulatedScene, SimulatedSceneGenerator,
-  StepParams,
+  GlobalParams, IntegrationParams, OutputParams, SimulatedScene,
+  SimulatedSceneGenerator, StepParams, SurfaceOutput,
 };
+pub use material::Material;
+pub use moduli::{ElasticModuli, InvalidParam, MaterialPreset};
+pub use scene_file::{SceneDescription, SceneFileError};
 pub use scene_model::{SceneModel, SceneModelParams, SceneModelState};
-pub use sim_mesh::{MeshParams, SimMesh};
+pub use scene_simulation::SceneSimulation;
+pub use sim_mesh::{
+  MeshParams, PartialMeshParams, SimMesh, DEFAULT_PARALLEL_THRESHOLD,
+};
@@ -0,0 +1,328 @@
+use crate::simulated_scene::{MeshParams, PartialMeshParams, S};
+use serde::Deserialize;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Frequency in Hz a damping ratio applies at when none is given.
+pub const DEFAULT_DAMPING_FREQUENCY: S = 1.0;
+
+/// Common materials in SI units (Pa and kg/m^3). Damping proportional to the
+/// stiffness makes them stiff for the explicit integrators, so they are best
+/// used with implicit Euler.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaterialPreset {
+  /// soft rubber
+  Rubber,
+  /// gelatin like gel
+  Gel,
+  /// soft polyurethane foam
+  Foam,
+}
+
+impl MaterialPreset {
+  pub fn youngs_modulus(&self) -> S {
+    match self {
+      MaterialPreset::Rubber => 1.0e6,
+      MaterialPreset::Gel => 2.0e4,
+      MaterialPreset::Foam => 5.0e4,
+    }
+  }
+
+  pub fn poisson_ratio(&self) -> S {
+    match self {
+      MaterialPreset::Rubber => 0.45,
+      MaterialPreset::Gel => 0.45,
+      MaterialPreset::Foam => 0.3,
+    }
+  }
+
+  pub fn density(&self) -> S {
+    match self {
+      MaterialPreset::Rubber => 1100.0,
+      MaterialPreset::Gel => 1050.0,
+      MaterialPreset::Foam => 50.0,
+    }
+  }
+
+  /// at `DEFAULT_DAMPING_FREQUENCY`
+  pub fn damping_ratio(&self) -> S {
+    match self {
+      MaterialPreset::Rubber => 0.05,
+      MaterialPreset::Gel => 0.1,
+      MaterialPreset::Foam => 0.3,
+    }
+  }
+}
+
+impl FromStr for MaterialPreset {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "rubber" => Ok(MaterialPreset::Rubber),
+      "gel" => Ok(MaterialPreset::Gel),
+      "foam" => Ok(MaterialPreset::Foam),
+      _ => Err(format!(
+        "unknown preset '{}' (expected rubber, gel or foam)",
+        s
+      )),
+    }
+  }
+}
+
+/// A parameter outside of its valid range, `name` is the key in
+/// `ElasticModuli`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidParam {
+  pub name: &'static str,
+  pub message: &'static str,
+}
+
+impl fmt::Display for InvalidParam {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid {}: {}", self.name, self.message)
+  }
+}
+
+impl Error for InvalidParam {}
+
+fn check(
+  valid: bool,
+  name: &'static str,
+  message: &'static str,
+) -> Result<(), InvalidParam> {
+  if valid {
+    Ok(())
+  } else {
+    Err(InvalidParam { name, message })
+  }
+}
+
+impl MeshParams {
+  /// Default parameters with the Lame parameters for the given Young's
+  /// modulus and Poisson's ratio. The strain used by the materials is twice
+  /// the Green strain, so `incompressibility` and `rigidity` are half of the
+  /// usual lambda and mu.
+  pub fn from_youngs_modulus(
+    youngs_modulus: S,
+    poisson_ratio: S,
+  ) -> Result<Self, InvalidParam> {
+    let mut params = Self::default();
+    params.set_youngs_modulus(youngs_modulus, poisson_ratio)?;
+
+    Ok(params)
+  }
+
+  /// Moduli, density and damping of the preset.
+  pub fn from_preset(preset: MaterialPreset) -> Self {
+    let mut params = Self::default();
+    ElasticModuli {
+      preset: Some(preset),
+      ..ElasticModuli::default()
+    }
+    .apply(&mut params)
+    .expect("presets are valid");
+
+    params
+  }
+
+  pub fn set_youngs_modulus(
+    &mut self,
+    youngs_modulus: S,
+    poisson_ratio: S,
+  ) -> Result<(), InvalidParam> {
+    check(youngs_modulus > 0.0, "youngs_modulus", "must be positive")?;
+    check(
+      poisson_ratio > -1.0 && poisson_ratio < 0.5,
+      "poisson_ratio",
+      "must be greater than -1 and less than 0.5",
+    )?;
+
+    let lambda = youngs_modulus * poisson_ratio
+      / ((1.0 + poisson_ratio) * (1.0 - 2.0 * poisson_ratio));
+    let mu = youngs_modulus / (2.0 * (1.0 + poisson_ratio));
+    self.incompressibility = lambda / 2.0;
+    self.rigidity = mu / 2.0;
+
+    Ok(())
+  }
+
+  pub fn youngs_modulus(&self) -> S {
+    let (lambda, mu) = (2.0 * self.incompressibility, 2.0 * self.rigidity);
+
+    mu * (3.0 * lambda + 2.0 * mu) / (lambda + mu)
+  }
+
+  pub fn poisson_ratio(&self) -> S {
+    self.incompressibility / (2.0 * (self.incompressibility + self.rigidity))
+  }
+
+  /// Sets the viscous parameters proportional to the elastic ones, so a
+  /// vibration at `frequency` (Hz) has the given damping ratio. Higher
+  /// frequencies are damped more and lower frequencies less.
+  pub fn set_damping_ratio(
+    &mut self,
+    damping_ratio: S,
+    frequency: S,
+  ) -> Result<(), InvalidParam> {
+    check(damping_ratio >= 0.0, "damping_ratio", "can't be negative")?;
+    check(frequency > 0.0, "damping_frequency", "must be positive")?;
+
+    let time_scale = damping_ratio / (PI * frequency);
+    self.viscous_incompressibility = time_scale * self.incompressibility;
+    self.viscous_rigidity = time_scale * self.rigidity;
+
+    Ok(())
+  }
+}
+
+/// Young's modulus, Poisson's ratio and damping ratio as an alternative to
+/// the Lame parameters and viscosities of `MeshParams`. Unset moduli keep the
+/// values implied by the parameters they're applied to.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElasticModuli {
+  /// sets the density along with the moduli and damping, which the other
+  /// keys and the keys given with `apply_with` override
+  pub preset: Option<MaterialPreset>,
+  pub youngs_modulus: Option<S>,
+  pub poisson_ratio: Option<S>,
+  pub damping_ratio: Option<S>,
+  /// in Hz, `DEFAULT_DAMPING_FREQUENCY` if not given
+  pub damping_frequency: Option<S>,
+}
+
+impl ElasticModuli {
+  pub fn apply(&self, params: &mut MeshParams) -> Result<(), InvalidParam> {
+    self.apply_with(params, &PartialMeshParams::default())
+  }
+
+  /// Applies the preset, then `keys`, then the other moduli. The preset's
+  /// damping ratio is only used if `keys` has no viscosities.
+  pub fn apply_with(
+    &self,
+    params: &mut MeshParams,
+    keys: &PartialMeshParams,
+  ) -> Result<(), InvalidParam> {
+    let mut damping_ratio = self.damping_ratio;
+    if let Some(preset) = self.preset {
+      params.density = preset.density();
+      params
+        .set_youngs_modulus(preset.youngs_modulus(), preset.poisson_ratio())?;
+      if keys.viscous_incompressibility.is_none()
+        && keys.viscous_rigidity.is_none()
+      {
+        damping_ratio = damping_ratio.or_else(|| Some(preset.damping_ratio()));
+      }
+    }
+    keys.apply(params);
+
+    if self.youngs_modulus.is_some() || self.poisson_ratio.is_some() {
+      params.set_youngs_modulus(
+        self
+          .youngs_modulus
+          .unwrap_or_else(|| params.youngs_modulus()),
+        self.poisson_ratio.unwrap_or_else(|| params.poisson_ratio()),
+      )?;
+    }
+
+    match damping_ratio {
+      Some(damping_ratio) => params.set_damping_ratio(
+        damping_ratio,
+        self.damping_frequency.unwrap_or(DEFAULT_DAMPING_FREQUENCY),
+      ),
+      None if self.damping_frequency.is_some() => Err(InvalidParam {
+        name: "damping_frequency",
+        message: "needs a damping ratio",
+      }),
+      // the viscosities are left as they are
+      None => Ok(()),
+    }
+  }
+}
+
+#[test]
+fn converts_moduli() {
+  use crate::simulated_scene::Material;
+  use nalgebra::{Matrix3, Vector3};
+
+  let params = MeshParams::from_youngs_modulus(1000.0, 0.3).unwrap();
+  assert!((params.youngs_modulus() - 1000.0).abs() < 1e-9);
+  assert!((params.poisson_ratio() - 0.3).abs() < 1e-12);
+
+  // small uniaxial stretch, the sides contract freely
+  let strain = 1e-6;
+  let f = Matrix3::from_diagonal(&Vector3::new(
+    1.0 + strain,
+    1.0 - 0.3 * strain,
+    1.0 - 0.3 * strain,
+  ));
+  let stress = Material::StVenantKirchhoff.stress(
+    &f,
+    params.incompressibility,
+    params.rigidity,
+  );
+  assert!((stress[(0, 0)] - 1000.0 * strain).abs() < 1e-4 * 1000.0 * strain);
+  assert!(stress[(1, 1)].abs() < 1e-4 * 1000.0 * strain);
+
+  for (youngs_modulus, poisson_ratio, name) in &[
+    (0.0, 0.3, "youngs_modulus"),
+    (1.0, 0.5, "poisson_ratio"),
+    (1.0, -1.0, "poisson_ratio"),
+  ] {
+    assert_eq!(
+      MeshParams::from_youngs_modulus(*youngs_modulus, *poisson_ratio)
+        .unwrap_err()
+        .name,
+      *name
+    );
+  }
+}
+
+#[test]
+fn applies_presets_and_damping() {
+  let rubber = MeshParams::from_preset(MaterialPreset::Rubber);
+  assert_eq!(rubber.density, 1100.0);
+  assert!((rubber.youngs_modulus() - 1.0e6).abs() < 1e-6);
+  // damping ratio of 0.05 at 1 Hz
+  assert!(
+    (rubber.viscous_rigidity / rubber.rigidity - 0.05 / PI).abs() < 1e-12
+  );
+
+  // keys override the preset, unset moduli keep their values
+  let mut params = MeshParams::default();
+  let poisson_ratio = params.poisson_ratio();
+  ElasticModuli {
+    youngs_modulus: Some(500.0),
+    damping_ratio: Some(0.2),
+    damping_frequency: Some(10.0),
+    ..ElasticModuli::default()
+  }
+  .apply(&mut params)
+  .unwrap();
+  assert!((params.youngs_modulus() - 500.0).abs() < 1e-9);
+  assert!((params.poisson_ratio() - poisson_ratio).abs() < 1e-12);
+  assert!(
+    (params.viscous_incompressibility / params.incompressibility
+      - 0.2 / (10.0 * PI))
+      .abs()
+      < 1e-12
+  );
+
+  let mut params = MeshParams::default();
+  assert!(ElasticModuli::default().apply(&mut params).is_ok());
+  assert_eq!(params, MeshParams::default());
+  let error = ElasticModuli {
+    damping_frequency: Some(2.0),
+    ..ElasticModuli::default()
+  }
+  .apply(&mut params)
+  .unwrap_err();
+  assert_eq!(error.name, "damping_frequency");
+
+  assert_eq!("Gel".parse(), Ok(MaterialPreset::Gel));
+  assert!("steel".parse::<MaterialPreset>().is_err());
+}
@@ -0,0 +1,1278 @@
+use crate::{
+  mesh::{
+    load_mesh_with_regions, load_surface_as_mesh, load_vtk, LoadedMesh,
+    MeshError, MeshShape, VertexOrdering, VtkMesh,
+  },
+  ode::{IntegratorType, RK4Settings},
+  simulated_scene::{
+    ElasticModuli, GlobalParams, IntegrationParams, InvalidParam, MeshParams,
+    OutputParams, PartialMeshParams, SceneModelParams, SimMesh,
+    SimulatedSceneGenerator, StepParams, SurfaceOutput, S,
+  },
+  surface_export::SurfaceFormat,
+  CameraInfo,
+};
+use nalgebra::{Point3, Rotation3, Transform3, Translation3, Vector3};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+  Io(PathBuf, io::Error),
+  /// toml errors name the offending key and line
+  Parse(PathBuf, toml::de::Error),
+  Invalid {
+    key: String,
+    message: String,
+  },
+  Mesh {
+    key: String,
+    /// none for generated meshes
+    path: Option<PathBuf>,
+    error: MeshError,
+  },
+}
+
+impl fmt::Display for SceneFileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SceneFileError::Io(path, error) => {
+        write!(f, "couldn't read scene file {}: {}", path.display(), error)
+      }
+      SceneFileError::Parse(path, error) => {
+        write!(f, "invalid scene file {}: {}", path.display(), error)
+      }
+      SceneFileError::Invalid { key, message } => {
+        write!(f, "invalid value for key `{}`: {}", key, message)
+      }
+      SceneFileError::Mesh {
+        key,
+        path: Some(path),
+        error,
+      } => write!(
+        f,
+        "couldn't load mesh {} (key `{}`): {}",
+        path.display(),
+        key,
+        error
+      ),
+      SceneFileError::Mesh {
+        key,
+        path: None,
+        error,
+      } => write!(f, "couldn't create mesh (key `{}`): {}", key, error),
+    }
+  }
+}
+
+impl Error for SceneFileError {}
+
+fn invalid(key: String, message: &str) -> SceneFileError {
+  SceneFileError::Invalid {
+    key,
+    message: message.to_owned(),
+  }
+}
+
+/// Parameters for part of a mesh. Tets are selected by the region tag from
+/// the mesh file and by the position of their centroid in mesh coordinates
+/// (before the translation, rotation and scale), tets have to match every
+/// given key. Tets matching several regions use the last one and tets
+/// matching none use the parameters of the mesh.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegionDescription {
+  /// region attribute of TetGen meshes or physical group of Gmsh meshes
+  pub tag: Option<i32>,
+  /// bounds of the centroid, `inf` and `-inf` leave an axis unbounded
+  pub min: Option<[f32; 3]>,
+  pub max: Option<[f32; 3]>,
+  /// the centroid must be within `radius` of `center`
+  pub center: Option<[f32; 3]>,
+  pub radius: Option<f32>,
+  /// override the parameters of the mesh, keys which aren't given keep the
+  /// mesh's values
+  pub params: PartialMeshParams,
+  /// applied like the moduli of the mesh, over the mesh's parameters
+  pub moduli: ElasticModuli,
+}
+
+impl RegionDescription {
+  pub fn region_params(
+    &self,
+    mesh_params: &MeshParams,
+  ) -> Result<MeshParams, InvalidParam> {
+    let mut params = mesh_params.clone();
+    self.moduli.apply_with(&mut params, &self.params)?;
+
+    Ok(params)
+  }
+
+  pub fn contains(&self, centroid: &Point3<f32>, tag: Option<i32>) -> bool {
+    let above_min = self
+      .min
+      .iter()
+      .all(|min| (0..3).all(|axis| centroid[axis] >= min[axis]));
+    let below_max = self
+      .max
+      .iter()
+      .all(|max| (0..3).all(|axis| centroid[axis] <= max[axis]));
+    let in_sphere = match (self.center, self.radius) {
+      (Some(center), Some(radius)) => {
+        (centroid - Point3::from(center)).norm() <= radius
+      }
+      _ => true,
+    };
+    let has_tag = match self.tag {
+      Some(region_tag) => tag == Some(region_tag),
+      None => true,
+    };
+
+    above_min && below_max && in_sphere && has_tag
+  }
+}
+
+/// Fiber direction of each tet for `MeshParams::fiber_stiffness`. Directions
+/// are given in mesh coordinates and rotated along with the mesh.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "field", rename_all = "lowercase", deny_unknown_fields)]
+pub enum FiberField {
+  /// the same direction everywhere
+  Constant { direction: [f32; 3] },
+  /// around `axis` through `origin`, like the fibers wound around a tube
+  Circumferential {
+    axis: [f32; 3],
+    #[serde(default)]
+    origin: [f32; 3],
+  },
+  /// away from `origin`
+  Radial {
+    #[serde(default)]
+    origin: [f32; 3],
+  },
+  /// the `VECTORS` of that name in the cell data of a legacy VTK mesh
+  File {
+    #[serde(default = "default_fiber_name")]
+    name: String,
+  },
+}
+
+fn default_fiber_name() -> String {
+  "fiber".to_owned()
+}
+
+impl FiberField {
+  /// Direction at a point in mesh coordinates, which isn't normalized and is
+  /// zero where the field is undefined. None for `File`, whose directions come
+  /// with the mesh.
+  pub fn direction(&self, point: &Point3<f32>) -> Option<Vector3<f32>> {
+    match self {
+      FiberField::Constant { direction } => Some(Vector3::from(*direction)),
+      FiberField::Circumferential { axis, origin } => {
+        Some(Vector3::from(*axis).cross(&(point - Point3::from(*origin))))
+      }
+      FiberField::Radial { origin } => Some(point - Point3::from(*origin)),
+      FiberField::File { .. } => None,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeshDescription {
+  /// relative paths are relative to the scene file
+  pub path: Option<PathBuf>,
+  /// generated instead of loaded from a path
+  pub generate: Option<MeshShape>,
+  /// cells along the longest side when filling `.obj`/`.stl` surfaces
+  pub surface_resolution: Option<usize>,
+  #[serde(default)]
+  pub translation: [f32; 3],
+  /// axis scaled by the angle in radians
+  #[serde(default)]
+  pub rotation: [f32; 3],
+  #[serde(default = "default_scale")]
+  pub scale: f32,
+  /// renumbering of the vertices for cache locality, original if not given
+  pub ordering: Option<VertexOrdering>,
+  /// keys which aren't given keep the defaults
+  #[serde(default)]
+  pub params: PartialMeshParams,
+  /// the preset is applied before `params` and the other moduli after
+  #[serde(default)]
+  pub moduli: ElasticModuli,
+  #[serde(default)]
+  pub regions: Vec<RegionDescription>,
+  /// only has an effect with a nonzero `fiber_stiffness`
+  pub fibers: Option<FiberField>,
+}
+
+fn default_scale() -> f32 {
+  1.0
+}
+
+impl MeshDescription {
+  pub fn new(path: PathBuf) -> Self {
+    Self {
+      path: Some(path),
+      ..Self::generated(None)
+    }
+  }
+
+  fn generated(generate: Option<MeshShape>) -> Self {
+    Self {
+      path: None,
+      generate,
+      surface_resolution: None,
+      translation: [0.0; 3],
+      rotation: [0.0; 3],
+      scale: default_scale(),
+      ordering: None,
+      params: PartialMeshParams::default(),
+      moduli: ElasticModuli::default(),
+      regions: Vec::new(),
+      fibers: None,
+    }
+  }
+
+  /// `params` with the moduli applied.
+  pub fn mesh_params(&self) -> Result<MeshParams, InvalidParam> {
+    let mut params = MeshParams::default();
+    self.moduli.apply_with(&mut params, &self.params)?;
+
+    Ok(params)
+  }
+
+  // the parameters of the mesh followed by those of each region, errors name
+  // the key
+  fn param_sets(
+    &self,
+    mesh_idx: usize,
+  ) -> Result<Vec<MeshParams>, SceneFileError> {
+    let invalid_moduli = |prefix: String, error: InvalidParam| {
+      invalid(
+        format!("meshes[{}].{}moduli.{}", mesh_idx, prefix, error.name),
+        error.message,
+      )
+    };
+
+    let mesh_params = self
+      .mesh_params()
+      .map_err(|error| invalid_moduli(String::new(), error))?;
+    let mut param_sets = vec![mesh_params];
+    for (region_idx, region) in self.regions.iter().enumerate() {
+      let params = region.region_params(&param_sets[0]).map_err(|error| {
+        invalid_moduli(format!("regions[{}].", region_idx), error)
+      })?;
+      param_sets.push(params);
+    }
+
+    Ok(param_sets)
+  }
+
+  // centroids of the tets of the loaded mesh in mesh coordinates
+  fn mesh_space_centroids(
+    &self,
+    (vertices, tetras): &LoadedMesh,
+  ) -> Vec<Point3<f32>> {
+    let to_mesh_space = self
+      .transform()
+      .try_inverse()
+      .unwrap_or_else(Transform3::identity);
+
+    tetras
+      .iter()
+      .map(|tetra| {
+        let centroid = tetra
+          .iter()
+          .map(|idx| vertices[*idx as usize])
+          .sum::<Vector3<f32>>()
+          / 4.0;
+
+        to_mesh_space.transform_point(&Point3::from(centroid))
+      })
+      .collect()
+  }
+
+  /// Index of the parameters of each tet of the loaded (and transformed)
+  /// mesh, 0 for the mesh's own and `i + 1` for region `i`. `tags` are the
+  /// region tags of the tets if the mesh file has them.
+  pub fn tetra_param_idxs(
+    &self,
+    loaded: &LoadedMesh,
+    tags: Option<&[i32]>,
+  ) -> Vec<u32> {
+    self
+      .mesh_space_centroids(loaded)
+      .iter()
+      .enumerate()
+      .map(|(tetra_idx, centroid)| {
+        let tag = tags.map(|tags| tags[tetra_idx]);
+
+        self
+          .regions
+          .iter()
+          .rposition(|region| region.contains(centroid, tag))
+          .map_or(0, |region_idx| region_idx as u32 + 1)
+      })
+      .collect()
+  }
+
+  /// Fiber direction of each tet of the loaded (and transformed) mesh,
+  /// evaluated at the centroids. None without fibers or for `File` fibers.
+  pub fn fiber_directions(
+    &self,
+    loaded: &LoadedMesh,
+  ) -> Option<Vec<Vector3<f32>>> {
+    let fibers = self.fibers.as_ref()?;
+    let transform = self.transform();
+
+    self
+      .mesh_space_centroids(loaded)
+      .iter()
+      .map(|centroid| {
+        fibers
+          .direction(centroid)
+          .map(|direction| transform.transform_vector(&direction))
+      })
+      .collect()
+  }
+
+  pub fn transform(&self) -> Transform3<f32> {
+    Transform3::from_matrix_unchecked(
+      Translation3::from(Vector3::from(self.translation)).to_homogeneous()
+        * Rotation3::new(Vector3::from(self.rotation)).to_homogeneous()
+        * nalgebra::Matrix4::new_scaling(self.scale),
+    )
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FloorDescription {
+  pub height: S,
+  pub friction_coeff: S,
+}
+
+impl Default for FloorDescription {
+  fn default() -> Self {
+    Self {
+      height: -3.0,
+      friction_coeff: 0.1,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SphereDescription {
+  pub radius: S,
+  pub pos: [S; 3],
+}
+
+impl Default for SphereDescription {
+  fn default() -> Self {
+    Self {
+      radius: 1.0,
+      pos: [0.0, -3.5, 0.0],
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollidersDescription {
+  pub penalty_force: S,
+  pub floor: FloorDescription,
+  pub sphere: SphereDescription,
+}
+
+impl Default for CollidersDescription {
+  fn default() -> Self {
+    Self {
+      penalty_force: 10000.0,
+      floor: FloorDescription::default(),
+      sphere: SphereDescription::default(),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDescription {
+  pub eye: [f32; 3],
+  pub at: [f32; 3],
+}
+
+impl Default for CameraDescription {
+  fn default() -> Self {
+    Self {
+      eye: [5.0, 0.0, 5.0],
+      at: [0.0, -3.0, 0.0],
+    }
+  }
+}
+
+/// relative directories are relative to the scene file
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputDescription {
+  pub state_dir: Option<PathBuf>,
+  pub surface_dir: Option<PathBuf>,
+  /// obj if not given
+  pub surface_format: Option<SurfaceFormat>,
+  pub surface_normals: bool,
+  pub vtk_dir: Option<PathBuf>,
+}
+
+impl OutputDescription {
+  pub fn output_params(&self) -> OutputParams {
+    OutputParams {
+      state_dir: self.state_dir.clone(),
+      surface: self.surface_dir.clone().map(|dir| SurfaceOutput {
+        dir,
+        format: self.surface_format.unwrap_or(SurfaceFormat::Obj),
+        normals: self.surface_normals,
+      }),
+      vtk_dir: self.vtk_dir.clone(),
+    }
+  }
+}
+
+/// Everything needed to build a `SimulatedSceneGenerator`, usually loaded
+/// from a toml scene file. Missing keys take the same defaults as the
+/// command line.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SceneDescription {
+  pub meshes: Vec<MeshDescription>,
+  pub gravity: S,
+  pub time_step: S,
+  pub speed_up: S,
+  pub integrator: IntegratorType,
+  /// tets a mesh needs before its forces are computed in parallel, see
+  /// `SimMesh::set_parallel_threshold`
+  pub parallel_threshold: Option<usize>,
+  pub colliders: CollidersDescription,
+  pub camera: CameraDescription,
+  pub output: OutputDescription,
+}
+
+impl Default for SceneDescription {
+  fn default() -> Self {
+    Self {
+      meshes: Vec::new(),
+      gravity: 9.8,
+      time_step: 0.0025,
+      speed_up: 1.0,
+      integrator: IntegratorType::RK4(RK4Settings {}),
+      parallel_threshold: None,
+      colliders: CollidersDescription::default(),
+      camera: CameraDescription::default(),
+      output: OutputDescription::default(),
+    }
+  }
+}
+
+impl SceneDescription {
+  pub fn from_toml(
+    contents: &str,
+    path: &Path,
+  ) -> Result<Self, SceneFileError> {
+    toml::from_str(contents)
+      .map_err(|error| SceneFileError::Parse(path.to_owned(), error))
+  }
+
+  /// Loads the scene, resolving relative paths. It isn't validated so
+  /// command line overrides can still fix values, see `validate`.
+  pub fn load(path: &Path) -> Result<Self, SceneFileError> {
+    let contents = fs::read_to_string(path)
+      .map_err(|error| SceneFileError::Io(path.to_owned(), error))?;
+
+    let mut scene = Self::from_toml(&contents, path)?;
+
+    if let Some(dir) = path.parent() {
+      let resolve = |p: &mut PathBuf| *p = dir.join(&*p);
+
+      for mesh in &mut scene.meshes {
+        if let Some(path) = &mut mesh.path {
+          resolve(path);
+        }
+      }
+      let output = &mut scene.output;
+      for output_dir in &mut [
+        &mut output.state_dir,
+        &mut output.surface_dir,
+        &mut output.vtk_dir,
+      ] {
+        if let Some(output_dir) = &mut **output_dir {
+          resolve(output_dir);
+        }
+      }
+    }
+
+    Ok(scene)
+  }
+
+  /// Checks the values of the scene, after any overrides are applied.
+  pub fn validate(&self) -> Result<(), SceneFileError> {
+    if self.meshes.is_empty() {
+      return Err(invalid("meshes".to_owned(), "at least one mesh is needed"));
+    }
+
+    for (key, value) in
+      &[("time_step", self.time_step), ("speed_up", self.speed_up)]
+    {
+      if *value <= 0.0 {
+        return Err(invalid(key.to_string(), "must be positive"));
+      }
+    }
+
+    if let IntegratorType::RK45(settings) = &self.integrator {
+      if settings.min_step <= 0.0 {
+        return Err(invalid(
+          "integrator.min_step".to_owned(),
+          "must be positive",
+        ));
+      }
+    }
+
+    if self.colliders.sphere.radius < 0.0 {
+      return Err(invalid(
+        "colliders.sphere.radius".to_owned(),
+        "can't be negative",
+      ));
+    }
+
+    for (mesh_idx, mesh) in self.meshes.iter().enumerate() {
+      let key = |name: &str| format!("meshes[{}].{}", mesh_idx, name);
+
+      match (&mesh.path, &mesh.generate) {
+        (Some(_), None) => {}
+        (None, Some(shape)) => {
+          for (name, size) in shape.sizes() {
+            if size <= 0.0 {
+              return Err(invalid(
+                key(&format!("generate.{}", name)),
+                "must be positive",
+              ));
+            }
+          }
+          for (name, resolution) in shape.resolutions() {
+            if resolution == 0 {
+              return Err(invalid(
+                key(&format!("generate.{}", name)),
+                "must be at least 1",
+              ));
+            }
+          }
+        }
+        _ => {
+          return Err(invalid(
+            key("path"),
+            "exactly one of path and generate is needed",
+          ))
+        }
+      }
+      if let Some(resolution) = mesh.surface_resolution {
+        let extension = mesh
+          .path
+          .as_ref()
+          .and_then(|path| path.extension())
+          .and_then(|ext| ext.to_str());
+        if !matches!(extension, Some("obj") | Some("stl")) {
+          return Err(invalid(
+            key("surface_resolution"),
+            "only applies to .obj and .stl paths",
+          ));
+        }
+        if resolution == 0 {
+          return Err(invalid(key("surface_resolution"), "must be at least 1"));
+        }
+      }
+      if mesh.scale <= 0.0 {
+        return Err(invalid(key("scale"), "must be positive"));
+      }
+      let has_tags = matches!(
+        mesh
+          .path
+          .as_ref()
+          .and_then(|path| path.extension())
+          .and_then(|ext| ext.to_str()),
+        Some("node") | Some("ele") | Some("msh")
+      ) && mesh.surface_resolution.is_none();
+      for (region_idx, region) in mesh.regions.iter().enumerate() {
+        let key =
+          |name: &str| key(&format!("regions[{}].{}", region_idx, name));
+
+        if region.tag.is_some() && !has_tags {
+          return Err(invalid(
+            key("tag"),
+            "only applies to TetGen (.node/.ele) and Gmsh (.msh) paths",
+          ));
+        }
+        match (region.center, region.radius) {
+          (Some(_), None) => {
+            return Err(invalid(key("radius"), "is needed with center"))
+          }
+          (None, Some(_)) => {
+            return Err(invalid(key("center"), "is needed with radius"))
+          }
+          (_, Some(radius)) if radius < 0.0 => {
+            return Err(invalid(key("radius"), "can't be negative"))
+          }
+          _ => {}
+        }
+      }
+
+      let is_vtk = matches!(
+        mesh
+          .path
+          .as_ref()
+          .and_then(|path| path.extension())
+          .and_then(|ext| ext.to_str()),
+        Some("vtk")
+      );
+      match &mesh.fibers {
+        Some(FiberField::Constant { direction }) if direction == &[0.0; 3] => {
+          return Err(invalid(key("fibers.direction"), "can't be zero"))
+        }
+        Some(FiberField::Circumferential { axis, .. }) if axis == &[0.0; 3] => {
+          return Err(invalid(key("fibers.axis"), "can't be zero"))
+        }
+        Some(FiberField::File { .. }) if !is_vtk => {
+          return Err(invalid(
+            key("fibers"),
+            "fibers from a file need a legacy VTK (.vtk) path",
+          ))
+        }
+        _ => {}
+      }
+
+      for (param_idx, params) in mesh.param_sets(mesh_idx)?.iter().enumerate() {
+        let prefix = match param_idx {
+          0 => "params".to_owned(),
+          _ => format!("regions[{}].params", param_idx - 1),
+        };
+
+        for (name, valid, message) in &[
+          ("density", params.density > 0.0, "must be positive"),
+          (
+            "mass_damping",
+            params.mass_damping >= 0.0,
+            "can't be negative",
+          ),
+          (
+            "stiffness_damping",
+            params.stiffness_damping >= 0.0,
+            "can't be negative",
+          ),
+          ("air_drag", params.air_drag >= 0.0, "can't be negative"),
+          (
+            "yield_strain",
+            params.yield_strain >= 0.0,
+            "can't be negative",
+          ),
+          ("creep_rate", params.creep_rate >= 0.0, "can't be negative"),
+          (
+            "max_plastic_strain",
+            params.max_plastic_strain >= 0.0 && params.max_plastic_strain < 1.0,
+            "must be at least 0 and less than 1",
+          ),
+          (
+            "toughness",
+            params.toughness.iter().all(|toughness| *toughness > 0.0),
+            "must be positive",
+          ),
+        ] {
+          if !valid {
+            return Err(invalid(key(&format!("{}.{}", prefix, name)), message));
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn into_generator(
+    self,
+  ) -> Result<SimulatedSceneGenerator, SceneFileError> {
+    let meshs = self
+      .meshes
+      .iter()
+      .enumerate()
+      .map(|(mesh_idx, mesh)| {
+        let transform = mesh.transform();
+        let param_sets = mesh.param_sets(mesh_idx)?;
+        // along with the region tags and the cell vectors of VTK meshes
+        let (loaded, key) = match (&mesh.path, &mesh.generate) {
+          (_, Some(shape)) => (
+            Ok((shape.generate(Some(&transform)), None, None)),
+            "generate",
+          ),
+          (Some(path), None) => match (mesh.surface_resolution, &mesh.fibers) {
+            (Some(resolution), _) => (
+              load_surface_as_mesh(path, resolution, Some(&transform))
+                .map(|loaded| (loaded, None, None)),
+              "path",
+            ),
+            (None, Some(FiberField::File { .. })) => (
+              load_vtk(path, Some(&transform)).map(
+                |VtkMesh {
+                   vertices,
+                   tetras,
+                   cell_vectors,
+                 }| {
+                  ((vertices, tetras), None, Some(cell_vectors))
+                },
+              ),
+              "path",
+            ),
+            (None, _) => (
+              load_mesh_with_regions(path, Some(&transform))
+                .map(|(loaded, tags)| (loaded, tags, None)),
+              "path",
+            ),
+          },
+          (None, None) => unreachable!("scene wasn't validated"),
+        };
+        let mesh_error = |error| SceneFileError::Mesh {
+          key: format!("meshes[{}].{}", mesh_idx, key),
+          path: mesh.path.clone(),
+          error,
+        };
+
+        let (loaded, tags, cell_vectors) = loaded.map_err(mesh_error)?;
+        let fiber_directions = match (&mesh.fibers, cell_vectors) {
+          (Some(FiberField::File { name }), Some(cell_vectors)) => Some(
+            cell_vectors
+              .into_iter()
+              .find(|(vectors_name, _)| vectors_name == name)
+              .map(|(_, vectors)| vectors)
+              .ok_or_else(|| {
+                invalid(
+                  format!("meshes[{}].fibers.name", mesh_idx),
+                  &format!("the mesh has no cell vectors named {}", name),
+                )
+              })?,
+          ),
+          _ => mesh.fiber_directions(&loaded),
+        };
+        let tetra_param_idxs = mesh.tetra_param_idxs(&loaded, tags.as_deref());
+
+        let mut sim_mesh = SimMesh::with_tetra_params(
+          loaded,
+          param_sets,
+          tetra_param_idxs,
+          mesh.ordering.unwrap_or(VertexOrdering::Original),
+        )
+        .map_err(mesh_error)?;
+        sim_mesh.set_parallel_threshold(self.parallel_threshold);
+        if let Some(fiber_directions) = fiber_directions {
+          let fiber_directions: Vec<Vector3<S>> = fiber_directions
+            .iter()
+            .map(|direction| nalgebra::convert(*direction))
+            .collect();
+          sim_mesh.set_fiber_directions(&fiber_directions);
+        }
+
+        Ok(sim_mesh)
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let colliders = &self.colliders;
+
+    Ok(SimulatedSceneGenerator::new(
+      CameraInfo {
+        eye: Point3::from(self.camera.eye),
+        at: Point3::from(self.camera.at),
+      },
+      GlobalParams {
+        scene_model_params: SceneModelParams { g: self.gravity },
+        integration_params: IntegrationParams {
+          step_params: StepParams {
+            time_step: self.time_step,
+            speed_up: self.speed_up,
+          },
+          integrator_type: self.integrator.clone(),
+        },
+        output_params: self.output.output_params(),
+      },
+      meshs,
+      colliders.penalty_force,
+      colliders.floor.friction_coeff,
+      colliders.floor.height,
+      colliders.sphere.radius,
+      Vector3::from(colliders.sphere.pos),
+    ))
+  }
+}
+
+#[test]
+fn parses_with_defaults() {
+  use crate::simulated_scene::{Material, MaterialPreset};
+
+  let scene = SceneDescription::from_toml(
+    r#"
+      gravity = 5.0
+      parallel_threshold = 5000
+
+      [integrator]
+      type = "rk45"
+      rel_tol = 1e-4
+
+      [[meshes]]
+      path = "a.mesh"
+      translation = [0.0, 1.0, 0.0]
+
+      [[meshes]]
+      path = "b.mesh"
+      ordering = "rcm"
+      [meshes.params]
+      rigidity = 500.0
+      material = "neo-hookean"
+
+      [meshes.moduli]
+      preset = "gel"
+      poisson_ratio = 0.4
+
+      [[meshes]]
+      generate = { shape = "sphere", radius = 0.5, resolution = 4 }
+    "#,
+    Path::new("scene.toml"),
+  )
+  .unwrap();
+  scene.validate().unwrap();
+
+  assert_eq!(scene.gravity, 5.0);
+  assert_eq!(scene.parallel_threshold, Some(5000));
+  assert_eq!(scene.time_step, SceneDescription::default().time_step);
+  assert_eq!(scene.meshes.len(), 3);
+  assert_eq!(
+    scene.meshes[2].generate,
+    Some(MeshShape::Sphere {
+      radius: 0.5,
+      resolution: 4
+    })
+  );
+  assert_eq!(scene.meshes[0].params, PartialMeshParams::default());
+  assert_eq!(scene.meshes[1].params.rigidity, Some(500.0));
+  assert_eq!(scene.meshes[1].params.material, Some(Material::NeoHookean));
+  assert_eq!(scene.meshes[1].moduli.preset, Some(MaterialPreset::Gel));
+  let params = scene.meshes[1].mesh_params().unwrap();
+  assert_eq!(params.density, MaterialPreset::Gel.density());
+  assert!((params.poisson_ratio() - 0.4).abs() < 1e-12);
+  assert_eq!(params.material, Material::NeoHookean);
+  assert_eq!(scene.meshes[0].ordering, None);
+  assert_eq!(scene.meshes[1].ordering, Some(VertexOrdering::Rcm));
+  match scene.integrator {
+    IntegratorType::RK45(settings) => {
+      assert_eq!(settings.rel_tol, 1e-4);
+      assert_eq!(settings.abs_tol, 1e-6);
+    }
+    _ => panic!("expected rk45"),
+  }
+}
+
+#[test]
+fn params_override_the_preset() {
+  use crate::simulated_scene::MaterialPreset;
+
+  let scene = SceneDescription::from_toml(
+    r#"
+      [[meshes]]
+      path = "a.mesh"
+      params = { density = 2.0, rigidity = 300.0 }
+      moduli = { preset = "rubber" }
+
+      [[meshes]]
+      path = "a.mesh"
+      params = { viscous_rigidity = 1.0 }
+      moduli = { preset = "rubber" }
+    "#,
+    Path::new("scene.toml"),
+  )
+  .unwrap();
+  let rubber = MeshParams::from_preset(MaterialPreset::Rubber);
+
+  let params = scene.meshes[0].mesh_params().unwrap();
+  assert_eq!(params.density, 2.0);
+  assert_eq!(params.rigidity, 300.0);
+  assert_eq!(params.incompressibility, rubber.incompressibility);
+  // the preset's damping ratio applies to the given rigidity
+  assert!(
+    (params.viscous_rigidity / params.rigidity
+      - rubber.viscous_rigidity / rubber.rigidity)
+      .abs()
+      < 1e-12
+  );
+
+  // given viscosities replace the preset's damping
+  let params = scene.meshes[1].mesh_params().unwrap();
+  assert_eq!(params.density, rubber.density);
+  assert_eq!(params.viscous_rigidity, 1.0);
+  assert_eq!(
+    params.viscous_incompressibility,
+    MeshParams::default().viscous_incompressibility
+  );
+}
+
+#[test]
+fn validates_moduli() {
+  let mut mesh = MeshDescription::new("a.mesh".into());
+  mesh.moduli.poisson_ratio = Some(0.5);
+  let error = mesh_validation_error(mesh);
+  assert!(
+    error.contains("meshes[0].moduli.poisson_ratio"),
+    "{}",
+    error
+  );
+}
+
+#[test]
+fn overrides_can_fix_loaded_values() {
+  let path = std::env::temp_dir()
+    .join(format!("overrides_can_fix_{}.toml", std::process::id()));
+  fs::write(
+    &path,
+    "[[meshes]]\npath = \"a.mesh\"\n[meshes.params]\ndensity = 0.0\n",
+  )
+  .unwrap();
+  let mut scene = SceneDescription::load(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+
+  assert!(scene.validate().is_err());
+  scene.meshes[0].params.density = Some(1.0);
+  scene.validate().unwrap();
+  // relative to the scene file
+  assert_eq!(
+    scene.meshes[0].path,
+    Some(std::env::temp_dir().join("a.mesh"))
+  );
+}
+
+#[test]
+fn errors_name_the_key() {
+  let error = SceneDescription::from_toml(
+    "[[meshes]]\npath = \"a.mesh\"\n[meshes.params]\ndensty = 1.0\n",
+    Path::new("scene.toml"),
+  )
+  .unwrap_err()
+  .to_string();
+  assert!(error.contains("densty"), "{}", error);
+
+  let error = SceneDescription::from_toml(
+    "time_step = \"fast\"\n",
+    Path::new("scene.toml"),
+  )
+  .unwrap_err()
+  .to_string();
+  assert!(error.contains("time_step"), "{}", error);
+
+  let mut scene = SceneDescription::default();
+  scene.meshes.push(MeshDescription::new("a.mesh".into()));
+  scene.meshes[0].params.density = Some(0.0);
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(error.contains("meshes[0].params.density"), "{}", error);
+}
+
+// validation error of a scene with only `mesh`
+#[cfg(test)]
+fn mesh_validation_error(mesh: MeshDescription) -> String {
+  let mut scene = SceneDescription::default();
+  scene.meshes.push(mesh);
+
+  scene.validate().unwrap_err().to_string()
+}
+
+#[test]
+fn validates_generated_meshes() {
+  let mut mesh = MeshDescription::new("a.mesh".into());
+  mesh.generate = Some(MeshShape::Beam {
+    length: 1.0,
+    thickness: 0.1,
+    segments: 0,
+  });
+  let error = mesh_validation_error(mesh.clone());
+  assert!(error.contains("meshes[0].path"), "{}", error);
+
+  mesh.path = None;
+  let error = mesh_validation_error(mesh);
+  assert!(error.contains("meshes[0].generate.segments"), "{}", error);
+}
+
+#[test]
+fn validates_surface_resolution() {
+  let mut mesh = MeshDescription::new("a.mesh".into());
+  mesh.surface_resolution = Some(8);
+  let error = mesh_validation_error(mesh.clone());
+  assert!(error.contains("only applies to .obj"), "{}", error);
+
+  mesh.path = Some("a.obj".into());
+  let mut scene = SceneDescription::default();
+  scene.meshes.push(mesh.clone());
+  scene.validate().unwrap();
+
+  mesh.surface_resolution = Some(0);
+  let error = mesh_validation_error(mesh);
+  assert!(error.contains("meshes[0].surface_resolution"), "{}", error);
+}
+
+#[test]
+fn validates_plasticity() {
+  let mut mesh = MeshDescription::new("a.mesh".into());
+  mesh.params.max_plastic_strain = Some(1.0);
+  let error = mesh_validation_error(mesh);
+  assert!(
+    error.contains("meshes[0].params.max_plastic_strain"),
+    "{}",
+    error
+  );
+}
+
+#[test]
+fn validates_toughness() {
+  let mut mesh = MeshDescription::new("a.mesh".into());
+  mesh.params.toughness = Some(0.0);
+  let error = mesh_validation_error(mesh);
+  assert!(error.contains("meshes[0].params.toughness"), "{}", error);
+}
+
+#[test]
+fn validates_air_drag() {
+  let mut mesh = MeshDescription::new("a.mesh".into());
+  mesh.params.air_drag = Some(-1.0);
+  let error = mesh_validation_error(mesh);
+  assert!(error.contains("meshes[0].params.air_drag"), "{}", error);
+}
+
+#[test]
+fn rk45_needs_a_min_step() {
+  use crate::ode::RK45Settings;
+
+  let mut scene = SceneDescription::default();
+  scene.meshes.push(MeshDescription::new("a.mesh".into()));
+  scene.integrator = IntegratorType::RK45(RK45Settings {
+    min_step: 0.0,
+    ..RK45Settings::default()
+  });
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(error.contains("integrator.min_step"), "{}", error);
+}
+
+#[test]
+fn assigns_regions() {
+  let scene = SceneDescription::from_toml(
+    r#"
+      [[meshes]]
+      translation = [5.0, 0.0, 0.0]
+      scale = 2.0
+      [meshes.generate]
+      shape = "box"
+      size = [2.0, 1.0, 1.0]
+      resolution = [4, 2, 2]
+      [meshes.params]
+      density = 2.0
+      rigidity = 50.0
+
+      [[meshes.regions]]
+      min = [0.0, -inf, -inf]
+      moduli = { youngs_modulus = 1000.0 }
+
+      [[meshes.regions]]
+      center = [-0.75, 0.0, 0.0]
+      radius = 0.3
+      params = { density = 7.0 }
+    "#,
+    Path::new("scene.toml"),
+  )
+  .unwrap();
+  scene.validate().unwrap();
+
+  let mesh = &scene.meshes[0];
+  let param_sets = mesh.param_sets(0).unwrap();
+  assert_eq!(param_sets.len(), 3);
+  // regions start from the mesh's parameters and override the given keys
+  assert_eq!(param_sets[1].density, 2.0);
+  assert!((param_sets[1].youngs_modulus() - 1000.0).abs() < 1e-9);
+  assert_eq!(param_sets[2].density, 7.0);
+  assert_eq!(param_sets[2].rigidity, 50.0);
+
+  // regions are in mesh coordinates
+  let shape = mesh.generate.clone().unwrap();
+  let untransformed = shape.generate(None);
+  let loaded = shape.generate(Some(&mesh.transform()));
+  let tetra_param_idxs = mesh.tetra_param_idxs(&loaded, None);
+  for (tetra, param_idx) in untransformed.1.iter().zip(&tetra_param_idxs) {
+    let centroid = tetra
+      .iter()
+      .map(|idx| untransformed.0[*idx as usize])
+      .sum::<Vector3<f32>>()
+      / 4.0;
+    let expected = if (centroid - Vector3::new(-0.75, 0.0, 0.0)).norm() <= 0.3 {
+      2
+    } else if centroid[0] >= 0.0 {
+      1
+    } else {
+      0
+    };
+    assert_eq!(*param_idx, expected);
+  }
+  for param_idx in 0..3 {
+    assert!(tetra_param_idxs.contains(&param_idx));
+  }
+
+  // the last matching region wins
+  let mut mesh = MeshDescription::new("meshes/cube.node".into());
+  mesh.regions = vec![
+    RegionDescription {
+      tag: Some(1),
+      ..RegionDescription::default()
+    },
+    RegionDescription {
+      tag: Some(2),
+      ..RegionDescription::default()
+    },
+  ];
+  let (loaded, tags) =
+    load_mesh_with_regions(mesh.path.as_ref().unwrap(), None).unwrap();
+  assert_eq!(
+    mesh.tetra_param_idxs(&loaded, tags.as_deref()),
+    vec![1; loaded.1.len()]
+  );
+  mesh.regions.push(RegionDescription::default());
+  assert_eq!(
+    mesh.tetra_param_idxs(&loaded, tags.as_deref()),
+    vec![3; loaded.1.len()]
+  );
+
+  let mut scene = SceneDescription::default();
+  scene.meshes.push(mesh);
+  scene.validate().unwrap();
+  scene.meshes[0].path = Some("a.mesh".into());
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(error.contains("meshes[0].regions[0].tag"), "{}", error);
+
+  scene.meshes[0].regions = vec![RegionDescription {
+    center: Some([0.0; 3]),
+    ..RegionDescription::default()
+  }];
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(error.contains("meshes[0].regions[0].radius"), "{}", error);
+
+  scene.meshes[0].regions[0].radius = Some(1.0);
+  scene.meshes[0].regions[0].moduli.poisson_ratio = Some(0.7);
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(
+    error.contains("meshes[0].regions[0].moduli.poisson_ratio"),
+    "{}",
+    error
+  );
+}
+
+#[test]
+fn assigns_fibers() {
+  use crate::mesh::write_vtk;
+  use std::io::Write;
+
+  let scene = SceneDescription::from_toml(
+    r#"
+      [[meshes]]
+      rotation = [0.0, 0.0, 1.5707963]
+      translation = [5.0, 0.0, 0.0]
+      params = { fiber_stiffness = 100.0 }
+      fibers = { field = "constant", direction = [2.0, 0.0, 0.0] }
+      [meshes.generate]
+      shape = "box"
+      size = [2.0, 1.0, 1.0]
+      resolution = [4, 2, 2]
+
+      [[meshes]]
+      generate = { shape = "sphere", radius = 1.0, resolution = 4 }
+      fibers = { field = "circumferential", axis = [0.0, 1.0, 0.0] }
+    "#,
+    Path::new("scene.toml"),
+  )
+  .unwrap();
+  scene.validate().unwrap();
+
+  // rotated along with the mesh
+  let mesh = &scene.meshes[0];
+  let loaded = mesh
+    .generate
+    .clone()
+    .unwrap()
+    .generate(Some(&mesh.transform()));
+  for direction in mesh.fiber_directions(&loaded).unwrap() {
+    assert!((direction - Vector3::new(0.0, 2.0, 0.0)).norm() < 1e-5);
+  }
+
+  let mesh = &scene.meshes[1];
+  let loaded = mesh.generate.clone().unwrap().generate(None);
+  let directions = mesh.fiber_directions(&loaded).unwrap();
+  for (tetra, direction) in loaded.1.iter().zip(&directions) {
+    let centroid = tetra
+      .iter()
+      .map(|idx| loaded.0[*idx as usize])
+      .sum::<Vector3<f32>>()
+      / 4.0;
+    assert!(direction.dot(&centroid).abs() < 1e-5);
+    assert_eq!(direction[1], 0.0);
+  }
+  assert!(MeshDescription::new("a.mesh".into())
+    .fiber_directions(&loaded)
+    .is_none());
+  assert_eq!(
+    FiberField::Radial { origin: [1.0; 3] }.direction(&Point3::origin()),
+    Some(Vector3::repeat(-1.0))
+  );
+
+  // directions from the cell data of a vtk mesh
+  let path = std::env::temp_dir()
+    .join(format!("assigns_fibers_{}.vtk", std::process::id()));
+  let box_mesh = crate::mesh::box_mesh(Vector3::repeat(1.0), [1, 1, 1]);
+  let mut contents = Vec::new();
+  write_vtk(&mut contents, &box_mesh).unwrap();
+  writeln!(
+    contents,
+    "CELL_DATA {}\nVECTORS fiber float",
+    box_mesh.1.len()
+  )
+  .unwrap();
+  for _ in &box_mesh.1 {
+    writeln!(contents, "1 0 0").unwrap();
+  }
+  fs::write(&path, contents).unwrap();
+
+  let mut scene = SceneDescription::default();
+  let mut mesh = MeshDescription::new(path.clone());
+  mesh.fibers = Some(FiberField::File {
+    name: "fiber".to_owned(),
+  });
+  scene.meshes.push(mesh);
+  scene.validate().unwrap();
+  let loads = scene.clone().into_generator().is_ok();
+  scene.meshes[0].fibers = Some(FiberField::File {
+    name: "fibre".to_owned(),
+  });
+  let error = scene.clone().into_generator().err().map(|e| e.to_string());
+  fs::remove_file(&path).unwrap();
+
+  assert!(loads);
+  let error = error.unwrap();
+  assert!(error.contains("meshes[0].fibers.name"), "{}", error);
+
+  scene.meshes[0].path = Some("a.mesh".into());
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(error.contains("meshes[0].fibers"), "{}", error);
+  scene.meshes[0].fibers = Some(FiberField::Constant {
+    direction: [0.0; 3],
+  });
+  let error = scene.validate().unwrap_err().to_string();
+  assert!(error.contains("meshes[0].fibers.direction"), "{}", error);
+}
@@ -1,5 +1,5 @@
 use crate::{
-  ode::{Model, ModelState},
+  ode::{Model, ModelState, SecondOrderModel},
   simulated_scene::{SimMesh, S},
 };
 use nalgebra::base::iter::{MatrixIter, MatrixIterMut};
@@ -18,7 +18,7 @@ pub struct SceneModelParams {
 pub struct SceneModel {
   sim_meshs: Vec<SimMesh>,
   params: SceneModelParams,
-  mesh_intervals: Vec<[u16; 2]>,
+  mesh_intervals: Vec<[u32; 2]>,
   penalty_force: S,
   floor_friction_coeff: S,
   floor_height: S,
@@ -42,20 +42,10 @@ impl SceneModel {
     sphere_radius: S,
     sphere_pos: Vector3<S>,
   ) -> Self {
-    let mut mesh_intervals = Vec::new();
-
-    let mut total_size = 0;
-
-    for mesh in &sim_meshs {
-      let new_total_size = total_size + mesh.num_vertices();
-      mesh_intervals.push([total_size, new_total_size]);
-      total_size = new_total_size;
-    }
-
     Self {
+      mesh_intervals: Self::compute_mesh_intervals(&sim_meshs),
       sim_meshs,
       params,
-      mesh_intervals,
       penalty_force,
       floor_friction_coeff,
       floor_height,
@@ -64,6 +54,20 @@ impl SceneModel {
     }
   }
 
+  fn compute_mesh_intervals(sim_meshs: &[SimMesh]) -> Vec<[u32; 2]> {
+    let mut mesh_intervals = Vec::new();
+
+    let mut total_size = 0;
+
+    for mesh in sim_meshs {
+      let new_total_size = total_size + mesh.num_vertices();
+      mesh_intervals.push([total_size, new_total_size]);
+      total_size = new_total_size;
+    }
+
+    mesh_intervals
+  }
+
   pub fn initial_state(&self) -> SceneModelState {
     SceneModelState {
       positions: self
@@ -86,7 +90,7 @@ impl SceneModel {
     &self.sim_meshs
   }
 
-  pub fn mesh_intervals(&self) -> &[[u16; 2]] {
+  pub fn mesh_intervals(&self) -> &[[u32; 2]] {
     &self.mesh_intervals
   }
 
@@ -101,6 +105,125 @@ impl SceneModel {
   pub fn sphere_pos(&self) -> Vector3<S> {
     self.sphere_pos
   }
+
+  /// Plastic flow of every mesh after a step, see
+  /// `SimMesh::update_plasticity`. Returns whether any rest shape changed.
+  pub fn update_plasticity(
+    &mut self,
+    state: &SceneModelState,
+    time_step: S,
+  ) -> bool {
+    let mut changed = false;
+    for ([start, end], mesh) in
+      self.mesh_intervals.iter().zip(self.sim_meshs.iter_mut())
+    {
+      changed |= mesh.update_plasticity(
+        &state.positions[*start as usize..*end as usize],
+        time_step,
+      );
+    }
+
+    changed
+  }
+
+  /// Fractures every mesh after a step (see `SimMesh::fracture`). The
+  /// vertices added start with the position and velocity of the vertex they
+  /// were split from, which moves the states of the later meshes. Returns
+  /// whether any mesh fractured.
+  pub fn update_fracture(&mut self, state: &mut SceneModelState) -> bool {
+    let sources: Vec<_> = self
+      .mesh_intervals
+      .iter()
+      .zip(self.sim_meshs.iter_mut())
+      .map(|([start, end], mesh)| {
+        let range = (*start as usize)..(*end as usize);
+        mesh.fracture(&state.positions[range.clone()], &state.velocities[range])
+      })
+      .collect();
+
+    if sources.iter().all(|sources| sources.is_empty()) {
+      return false;
+    }
+
+    let mut positions = Vec::new();
+    let mut velocities = Vec::new();
+    for ([start, end], sources) in self.mesh_intervals.iter().zip(&sources) {
+      let range = (*start as usize)..(*end as usize);
+      positions.extend_from_slice(&state.positions[range.clone()]);
+      velocities.extend_from_slice(&state.velocities[range.clone()]);
+
+      for vertex_idx in sources {
+        let vertex_idx = range.start + *vertex_idx as usize;
+        positions.push(state.positions[vertex_idx]);
+        velocities.push(state.velocities[vertex_idx]);
+      }
+    }
+
+    state.positions = positions;
+    state.velocities = velocities;
+    self.mesh_intervals = Self::compute_mesh_intervals(&self.sim_meshs);
+
+    true
+  }
+
+  // floor and sphere penalty forces
+  fn external_force(&self, pos: &Vector3<S>, vel: &Vector3<S>) -> Vector3<S> {
+    let mut force = Vector3::zeros();
+
+    if pos[1] < self.floor_height {
+      let normal_force = self.penalty_force * (self.floor_height - pos[1]);
+      force -= vel.normalize() * normal_force * self.floor_friction_coeff;
+      force[1] += normal_force;
+    }
+
+    let sphere_diff = pos - self.sphere_pos;
+    let sphere_diff_norm = sphere_diff.norm();
+
+    if sphere_diff_norm < self.sphere_radius {
+      force += sphere_diff / sphere_diff_norm
+        * self.penalty_force
+        * (self.sphere_radius - sphere_diff_norm);
+    }
+
+    force
+  }
+
+  fn external_force_differential(
+    &self,
+    pos: &Vector3<S>,
+    vel: &Vector3<S>,
+    d_pos: &Vector3<S>,
+    d_vel: &Vector3<S>,
+  ) -> Vector3<S> {
+    let mut d_force = Vector3::zeros();
+
+    if pos[1] < self.floor_height {
+      let normal_force = self.penalty_force * (self.floor_height - pos[1]);
+      let d_normal_force = -self.penalty_force * d_pos[1];
+
+      let vel_norm = vel.norm();
+      let vel_dir = vel / vel_norm;
+      let d_vel_dir = (d_vel - vel_dir * vel_dir.dot(d_vel)) / vel_norm;
+
+      d_force -= (d_vel_dir * normal_force + vel_dir * d_normal_force)
+        * self.floor_friction_coeff;
+      d_force[1] += d_normal_force;
+    }
+
+    let sphere_diff = pos - self.sphere_pos;
+    let sphere_diff_norm = sphere_diff.norm();
+
+    if sphere_diff_norm < self.sphere_radius {
+      let sphere_dir = sphere_diff / sphere_diff_norm;
+      let radius_ratio = self.sphere_radius / sphere_diff_norm;
+
+      d_force += self.penalty_force
+        * ((radius_ratio - 1.0) * d_pos
+          - radius_ratio * sphere_dir.dot(d_pos) * sphere_dir);
+    }
+
+    d_force
+  }
 }
 
 type BaseIntoIterGen<'a, I, M> = Flatten<Map<I, M>>;
@@ -210,29 +333,7 @@ impl Model for SceneModel {
         &x.positions[start..end]
           .iter()
           .zip(&x.velocities[start..end])
-          .map(|(pos, vel)| {
-            let mut force = Vector3::zeros();
-
-            if pos[1] < self.floor_height {
-              let normal_force =
-                self.penalty_force * (self.floor_height - pos[1]);
-              force -=
-                vel.normalize() * normal_force * self.floor_friction_coeff;
-              force[1] += normal_force;
-            }
-
-            let sphere_diff = pos - self.sphere_pos;
-            let sphere_diff_norm = sphere_diff.norm();
-
-            if sphere_diff_norm < self.sphere_radius {
-              // dbg!(sphere_diff / sphere_diff_norm);
-              force += sphere_diff / sphere_diff_norm
-                * self.penalty_force
-                * (self.sphere_radius - sphere_diff_norm);
-            }
-
-            force
-          })
+          .map(|(pos, vel)| self.external_force(pos, vel))
           .collect::<Vec<_>>(),
         self.params.g,
       );
@@ -241,4 +342,198 @@ impl Model for SceneModel {
       dxdt.positions[start..end].copy_from_slice(&x.velocities[start..end]);
     }
   }
+
+  fn jacobian_vector_product(
+    &self,
+    x: &Self::State,
+    _: &Self::State,
+    v: &Self::State,
+    jv: &mut Self::State,
+    _: &Self::S,
+  ) {
+    jv.zeros_as(x);
+
+    for ([start, end], mesh) in
+      self.mesh_intervals.iter().zip(self.sim_meshs.iter())
+    {
+      let start = *start as usize;
+      let end = *end as usize;
+
+      let d_accels = mesh.vertex_accels_differential(
+        &x.positions[start..end],
+        &x.velocities[start..end],
+        &v.positions[start..end],
+        &v.velocities[start..end],
+        &x.positions[start..end]
+          .iter()
+          .zip(&x.velocities[start..end])
+          .zip(&v.positions[start..end])
+          .zip(&v.velocities[start..end])
+          .map(|(((pos, vel), d_pos), d_vel)| {
+            self.external_force_differential(pos, vel, d_pos, d_vel)
+          })
+          .collect::<Vec<_>>(),
+      );
+
+      jv.velocities[start..end].copy_from_slice(&d_accels);
+      jv.positions[start..end].copy_from_slice(&v.velocities[start..end]);
+    }
+  }
+}
+
+impl SecondOrderModel for SceneModel {
+  fn drift(x: &mut Self::State, scale: Self::S) {
+    for (pos, vel) in x.positions.iter_mut().zip(&x.velocities) {
+      *pos += scale * vel;
+    }
+  }
+
+  fn kick(x: &mut Self::State, scale: Self::S, dxdt: &Self::State) {
+    for (vel, accel) in x.velocities.iter_mut().zip(&dxdt.velocities) {
+      *vel += scale * accel;
+    }
+  }
+}
+
+#[test]
+fn jacobian_vector_product_matches_finite_difference() {
+  use crate::ode::{
+    finite_difference_jacobian_vector_product, DEFAULT_FD_STEP,
+  };
+  use crate::simulated_scene::{Material, MeshParams};
+
+  let mesh = SimMesh::new(
+    (
+      vec![
+        Vector3::new(0.0, -3.2, 0.0),
+        Vector3::new(1.0, -2.9, 0.0),
+        Vector3::new(0.0, -2.0, 0.0),
+        Vector3::new(0.0, -3.1, 1.0),
+      ],
+      vec![[0, 1, 2, 3]],
+    ),
+    MeshParams {
+      incompressibility: 100.0,
+      rigidity: 100.0,
+      viscous_incompressibility: 2.0,
+      viscous_rigidity: 5.0,
+      mass_damping: 0.0,
+      stiffness_damping: 0.0,
+      air_drag: 0.0,
+      density: 5.0,
+      material: Material::StVenantKirchhoff,
+      fiber_stiffness: 0.0,
+      yield_strain: 0.05,
+      creep_rate: 0.0,
+      max_plastic_strain: 0.5,
+      toughness: None,
+    },
+  )
+  .unwrap();
+
+  // vertex 0 and 3 are below the floor and vertex 0 and 1 are in the sphere
+  let model = SceneModel::new(
+    vec![mesh],
+    SceneModelParams { g: 9.8 },
+    10000.0,
+    0.1,
+    -3.0,
+    1.0,
+    Vector3::new(0.5, -3.5, 0.0),
+  );
+
+  let mut x = model.initial_state();
+  for (i, vel) in x.velocities.iter_mut().enumerate() {
+    *vel = Vector3::new(0.3, -0.5, 0.2 * i as S);
+  }
+
+  let mut v = SceneModelState::new();
+  v.zeros_as(&x);
+  for (i, (d_pos, d_vel)) in v
+    .positions
+    .iter_mut()
+    .zip(v.velocities.iter_mut())
+    .enumerate()
+  {
+    let i = i as S;
+    *d_pos = Vector3::new(0.1 * i, -0.2, 0.3 - 0.1 * i);
+    *d_vel = Vector3::new(-0.4, 0.1 * i, 0.2);
+  }
+
+  let mut dxdt = SceneModelState::new();
+  dxdt.zeros_as(&x);
+  model.derivative(&x, &mut dxdt, &0.0);
+
+  let mut analytic = SceneModelState::new();
+  let mut finite_difference = SceneModelState::new();
+  model.jacobian_vector_product(&x, &dxdt, &v, &mut analytic, &0.0);
+  finite_difference_jacobian_vector_product(
+    &model,
+    &x,
+    &dxdt,
+    &v,
+    &mut finite_difference,
+    &0.0,
+    DEFAULT_FD_STEP.into(),
+  );
+
+  let scale = finite_difference
+    .velocities
+    .iter()
+    .map(|v| v.norm())
+    .sum::<S>();
+  for (analytic, finite_difference) in
+    (&analytic).into_iter().zip(&finite_difference)
+  {
+    assert!((analytic - finite_difference).abs() < 1e-4 * scale);
+  }
+}
+
+#[test]
+fn fracture_keeps_states_of_other_meshes() {
+  use crate::simulated_scene::{sim_mesh::face_sharing_tetras, MeshParams};
+
+  let get_mesh = |toughness| {
+    SimMesh::new(
+      face_sharing_tetras(),
+      MeshParams {
+        toughness,
+        ..MeshParams::default()
+      },
+    )
+    .unwrap()
+  };
+
+  let mut model = SceneModel::new(
+    vec![get_mesh(Some(1.0)), get_mesh(None)],
+    SceneModelParams { g: 9.8 },
+    10000.0,
+    0.1,
+    -10.0,
+    0.0,
+    Vector3::zeros(),
+  );
+
+  let mut state = model.initial_state();
+  for (i, (pos, vel)) in state
+    .positions
+    .iter_mut()
+    .zip(state.velocities.iter_mut())
+    .enumerate()
+  {
+    pos[2] *= 1.5;
+    *vel = Vector3::new(i as S, 0.0, 0.0);
+  }
+  let before = state.clone();
+
+  assert!(model.update_fracture(&mut state));
+
+  assert_eq!(model.mesh_intervals(), &[[0, 8], [8, 13]]);
+  assert_eq!(state.positions[..5], before.positions[..5]);
+  assert_eq!(state.velocities[..5], before.velocities[..5]);
+  // the shared face is split off
+  assert_eq!(state.positions[5..8], before.positions[..3]);
+  assert_eq!(state.velocities[5..8], before.velocities[..3]);
+  assert_eq!(state.positions[8..], before.positions[5..]);
+  assert_eq!(state.velocities[8..], before.velocities[5..]);
 }
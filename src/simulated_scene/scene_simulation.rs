@@ -0,0 +1,311 @@
+use crate::{
+  ode::{Integrator, IntegratorType, SwappableIntegrator},
+  simulated_scene::{
+    OutputParams, SceneModel, SceneModelState, SimMesh, StepParams, S,
+  },
+  surface_export::{write_surface, SurfaceFormat},
+  vtk_export::{write_vtu, PvdWriter},
+  HeadlessScene, Scene,
+};
+use nalgebra::Vector3;
+use std::fs::File;
+use std::io::{self, prelude::*, BufWriter};
+use std::path::Path;
+
+/// The simulation without any rendering, shared by the windowed and
+/// headless scenes.
+pub struct SceneSimulation {
+  scene_model: SceneModel,
+  scene_state: SceneModelState,
+  step_params: StepParams,
+  integrator: SwappableIntegrator<SceneModel>,
+  output_params: OutputParams,
+  // opened on the first saved frame
+  pvd: Option<PvdWriter>,
+  time: S,
+}
+
+impl SceneSimulation {
+  pub fn new(
+    scene_model: SceneModel,
+    step_params: StepParams,
+    integrator_type: IntegratorType,
+    output_params: OutputParams,
+  ) -> Self {
+    Self {
+      scene_state: scene_model.initial_state(),
+      scene_model,
+      step_params,
+      integrator: SwappableIntegrator::new(integrator_type),
+      output_params,
+      pvd: None,
+      time: 0.0,
+    }
+  }
+
+  pub fn scene_model(&self) -> &SceneModel {
+    &self.scene_model
+  }
+
+  pub fn scene_state(&self) -> &SceneModelState {
+    &self.scene_state
+  }
+
+  /// each mesh along with the slices of the state that belong to it
+  pub fn meshs_states(
+    &self,
+  ) -> impl Iterator<Item = (&SimMesh, &[Vector3<S>], &[Vector3<S>])> {
+    let state = &self.scene_state;
+    self
+      .scene_model
+      .meshs()
+      .iter()
+      .zip(self.scene_model.mesh_intervals().iter())
+      .map(move |(sim_mesh, [start, end])| {
+        let range = (*start as usize)..(*end as usize);
+        (
+          sim_mesh,
+          &state.positions[range.clone()],
+          &state.velocities[range],
+        )
+      })
+  }
+
+  /// positions and velocities of every vertex as csv, vertices are in the
+  /// order of the loaded meshes
+  pub fn write_state_csv(&self, path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "mesh,vertex,x,y,z,vx,vy,vz")?;
+
+    for (mesh_idx, (sim_mesh, positions, velocities)) in
+      self.meshs_states().enumerate()
+    {
+      let positions = sim_mesh.vertex_values_original_order(positions);
+      let velocities = sim_mesh.vertex_values_original_order(velocities);
+
+      for (vertex_idx, (pos, vel)) in
+        positions.iter().zip(&velocities).enumerate()
+      {
+        writeln!(
+          writer,
+          "{},{},{},{},{},{},{},{}",
+          mesh_idx, vertex_idx, pos[0], pos[1], pos[2], vel[0], vel[1], vel[2]
+        )?;
+      }
+    }
+
+    writer.flush()
+  }
+
+  /// boundary surface of each mesh as `mesh_{mesh}_{frame}.{obj,ply}`
+  pub fn write_surfaces(
+    &self,
+    dir: &Path,
+    frame: usize,
+    format: SurfaceFormat,
+    normals: bool,
+  ) -> io::Result<()> {
+    for (mesh_idx, (sim_mesh, positions, _)) in self.meshs_states().enumerate()
+    {
+      let (positions, faces) = sim_mesh.boundary_vertices_faces(positions);
+
+      write_surface(
+        &dir.join(format!(
+          "mesh_{}_{}.{}",
+          mesh_idx,
+          frame,
+          format.extension()
+        )),
+        format,
+        &positions,
+        &faces,
+        normals,
+      )?;
+    }
+
+    Ok(())
+  }
+
+  /// Each mesh as `mesh_{mesh}_{frame}.vtu` with per vertex velocity and per
+  /// tet elastic strain, stress and plastic strain (frobenius) norms,
+  /// numbered as the loaded meshes.
+  /// Returns the file names.
+  pub fn write_vtus(
+    &self,
+    dir: &Path,
+    frame: usize,
+  ) -> io::Result<Vec<String>> {
+    self
+      .meshs_states()
+      .enumerate()
+      .map(|(mesh_idx, (sim_mesh, positions, velocities))| {
+        let (strain_norms, stress_norms): (Vec<_>, Vec<_>) = sim_mesh
+          .tetra_strains_stresses(positions, velocities)
+          .iter()
+          .map(|(strain, stress)| (strain.norm(), stress.norm()))
+          .unzip();
+        let plastic_strain_norms: Vec<_> = sim_mesh
+          .plastic_strains()
+          .iter()
+          .map(|strain| strain.norm())
+          .collect();
+        let original =
+          |values: &[S]| sim_mesh.tetra_values_original_order(values);
+
+        let file_name = format!("mesh_{}_{}.vtu", mesh_idx, frame);
+        let mut writer = BufWriter::new(File::create(dir.join(&file_name))?);
+
+        write_vtu(
+          &mut writer,
+          &sim_mesh.vertex_values_original_order(positions),
+          &sim_mesh.tetras_original_order(),
+          &[(
+            "velocity",
+            &sim_mesh.vertex_values_original_order(velocities),
+          )],
+          &[
+            ("strain_norm", &original(&strain_norms)),
+            ("stress_norm", &original(&stress_norms)),
+            ("plastic_strain_norm", &original(&plastic_strain_norms)),
+          ],
+        )?;
+        writer.flush()?;
+
+        Ok(file_name)
+      })
+      .collect()
+  }
+}
+
+impl Scene for SceneSimulation {
+  fn update(&mut self, delta_secs: f32) {
+    let delta_secs = (delta_secs as S) * self.step_params.speed_up;
+    let steps = (delta_secs / self.step_params.time_step).ceil() as usize;
+    let time_step = delta_secs / steps as S;
+
+    for _ in 0..steps {
+      self.integrator.step(
+        &self.scene_model,
+        &mut self.scene_state,
+        &mut self.time,
+        &time_step,
+      );
+      // the rest shapes and topology only change between steps, integrators
+      // which carry values from the last step have to drop them when they do
+      let flowed = self
+        .scene_model
+        .update_plasticity(&self.scene_state, time_step);
+      let fractured = self.scene_model.update_fracture(&mut self.scene_state);
+      if flowed || fractured {
+        self.integrator.reset();
+      }
+    }
+  }
+
+  fn save_frame(&mut self, frame: usize) -> io::Result<()> {
+    if let Some(state_dir) = &self.output_params.state_dir {
+      self.write_state_csv(&state_dir.join(format!("state_{}.csv", frame)))?;
+    }
+
+    if let Some(surface) = &self.output_params.surface {
+      self.write_surfaces(
+        &surface.dir,
+        frame,
+        surface.format,
+        surface.normals,
+      )?;
+    }
+
+    if let Some(vtk_dir) = &self.output_params.vtk_dir {
+      let file_names = self.write_vtus(vtk_dir, frame)?;
+
+      if self.pvd.is_none() {
+        self.pvd = Some(PvdWriter::create(&vtk_dir.join("scene.pvd"))?);
+      }
+      if let Some(pvd) = &mut self.pvd {
+        for (mesh_idx, file_name) in file_names.iter().enumerate() {
+          pvd.add(self.time, mesh_idx, file_name)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn summary(&self) -> Option<String> {
+    self.integrator.step_stats().map(|stats| {
+      format!(
+        "accepted steps: {}, rejected steps: {}, failed steps: {}",
+        stats.accepted, stats.rejected, stats.failed
+      )
+    })
+  }
+}
+
+impl HeadlessScene for SceneSimulation {
+  fn time(&self) -> f32 {
+    self.time as f32
+  }
+}
+
+#[test]
+fn verlet_uses_the_rest_shape_after_plastic_flow() {
+  use crate::ode::{VelocityVerlet, VelocityVerletSettings};
+  use crate::simulated_scene::{MeshParams, SceneModelParams};
+
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::new(1.0, 1.0, 1.0), [2, 2, 2]);
+  let mesh = SimMesh::new(
+    (vertices, tetras),
+    MeshParams {
+      yield_strain: 0.01,
+      creep_rate: 20.0,
+      ..MeshParams::default()
+    },
+  )
+  .unwrap();
+  let scene_model = SceneModel::new(
+    vec![mesh],
+    SceneModelParams { g: 9.8 },
+    10000.0,
+    0.1,
+    -10.0,
+    0.0,
+    Vector3::zeros(),
+  );
+  // exact in f32 so the simulation takes the same steps
+  let time_step = 1.0 / 1024.0;
+
+  let mut simulation = SceneSimulation::new(
+    scene_model.clone(),
+    StepParams {
+      time_step,
+      speed_up: 1.0,
+    },
+    IntegratorType::VelocityVerlet(VelocityVerletSettings::default()),
+    OutputParams::default(),
+  );
+  // squashed so it flows
+  for pos in &mut simulation.scene_state.positions {
+    pos.component_mul_assign(&Vector3::new(1.1, 0.8, 1.1));
+  }
+
+  // a new integrator for every step never reuses an old derivative
+  let mut expected_model = scene_model;
+  let mut expected = simulation.scene_state.clone();
+  let mut time = 0.0;
+  for _ in 0..10 {
+    VelocityVerlet::new(VelocityVerletSettings::default()).step(
+      &expected_model,
+      &mut expected,
+      &mut time,
+      &time_step,
+    );
+    assert!(expected_model.update_plasticity(&expected, time_step));
+  }
+
+  simulation.update(10.0 / 1024.0);
+  assert_eq!(simulation.scene_state.positions, expected.positions);
+  assert_eq!(simulation.scene_state.velocities, expected.velocities);
+}
@@ -1,8 +1,19 @@
-use crate::simulated_scene::S;
+use crate::mesh::{
+  check_indices, reorder_mesh, MeshError, Reordering, VertexOrdering,
+};
+use crate::simulated_scene::{
+  material::{
+    fiber_stress, fiber_stress_differential, flow_plastic_strain,
+    strain_to_stress, Material,
+  },
+  S,
+};
 use crate::LoadedMesh;
 use nalgebra::{Matrix3, Point3, Vector3};
 use rayon::prelude::*;
-use std::collections::HashSet;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
 
 #[cfg(test)]
@@ -14,7 +25,8 @@ use proptest::prelude::*;
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct MeshParams {
   pub incompressibility: S,
   pub rigidity: S,
@@ -22,36 +34,167 @@ pub struct MeshParams {
   pub viscous_incompressibility: S,
   pub viscous_rigidity: S,
 
+  /// Rayleigh damping, a force of `-mass_damping * mass * velocity` on each
+  /// vertex (in 1/s)
+  pub mass_damping: S,
+  /// Rayleigh damping, a stress of `stiffness_damping` times the rate of
+  /// change of the elastic stress (in s), which damps higher frequencies more
+  pub stiffness_damping: S,
+  /// quadratic drag, a force of `-air_drag * area * |v| v` on each boundary
+  /// vertex where area is a third of the rest area of its boundary faces
+  pub air_drag: S,
+
   pub density: S,
+
+  pub material: Material,
+  /// Young's modulus added along the fiber direction of each tet (see
+  /// `SimMesh::set_fiber_directions`), which makes the material transversely
+  /// isotropic
+  pub fiber_stiffness: S,
+
+  /// Norm of the deviatoric elastic strain beyond which the rest shape flows
+  /// plastically, see `SimMesh::update_plasticity`
+  pub yield_strain: S,
+  /// fraction of the strain beyond the yield strain absorbed per second, zero
+  /// for purely elastic meshes
+  pub creep_rate: S,
+  /// limit on the norm of the plastic strain, less than 1
+  pub max_plastic_strain: S,
+
+  /// Largest principal (Cauchy) stress a tet withstands before it separates
+  /// from one of its neighbors, see `SimMesh::fracture`. None for meshes
+  /// which don't fracture
+  pub toughness: Option<S>,
+}
+
+impl Default for MeshParams {
+  fn default() -> Self {
+    Self {
+      incompressibility: 100.0,
+      rigidity: 100.0,
+      viscous_incompressibility: 2.0,
+      viscous_rigidity: 5.0,
+      mass_damping: 0.0,
+      stiffness_damping: 0.0,
+      air_drag: 0.0,
+      density: 5.0,
+      material: Material::StVenantKirchhoff,
+      fiber_stiffness: 0.0,
+      yield_strain: 0.05,
+      creep_rate: 0.0,
+      max_plastic_strain: 0.5,
+      toughness: None,
+    }
+  }
+}
+
+/// `MeshParams` with only the keys given in a scene file or on the command
+/// line, applied over other parameters by `ElasticModuli::apply_with`.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PartialMeshParams {
+  pub incompressibility: Option<S>,
+  pub rigidity: Option<S>,
+  pub viscous_incompressibility: Option<S>,
+  pub viscous_rigidity: Option<S>,
+  pub mass_damping: Option<S>,
+  pub stiffness_damping: Option<S>,
+  pub air_drag: Option<S>,
+  pub density: Option<S>,
+  pub material: Option<Material>,
+  pub fiber_stiffness: Option<S>,
+  pub yield_strain: Option<S>,
+  pub creep_rate: Option<S>,
+  pub max_plastic_strain: Option<S>,
+  pub toughness: Option<S>,
+}
+
+impl PartialMeshParams {
+  pub fn apply(&self, params: &mut MeshParams) {
+    fn set<T: Clone>(param: &mut T, key: &Option<T>) {
+      if let Some(value) = key {
+        *param = value.clone();
+      }
+    }
+
+    set(&mut params.incompressibility, &self.incompressibility);
+    set(&mut params.rigidity, &self.rigidity);
+    set(
+      &mut params.viscous_incompressibility,
+      &self.viscous_incompressibility,
+    );
+    set(&mut params.viscous_rigidity, &self.viscous_rigidity);
+    set(&mut params.mass_damping, &self.mass_damping);
+    set(&mut params.stiffness_damping, &self.stiffness_damping);
+    set(&mut params.air_drag, &self.air_drag);
+    set(&mut params.density, &self.density);
+    set(&mut params.material, &self.material);
+    set(&mut params.fiber_stiffness, &self.fiber_stiffness);
+    set(&mut params.yield_strain, &self.yield_strain);
+    set(&mut params.creep_rate, &self.creep_rate);
+    set(&mut params.max_plastic_strain, &self.max_plastic_strain);
+    set(&mut params.toughness, &self.toughness.map(Some));
+  }
 }
 
 #[derive(Clone)]
 pub struct SimMesh {
   vertex_positions_obj_space: Vec<Vector3<S>>, // per vertex
   vertex_mass: Vec<S>,                         // per vertex
+  // mass times the mass damping of the tets, summed like the mass
+  vertex_mass_damping: Vec<S>, // per vertex
+  // air drag of the boundary faces times a third of their area
+  vertex_air_drag: Vec<S>, // per vertex
 
-  tetras: Vec<[u16; 4]>, // per tet
+  tetras: Vec<[u32; 4]>, // per tet
   // scaled by face area
   opposite_normals: Vec<[Vector3<S>; 4]>, // per tet
   inv_barycentric_mat: Vec<Matrix3<S>>,   // per tet
 
-  boundary_vertices: Vec<u16>,
+  boundary_vertices: Vec<u32>,
   // indexing scheme must be the same as boundary_vertices
-  boundary_faces: Vec<[u16; 3]>,
-
-  params: MeshParams,
+  boundary_faces: Vec<[u32; 3]>,
+  // the tet across the face opposite each corner, None on the boundary and
+  // along cracks
+  tetra_neighbors: Vec<[Option<u32>; 4]>, // per tet
+
+  // the tets using each vertex along with the position of the vertex in the
+  // tet, those of vertex i are in
+  // vertex_tetras[vertex_tetra_starts[i]..vertex_tetra_starts[i + 1]]
+  vertex_tetra_starts: Vec<u32>,
+  vertex_tetras: Vec<(u32, u8)>,
+
+  reordering: Reordering,
+  parallel_threshold: Option<usize>,
+
+  params: Vec<MeshParams>,
+  tetra_param_idxs: Vec<u32>, // per tet
+  // unit length, or zero for tets without fibers
+  fiber_directions: Vec<Vector3<S>>, // per tet
+
+  // symmetric, the rest shape of each tet is deformed by I + plastic strain
+  plastic_strains: Vec<Matrix3<S>>, // per tet
+  // inverse of I + plastic strain
+  inv_plastic_deformations: Vec<Matrix3<S>>, // per tet
 }
 
+/// tets needed before forces are computed in parallel, unless set with
+/// `SimMesh::set_parallel_threshold`
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 1000;
+
+// relative step of the central differences for the change of the stiffness
+const STIFFNESS_FD_STEP: S = 1e-5;
+
 impl SimMesh {
   fn get_vertex(
-    tetra: [u16; 4],
+    tetra: [u32; 4],
     vals: &[Vector3<S>],
     idx: usize,
   ) -> Vector3<S> {
     vals[tetra[idx] as usize]
   }
 
-  fn tetra_val_edges(tetra: [u16; 4], vals: &[Vector3<S>]) -> Matrix3<S> {
+  fn tetra_val_edges(tetra: [u32; 4], vals: &[Vector3<S>]) -> Matrix3<S> {
     let get_vertex = |idx| Self::get_vertex(tetra, vals, idx);
     Matrix3::from_columns(&[
       get_vertex(0) - get_vertex(3),
@@ -60,10 +203,74 @@ impl SimMesh {
     ])
   }
 
-  pub fn new(
-    (vertex_positions_obj_space, tetras): LoadedMesh,
+  fn tetra_volume(edges: &Matrix3<S>) -> S {
+    edges.determinant().abs() / 6.0
+  }
+
+  // vertices of the face opposite corner, in the order of the tet
+  fn face_vertices(tetra: [u32; 4], corner: usize) -> [u32; 3] {
+    let mut face = [0; 3];
+    for (face_vertex, idx) in
+      face.iter_mut().zip((0..4).filter(|idx| *idx != corner))
+    {
+      *face_vertex = tetra[idx];
+    }
+
+    face
+  }
+
+  pub fn new(mesh: LoadedMesh, params: MeshParams) -> Result<Self, MeshError> {
+    Self::with_ordering(mesh, params, VertexOrdering::Original)
+  }
+
+  /// The vertices and tets are renumbered first, see `reorder_mesh`. Errors
+  /// use the original numbering.
+  pub fn with_ordering(
+    mesh: LoadedMesh,
     params: MeshParams,
-  ) -> Self {
+    ordering: VertexOrdering,
+  ) -> Result<Self, MeshError> {
+    let num_tetras = mesh.1.len();
+
+    Self::with_tetra_params(mesh, vec![params], vec![0; num_tetras], ordering)
+  }
+
+  /// Like `with_ordering`, but the parameters of each tet (in the order of
+  /// the loaded mesh) are given by an index into `params`.
+  pub fn with_tetra_params(
+    mesh: LoadedMesh,
+    params: Vec<MeshParams>,
+    tetra_param_idxs: Vec<u32>,
+    ordering: VertexOrdering,
+  ) -> Result<Self, MeshError> {
+    check_indices(&mesh)?;
+    if tetra_param_idxs.len() != mesh.1.len() {
+      return Err(MeshError::ParamIndexCount {
+        count: tetra_param_idxs.len(),
+        num_tetras: mesh.1.len(),
+      });
+    }
+    for (tetra_idx, param_idx) in tetra_param_idxs.iter().enumerate() {
+      if *param_idx as usize >= params.len() {
+        return Err(MeshError::ParamIndexOutOfRange {
+          tetra: tetra_idx,
+          param: *param_idx as usize,
+          num_params: params.len(),
+        });
+      }
+    }
+
+    let (mesh, reordering) = reorder_mesh(mesh, ordering);
+    let tetra_param_idxs: Vec<u32> = reordering
+      .tetras
+      .iter()
+      .map(|original_idx| tetra_param_idxs[*original_idx as usize])
+      .collect();
+    let (vertex_positions_obj_space, tetras) = mesh;
+    let degenerate = |tetra_idx: usize| MeshError::DegenerateTetra {
+      tetra: reordering.tetras[tetra_idx] as usize,
+    };
+
     let vertex_positions_obj_space: Vec<Vector3<S>> =
       vertex_positions_obj_space
         .iter()
@@ -73,26 +280,30 @@ impl SimMesh {
     let mut inv_barycentric_mat = Vec::new();
     let mut opposite_normals = Vec::new();
     let mut vertex_mass = vec![0.0; vertex_positions_obj_space.len()];
+    let mut vertex_mass_damping = vertex_mass.clone();
 
-    let mut boundary_faces_set = HashSet::new();
-
-    for tetra in &tetras {
+    for (tetra_idx, tetra) in tetras.iter().enumerate() {
       let edges = Self::tetra_val_edges(*tetra, &vertex_positions_obj_space);
-      inv_barycentric_mat.push(
-        // TODO: fix expect
-        edges.try_inverse().expect(
-          "all tetrahedrons should have inverses for barycentric coords",
-        ),
-      );
-
-      // TODO: check use of columns is as expected
-      let volume = (edges.column(0).cross(&edges.column(1)))
-        .dot(&edges.column(2))
-        .abs()
-        / 6.0;
+      let volume = Self::tetra_volume(&edges);
+
+      // relative to the size of the tet so scale doesn't matter
+      let max_edge = edges
+        .column_iter()
+        .map(|edge| edge.norm())
+        .fold(0.0, S::max);
+      if volume <= S::EPSILON * max_edge.powi(3) {
+        return Err(degenerate(tetra_idx));
+      }
+
+      inv_barycentric_mat
+        .push(edges.try_inverse().ok_or_else(|| degenerate(tetra_idx))?);
 
+      let tetra_params = &params[tetra_param_idxs[tetra_idx] as usize];
+      let mass = tetra_params.density * volume / 4.0;
       for vertex_idx in tetra {
-        vertex_mass[*vertex_idx as usize] += params.density * volume / 4.0;
+        vertex_mass[*vertex_idx as usize] += mass;
+        vertex_mass_damping[*vertex_idx as usize] +=
+          tetra_params.mass_damping * mass;
       }
 
       let mut tet_opposite_normals = [Vector3::zeros(); 4];
@@ -128,117 +339,755 @@ impl SimMesh {
 
         if above_plane {
           *opposite_normal *= -1.0;
-
-          face.reverse();
         }
 
         debug_assert_eq!(
           face.len(),
-          HashSet::<u16>::from_iter(face.iter().cloned()).len()
+          HashSet::<u32>::from_iter(face.iter().cloned()).len()
         );
-
-        if boundary_faces_set.contains(face) {
-          boundary_faces_set.remove(face);
-        } else {
-          boundary_faces_set.insert(face.clone());
-        }
       }
 
       opposite_normals.push(tet_opposite_normals);
     }
 
-    let mut boundary_vertices = Vec::new();
-    let mut vertex_idx_to_boundary_vertex_idx = Vec::new();
-    vertex_idx_to_boundary_vertex_idx
-      .resize(vertex_positions_obj_space.len(), None);
-
-    let boundary_faces = boundary_faces_set
-      .into_iter()
-      .map(|boundary_face| {
-        let mut new_boundary_face = [0; 3];
-        for (i, vertex_idx) in boundary_face.iter().enumerate() {
-          let vertex_idx = *vertex_idx as usize;
-          new_boundary_face[i] = if let Some(boundary_vertex_idx) =
-            vertex_idx_to_boundary_vertex_idx[vertex_idx]
-          {
-            boundary_vertex_idx
-          } else {
-            let boundary_vertex_idx = boundary_vertices.len() as u16;
-            boundary_vertices.push(vertex_idx as u16);
-            vertex_idx_to_boundary_vertex_idx[vertex_idx] =
-              Some(boundary_vertex_idx);
-
-            boundary_vertex_idx
-          };
-        }
+    let (vertex_tetra_starts, vertex_tetras) =
+      Self::vertex_tetras(vertex_positions_obj_space.len(), &tetras);
+    let tetra_neighbors = Self::tetra_neighbors(&tetras);
 
-        new_boundary_face
-      })
-      .collect();
+    let fiber_directions = vec![Vector3::zeros(); tetras.len()];
+    let plastic_strains = vec![Matrix3::zeros(); tetras.len()];
+    let inv_plastic_deformations = vec![Matrix3::identity(); tetras.len()];
 
-    Self {
+    let mut sim_mesh = Self {
       vertex_positions_obj_space,
       vertex_mass,
+      vertex_mass_damping,
+      vertex_air_drag: Vec::new(),
       tetras,
       opposite_normals,
       inv_barycentric_mat,
-      boundary_vertices,
-      boundary_faces,
+      boundary_vertices: Vec::new(),
+      boundary_faces: Vec::new(),
+      tetra_neighbors,
+      vertex_tetra_starts,
+      vertex_tetras,
+      reordering,
+      parallel_threshold: None,
       params,
+      tetra_param_idxs,
+      fiber_directions,
+      plastic_strains,
+      inv_plastic_deformations,
+    };
+    sim_mesh.update_boundary();
+
+    Ok(sim_mesh)
+  }
+
+  // counting sort of the tet corners by vertex, so each vertex's tets stay in
+  // order and forces are summed the same way however they are computed
+  fn vertex_tetras(
+    num_vertices: usize,
+    tetras: &[[u32; 4]],
+  ) -> (Vec<u32>, Vec<(u32, u8)>) {
+    let mut vertex_tetra_starts = vec![0; num_vertices + 1];
+    for tetra in tetras {
+      for vertex_idx in tetra {
+        vertex_tetra_starts[*vertex_idx as usize + 1] += 1;
+      }
+    }
+    for vertex_idx in 0..num_vertices {
+      vertex_tetra_starts[vertex_idx + 1] += vertex_tetra_starts[vertex_idx];
+    }
+    let mut vertex_tetras = vec![(0, 0); 4 * tetras.len()];
+    let mut next_idxs = vertex_tetra_starts.clone();
+    for (tetra_idx, tetra) in tetras.iter().enumerate() {
+      for (corner, vertex_idx) in tetra.iter().enumerate() {
+        let next_idx = &mut next_idxs[*vertex_idx as usize];
+        vertex_tetras[*next_idx as usize] = (tetra_idx as u32, corner as u8);
+        *next_idx += 1;
+      }
+    }
+
+    (vertex_tetra_starts, vertex_tetras)
+  }
+
+  // pairs up the tets sharing each face
+  fn tetra_neighbors(tetras: &[[u32; 4]]) -> Vec<[Option<u32>; 4]> {
+    let mut neighbors = vec![[None; 4]; tetras.len()];
+    let mut unpaired: HashMap<_, (usize, usize)> = HashMap::new();
+    for (tetra_idx, tetra) in tetras.iter().enumerate() {
+      for corner in 0..4 {
+        let mut face = Self::face_vertices(*tetra, corner);
+        face.sort_unstable();
+
+        match unpaired.remove(&face) {
+          Some((other_idx, other_corner)) => {
+            neighbors[tetra_idx][corner] = Some(other_idx as u32);
+            neighbors[other_idx][other_corner] = Some(tetra_idx as u32);
+          }
+          None => {
+            unpaired.insert(face, (tetra_idx, corner));
+          }
+        }
+      }
+    }
+
+    neighbors
+  }
+
+  // faces without a neighbor, oriented outward
+  fn update_boundary(&mut self) {
+    let positions = &self.vertex_positions_obj_space;
+    let mut boundary_vertices = Vec::new();
+    let mut vertex_idx_to_boundary_vertex_idx = vec![None; positions.len()];
+    let mut boundary_faces = Vec::new();
+    let mut vertex_air_drag = vec![0.0; positions.len()];
+
+    for (tetra_idx, (tetra, neighbors)) in
+      self.tetras.iter().zip(&self.tetra_neighbors).enumerate()
+    {
+      for (corner, neighbor) in neighbors.iter().enumerate() {
+        if neighbor.is_some() {
+          continue;
+        }
+
+        let mut face = Self::face_vertices(*tetra, corner);
+        let vertices = [
+          positions[face[0] as usize],
+          positions[face[1] as usize],
+          positions[face[2] as usize],
+        ];
+        let normal =
+          (vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0]));
+        if normal.dot(&self.opposite_normals[tetra_idx][corner]) < 0.0 {
+          face.swap(1, 2);
+        }
+
+        let air_drag = self.params[self.tetra_param_idxs[tetra_idx] as usize]
+          .air_drag
+          * normal.norm()
+          / 6.0;
+        for vertex_idx in &face {
+          vertex_air_drag[*vertex_idx as usize] += air_drag;
+        }
+
+        let mut boundary_face = [0; 3];
+        for (boundary_vertex_idx, vertex_idx) in
+          boundary_face.iter_mut().zip(&face)
+        {
+          let vertex_idx = *vertex_idx as usize;
+          *boundary_vertex_idx = *vertex_idx_to_boundary_vertex_idx[vertex_idx]
+            .get_or_insert_with(|| {
+              boundary_vertices.push(vertex_idx as u32);
+
+              boundary_vertices.len() as u32 - 1
+            });
+        }
+
+        boundary_faces.push(boundary_face);
+      }
     }
+
+    self.boundary_vertices = boundary_vertices;
+    self.boundary_faces = boundary_faces;
+    self.vertex_air_drag = vertex_air_drag;
   }
 
-  pub fn num_vertices(&self) -> u16 {
-    self.vertex_mass.len() as u16
+  /// Forces are computed in parallel for meshes with at least this many
+  /// tets. None uses `DEFAULT_PARALLEL_THRESHOLD` when rayon has more than
+  /// one thread.
+  pub fn set_parallel_threshold(&mut self, threshold: Option<usize>) {
+    self.parallel_threshold = threshold;
+  }
+
+  pub fn num_vertices(&self) -> u32 {
+    self.vertex_mass.len() as u32
   }
 
   pub fn vertices_obj_space(&self) -> &[Vector3<S>] {
     &self.vertex_positions_obj_space
   }
 
-  fn get_mat(
-    &self,
-    tetra: [u16; 4],
+  pub fn tetras(&self) -> &[[u32; 4]] {
+    &self.tetras
+  }
+
+  /// the distinct parameters used by the tets
+  pub fn params(&self) -> &[MeshParams] {
+    &self.params
+  }
+
+  /// index into `params` of each tet
+  pub fn tetra_param_idxs(&self) -> &[u32] {
+    &self.tetra_param_idxs
+  }
+
+  /// Fiber direction of each tet in the rest configuration, in the order of
+  /// the loaded mesh. Directions are normalized, zero vectors leave the tet
+  /// without fibers. Fibers only have an effect with a nonzero
+  /// `MeshParams::fiber_stiffness`. Panics if there isn't a direction for
+  /// every tet.
+  pub fn set_fiber_directions(&mut self, directions: &[Vector3<S>]) {
+    assert_eq!(directions.len(), self.tetras.len());
+
+    self.fiber_directions = self
+      .reordering
+      .tetras
+      .iter()
+      .map(|original_idx| {
+        let direction = directions[*original_idx as usize];
+        direction.try_normalize(0.0).unwrap_or_else(Vector3::zeros)
+      })
+      .collect();
+  }
+
+  /// unit fiber direction of each tet, zero if it has none
+  pub fn fiber_directions(&self) -> &[Vector3<S>] {
+    &self.fiber_directions
+  }
+
+  /// plastic strain of each tet, see `update_plasticity`
+  pub fn plastic_strains(&self) -> &[Matrix3<S>] {
+    &self.plastic_strains
+  }
+
+  /// original index of each vertex and tet
+  pub fn reordering(&self) -> &Reordering {
+    &self.reordering
+  }
+
+  /// Per vertex values in the order of the loaded mesh, vertices added by
+  /// `fracture` follow those of the loaded mesh.
+  pub fn vertex_values_original_order<T: Clone>(&self, values: &[T]) -> Vec<T> {
+    Reordering::to_original(&self.reordering.vertices, values)
+  }
+
+  /// per tet values in the order of the loaded mesh
+  pub fn tetra_values_original_order<T: Clone>(&self, values: &[T]) -> Vec<T> {
+    Reordering::to_original(&self.reordering.tetras, values)
+  }
+
+  /// tets as loaded, in the original order and numbering
+  pub fn tetras_original_order(&self) -> Vec<[u32; 4]> {
+    let tetras: Vec<_> = self
+      .tetras
+      .iter()
+      .map(|tetra| {
+        let mut original = [0; 4];
+        for (original_idx, idx) in original.iter_mut().zip(tetra) {
+          *original_idx = self.reordering.vertices[*idx as usize];
+        }
+
+        original
+      })
+      .collect();
+
+    self.tetra_values_original_order(&tetras)
+  }
+
+  // deformation gradient and its rate of change
+  fn deformation_grads(
+    tetra: [u32; 4],
     inv_barycentric_mat: &Matrix3<S>,
     positions: &[Vector3<S>],
     velocities: &[Vector3<S>],
-  ) -> Matrix3<S> {
+  ) -> (Matrix3<S>, Matrix3<S>) {
     let compute_deformation_grad = |vals: &[Vector3<S>]| {
       let val_edges = Self::tetra_val_edges(tetra, vals);
 
       val_edges * inv_barycentric_mat
     };
 
-    let deformation_grad = compute_deformation_grad(positions);
-    let velocity_deformation_grad = compute_deformation_grad(velocities);
+    (
+      compute_deformation_grad(positions),
+      compute_deformation_grad(velocities),
+    )
+  }
+
+  fn tetra_params(&self, tetra_idx: usize) -> &MeshParams {
+    &self.params[self.tetra_param_idxs[tetra_idx] as usize]
+  }
+
+  // maps edges to the elastic part of the deformation gradient, the rest
+  // shape includes the plastic deformation
+  fn inv_rest_mat(&self, tetra_idx: usize) -> Matrix3<S> {
+    self.inv_barycentric_mat[tetra_idx]
+      * self.inv_plastic_deformations[tetra_idx]
+  }
+
+  // differential of the elastic stress, including the fibers
+  fn elastic_stress_differential(
+    &self,
+    tetra_idx: usize,
+    deformation_grad: &Matrix3<S>,
+    d_deformation_grad: &Matrix3<S>,
+  ) -> Matrix3<S> {
+    let params = self.tetra_params(tetra_idx);
+
+    params.material.stress_differential(
+      deformation_grad,
+      d_deformation_grad,
+      params.incompressibility,
+      params.rigidity,
+    ) + fiber_stress_differential(
+      deformation_grad,
+      d_deformation_grad,
+      &self.fiber_directions[tetra_idx],
+      params.fiber_stiffness,
+    )
+  }
 
-    let elastic_strain =
-      deformation_grad.transpose() * deformation_grad - Matrix3::identity();
+  // first Piola-Kirchhoff stress, elastic (including the fibers), viscous and
+  // stiffness proportional damping
+  fn get_mat(
+    &self,
+    tetra_idx: usize,
+    positions: &[Vector3<S>],
+    velocities: &[Vector3<S>],
+  ) -> Matrix3<S> {
+    let params = self.tetra_params(tetra_idx);
+    let (deformation_grad, velocity_deformation_grad) = Self::deformation_grads(
+      self.tetras[tetra_idx],
+      &self.inv_rest_mat(tetra_idx),
+      positions,
+      velocities,
+    );
 
     let viscous_strain = deformation_grad.transpose()
       * velocity_deformation_grad
       + velocity_deformation_grad.transpose() * deformation_grad;
 
-    let strain_to_stress = |strain: Matrix3<_>, incompressibility, rigidity| {
-      incompressibility * Matrix3::identity() * strain.trace()
-        + 2.0 * rigidity * strain
+    let viscous_stress = strain_to_stress(
+      viscous_strain,
+      params.viscous_incompressibility,
+      params.viscous_rigidity,
+    );
+
+    let stress = params.material.stress(
+      &deformation_grad,
+      params.incompressibility,
+      params.rigidity,
+    ) + fiber_stress(
+      &deformation_grad,
+      &self.fiber_directions[tetra_idx],
+      params.fiber_stiffness,
+    ) + deformation_grad * viscous_stress;
+
+    let damping_stress = if params.stiffness_damping != 0.0 {
+      params.stiffness_damping
+        * self.elastic_stress_differential(
+          tetra_idx,
+          &deformation_grad,
+          &velocity_deformation_grad,
+        )
+    } else {
+      Matrix3::zeros()
     };
 
-    let elastic_stress = strain_to_stress(
-      elastic_strain,
-      self.params.incompressibility,
-      self.params.rigidity,
-    );
+    // the stresses are for the elastic part of the deformation
+    (stress + damping_stress)
+      * self.inv_plastic_deformations[tetra_idx].transpose()
+  }
+
+  /// Elastic strain (`F^T F - I` without the plastic deformation) and total
+  /// (elastic and viscous) first Piola-Kirchhoff stress of each tet
+  pub fn tetra_strains_stresses(
+    &self,
+    positions: &[Vector3<S>],
+    velocities: &[Vector3<S>],
+  ) -> Vec<(Matrix3<S>, Matrix3<S>)> {
+    (0..self.tetras.len())
+      .map(|tetra_idx| {
+        let (deformation_grad, _) = Self::deformation_grads(
+          self.tetras[tetra_idx],
+          &self.inv_rest_mat(tetra_idx),
+          positions,
+          velocities,
+        );
+        let strain =
+          deformation_grad.transpose() * deformation_grad - Matrix3::identity();
+
+        (strain, self.get_mat(tetra_idx, positions, velocities))
+      })
+      .collect()
+  }
 
+  // directional derivative of get_mat along (d_positions, d_velocities)
+  fn get_mat_differential(
+    &self,
+    tetra_idx: usize,
+    positions: &[Vector3<S>],
+    velocities: &[Vector3<S>],
+    d_positions: &[Vector3<S>],
+    d_velocities: &[Vector3<S>],
+  ) -> Matrix3<S> {
+    let params = self.tetra_params(tetra_idx);
+    let tetra = self.tetras[tetra_idx];
+    let inv_rest_mat = self.inv_rest_mat(tetra_idx);
+    let (deformation_grad, velocity_deformation_grad) =
+      Self::deformation_grads(tetra, &inv_rest_mat, positions, velocities);
+    let (d_deformation_grad, d_velocity_deformation_grad) =
+      Self::deformation_grads(tetra, &inv_rest_mat, d_positions, d_velocities);
+
+    let viscous_strain = deformation_grad.transpose()
+      * velocity_deformation_grad
+      + velocity_deformation_grad.transpose() * deformation_grad;
+    let d_viscous_strain = d_deformation_grad.transpose()
+      * velocity_deformation_grad
+      + deformation_grad.transpose() * d_velocity_deformation_grad
+      + d_velocity_deformation_grad.transpose() * deformation_grad
+      + velocity_deformation_grad.transpose() * d_deformation_grad;
+
+    // strain to stress is linear
     let viscous_stress = strain_to_stress(
       viscous_strain,
-      self.params.viscous_incompressibility,
-      self.params.viscous_rigidity,
+      params.viscous_incompressibility,
+      params.viscous_rigidity,
     );
+    let d_viscous_stress = strain_to_stress(
+      d_viscous_strain,
+      params.viscous_incompressibility,
+      params.viscous_rigidity,
+    );
+
+    let mut d_stress = self.elastic_stress_differential(
+      tetra_idx,
+      &deformation_grad,
+      &d_deformation_grad,
+    ) + d_deformation_grad * viscous_stress
+      + deformation_grad * d_viscous_stress;
+
+    if params.stiffness_damping != 0.0 {
+      let damping_stress = |deformation_grad: &Matrix3<S>| {
+        self.elastic_stress_differential(
+          tetra_idx,
+          deformation_grad,
+          &velocity_deformation_grad,
+        )
+      };
+
+      // the change of the stiffness with the deformation, by central
+      // differences as there are no second derivatives of the stresses
+      let d_norm = d_deformation_grad.norm();
+      let stiffness_change = if d_norm > 0.0 {
+        let step = STIFFNESS_FD_STEP * (1.0 + deformation_grad.norm()) / d_norm;
+        (damping_stress(&(deformation_grad + step * d_deformation_grad))
+          - damping_stress(&(deformation_grad - step * d_deformation_grad)))
+          / (2.0 * step)
+      } else {
+        Matrix3::zeros()
+      };
+
+      d_stress += params.stiffness_damping
+        * (stiffness_change
+          + self.elastic_stress_differential(
+            tetra_idx,
+            &deformation_grad,
+            &d_velocity_deformation_grad,
+          ));
+    }
+
+    d_stress * self.inv_plastic_deformations[tetra_idx].transpose()
+  }
+
+  /// Absorbs elastic strain beyond the yield strain of each tet into its
+  /// plastic strain for a step of `time_step`, which changes its rest shape
+  /// (see `flow_plastic_strain`). Meant to be called between integrator
+  /// steps, the forces within a step use a fixed rest shape. Does nothing
+  /// for meshes without a `creep_rate`. Returns whether any rest shape
+  /// changed.
+  pub fn update_plasticity(
+    &mut self,
+    positions: &[Vector3<S>],
+    time_step: S,
+  ) -> bool {
+    if self.params.iter().all(|params| params.creep_rate <= 0.0) {
+      return false;
+    }
+
+    let plastic_strains = self.tetra_mats(self.use_par(), |tetra_idx| {
+      let params = self.tetra_params(tetra_idx);
+      let plastic_strain = &self.plastic_strains[tetra_idx];
+      if params.creep_rate <= 0.0 {
+        return *plastic_strain;
+      }
+
+      let deformation_grad =
+        Self::tetra_val_edges(self.tetras[tetra_idx], positions)
+          * self.inv_rest_mat(tetra_idx);
+
+      flow_plastic_strain(
+        &deformation_grad,
+        plastic_strain,
+        params.yield_strain,
+        params.creep_rate * time_step,
+        params.max_plastic_strain,
+      )
+    });
+
+    let mut changed = false;
+    for ((plastic_strain, inv_plastic_deformation), new_plastic_strain) in self
+      .plastic_strains
+      .iter_mut()
+      .zip(&mut self.inv_plastic_deformations)
+      .zip(plastic_strains)
+    {
+      if new_plastic_strain != *plastic_strain {
+        changed = true;
+        *plastic_strain = new_plastic_strain;
+        // positive definite as the plastic strain has a norm below 1
+        *inv_plastic_deformation = (Matrix3::identity() + new_plastic_strain)
+          .try_inverse()
+          .unwrap_or_else(Matrix3::identity);
+      }
+    }
+
+    changed
+  }
+
+  // symmetric Cauchy stress, zero for inverted tets
+  fn cauchy_stress(
+    &self,
+    tetra_idx: usize,
+    positions: &[Vector3<S>],
+    velocities: &[Vector3<S>],
+  ) -> Matrix3<S> {
+    let edges = Self::tetra_val_edges(self.tetras[tetra_idx], positions);
+    let volume_ratio = (edges * self.inv_rest_mat(tetra_idx)).determinant();
+    if volume_ratio <= 0.0 {
+      return Matrix3::zeros();
+    }
+
+    // the plastic deformation cancels out of P F^T
+    let stress = self.get_mat(tetra_idx, positions, velocities)
+      * (edges * self.inv_barycentric_mat[tetra_idx]).transpose()
+      / volume_ratio;
+
+    (stress + stress.transpose()) / 2.0
+  }
+
+  /// Separates each tet whose largest principal stress exceeds its
+  /// `MeshParams::toughness` from the neighbor across the face most
+  /// perpendicular to that stress. A vertex is duplicated once the tets
+  /// around it are no longer all connected through faces, so cracks open as
+  /// they grow through the mesh. New vertices are appended and the vertex
+  /// each was split from is returned, their positions and velocities should
+  /// start as those of that vertex. Masses and the boundary are updated to
+  /// match. Meant to be called between integrator steps like
+  /// `update_plasticity`, does nothing for meshes without a toughness.
+  pub fn fracture(
+    &mut self,
+    positions: &[Vector3<S>],
+    velocities: &[Vector3<S>],
+  ) -> Vec<u32> {
+    if self.params.iter().all(|params| params.toughness.is_none()) {
+      return Vec::new();
+    }
+
+    let stresses = self.tetra_mats(self.use_par(), |tetra_idx| {
+      self.cauchy_stress(tetra_idx, positions, velocities)
+    });
+
+    let mut cut_vertices = Vec::new();
+    for (tetra_idx, stress) in stresses.iter().enumerate() {
+      let toughness = match self.tetra_params(tetra_idx).toughness {
+        Some(toughness) => toughness,
+        None => continue,
+      };
+
+      let eigen = stress.symmetric_eigen();
+      let (principal_idx, principal_stress) = eigen.eigenvalues.argmax();
+      if principal_stress <= toughness {
+        continue;
+      }
+      let direction = eigen.eigenvectors.column(principal_idx);
+
+      // the crack is perpendicular to the principal direction
+      let tetra = self.tetras[tetra_idx];
+      let cut_corner = (0..4)
+        .filter(|corner| self.tetra_neighbors[tetra_idx][*corner].is_some())
+        .map(|corner| {
+          let face = Self::face_vertices(tetra, corner);
+          let get_vertex = |idx: usize| positions[face[idx] as usize];
+          let normal = (get_vertex(1) - get_vertex(0))
+            .cross(&(get_vertex(2) - get_vertex(0)));
+          let alignment = normal
+            .try_normalize(0.0)
+            .map_or(0.0, |normal| normal.dot(&direction).abs());
+
+          (corner, alignment)
+        })
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .map(|(corner, _)| corner);
+
+      if let Some(corner) = cut_corner {
+        if let Some(neighbor_idx) = self.tetra_neighbors[tetra_idx][corner] {
+          for neighbor in &mut self.tetra_neighbors[neighbor_idx as usize] {
+            if *neighbor == Some(tetra_idx as u32) {
+              *neighbor = None;
+            }
+          }
+        }
+        self.tetra_neighbors[tetra_idx][corner] = None;
+
+        cut_vertices.extend_from_slice(&Self::face_vertices(tetra, corner));
+      }
+    }
+
+    if cut_vertices.is_empty() {
+      return Vec::new();
+    }
+
+    cut_vertices.sort_unstable();
+    cut_vertices.dedup();
+
+    let mut sources = Vec::new();
+    for vertex_idx in cut_vertices {
+      self.split_vertex(vertex_idx, &mut sources);
+    }
+
+    let (vertex_tetra_starts, vertex_tetras) =
+      Self::vertex_tetras(self.vertex_mass.len(), &self.tetras);
+    self.vertex_tetra_starts = vertex_tetra_starts;
+    self.vertex_tetras = vertex_tetras;
+    self.update_boundary();
+
+    sources
+  }
+
+  // gives each group of the vertex's tets that is connected through faces,
+  // other than the first, its own copy of the vertex
+  fn split_vertex(&mut self, vertex_idx: u32, sources: &mut Vec<u32>) {
+    let start = self.vertex_tetra_starts[vertex_idx as usize] as usize;
+    let end = self.vertex_tetra_starts[vertex_idx as usize + 1] as usize;
+    let vertex_tetras = self.vertex_tetras[start..end].to_vec();
+
+    let mut groups = vec![None; vertex_tetras.len()];
+    let mut num_groups = 0;
+    for first in 0..vertex_tetras.len() {
+      if groups[first].is_some() {
+        continue;
+      }
+
+      groups[first] = Some(num_groups);
+      let mut stack = vec![first];
+      while let Some(local_idx) = stack.pop() {
+        let (tetra_idx, corner) = vertex_tetras[local_idx];
+
+        // the faces opposite the other corners contain the vertex
+        for (other_corner, neighbor) in
+          self.tetra_neighbors[tetra_idx as usize].iter().enumerate()
+        {
+          if other_corner == corner as usize {
+            continue;
+          }
+
+          if let Some(neighbor_idx) = neighbor {
+            let neighbor_local_idx = vertex_tetras
+              .iter()
+              .position(|(idx, _)| idx == neighbor_idx)
+              .unwrap();
+
+            if groups[neighbor_local_idx].is_none() {
+              groups[neighbor_local_idx] = Some(num_groups);
+              stack.push(neighbor_local_idx);
+            }
+          }
+        }
+      }
+
+      num_groups += 1;
+    }
+
+    let first_new_idx = self.vertex_mass.len() as u32;
+    for _ in 1..num_groups {
+      let new_idx = self.vertex_mass.len() as u32;
+      self
+        .vertex_positions_obj_space
+        .push(self.vertex_positions_obj_space[vertex_idx as usize]);
+      self.vertex_mass.push(0.0);
+      self.vertex_mass_damping.push(0.0);
+      // duplicates follow the loaded vertices in the original order
+      self.reordering.vertices.push(new_idx);
+      sources.push(vertex_idx);
+    }
+
+    for ((tetra_idx, corner), group) in vertex_tetras.iter().zip(groups) {
+      let group = group.unwrap();
+      if group == 0 {
+        continue;
+      }
+
+      let tetra_idx = *tetra_idx as usize;
+      let new_idx = first_new_idx + group - 1;
+      let params = self.tetra_params(tetra_idx);
+      let mass = params.density
+        * Self::tetra_volume(&Self::tetra_val_edges(
+          self.tetras[tetra_idx],
+          &self.vertex_positions_obj_space,
+        ))
+        / 4.0;
+      let mass_damping = params.mass_damping * mass;
+
+      self.tetras[tetra_idx][*corner as usize] = new_idx;
+      self.vertex_mass[vertex_idx as usize] -= mass;
+      self.vertex_mass[new_idx as usize] += mass;
+      self.vertex_mass_damping[vertex_idx as usize] -= mass_damping;
+      self.vertex_mass_damping[new_idx as usize] += mass_damping;
+    }
+  }
+
+  fn use_par(&self) -> bool {
+    let threshold = self.parallel_threshold.unwrap_or_else(|| {
+      if rayon::current_num_threads() > 1 {
+        DEFAULT_PARALLEL_THRESHOLD
+      } else {
+        usize::MAX
+      }
+    });
+
+    self.tetras.len() >= threshold
+  }
+
+  fn tetra_mats<F>(&self, use_par: bool, get_mat: F) -> Vec<Matrix3<S>>
+  where
+    F: Fn(usize) -> Matrix3<S> + Sync,
+  {
+    if use_par {
+      (0..self.tetras.len())
+        .into_par_iter()
+        .map(&get_mat)
+        .collect::<Vec<_>>()
+    } else {
+      (0..self.tetras.len()).map(get_mat).collect::<Vec<_>>()
+    }
+  }
 
-    let stress = elastic_stress + viscous_stress;
+  // each vertex gathers the forces from its tets, so vertices are
+  // independent
+  fn add_tetra_forces(
+    &self,
+    use_par: bool,
+    mats: &[Matrix3<S>],
+    forces: &mut [Vector3<S>],
+  ) {
+    let gather = |(vertex_idx, force): (usize, &mut Vector3<S>)| {
+      let start = self.vertex_tetra_starts[vertex_idx] as usize;
+      let end = self.vertex_tetra_starts[vertex_idx + 1] as usize;
+
+      for (tetra_idx, corner) in &self.vertex_tetras[start..end] {
+        let tetra_idx = *tetra_idx as usize;
+        *force +=
+          mats[tetra_idx] * self.opposite_normals[tetra_idx][*corner as usize];
+      }
+    };
 
-    deformation_grad * stress
+    if use_par {
+      forces.par_iter_mut().enumerate().for_each(gather);
+    } else {
+      forces.iter_mut().enumerate().for_each(gather);
+    }
   }
 
   pub fn vertex_accels(
@@ -249,39 +1098,21 @@ impl SimMesh {
     g: S,
   ) -> Vec<Vector3<S>> {
     let mut forces = forces.to_vec();
+    let use_par = self.use_par();
 
-    let use_par = positions.len() > 300;
+    let mats = self.tetra_mats(use_par, |tetra_idx| {
+      self.get_mat(tetra_idx, positions, velocities)
+    });
 
-    let mats = if use_par {
-      self
-        .tetras
-        .par_iter()
-        .zip(self.inv_barycentric_mat.par_iter())
-        .map(|(tetra, inv_barycentric_mat)| {
-          self.get_mat(*tetra, inv_barycentric_mat, positions, velocities)
-        })
-        .collect::<Vec<_>>()
-    } else {
-      self
-        .tetras
-        .iter()
-        .zip(self.inv_barycentric_mat.iter())
-        .map(|(tetra, inv_barycentric_mat)| {
-          self.get_mat(*tetra, inv_barycentric_mat, positions, velocities)
-        })
-        .collect::<Vec<_>>()
-    };
+    self.add_tetra_forces(use_par, &mats, &mut forces);
 
-    for ((mat, opposite_normals), tetra) in
-      mats.iter().zip(&self.opposite_normals).zip(&self.tetras)
+    // mass proportional damping and air drag
+    for ((force, velocity), (mass_damping, air_drag)) in forces
+      .iter_mut()
+      .zip(velocities)
+      .zip(self.vertex_mass_damping.iter().zip(&self.vertex_air_drag))
     {
-      for (vertex_idx, opposite_normal) in
-        tetra.iter().zip(opposite_normals.iter())
-      {
-        let force = mat * opposite_normal;
-
-        forces[*vertex_idx as usize] += force;
-      }
+      *force -= (mass_damping + air_drag * velocity.norm()) * velocity;
     }
 
     // gravity
@@ -300,10 +1131,59 @@ impl SimMesh {
       .collect()
   }
 
+  /// Directional derivative of `vertex_accels` along (d_positions,
+  /// d_velocities). Gravity is constant so only the differential of the
+  /// external forces is needed.
+  pub fn vertex_accels_differential(
+    &self,
+    positions: &[Vector3<S>],
+    velocities: &[Vector3<S>],
+    d_positions: &[Vector3<S>],
+    d_velocities: &[Vector3<S>],
+    d_forces: &[Vector3<S>],
+  ) -> Vec<Vector3<S>> {
+    let mut d_forces = d_forces.to_vec();
+    let use_par = self.use_par();
+
+    let mats = self.tetra_mats(use_par, |tetra_idx| {
+      self.get_mat_differential(
+        tetra_idx,
+        positions,
+        velocities,
+        d_positions,
+        d_velocities,
+      )
+    });
+
+    self.add_tetra_forces(use_par, &mats, &mut d_forces);
+
+    for (((d_force, velocity), d_velocity), (mass_damping, air_drag)) in
+      d_forces
+        .iter_mut()
+        .zip(velocities)
+        .zip(d_velocities)
+        .zip(self.vertex_mass_damping.iter().zip(&self.vertex_air_drag))
+    {
+      *d_force -= *mass_damping * d_velocity;
+
+      let speed = velocity.norm();
+      if speed > 0.0 {
+        *d_force -= *air_drag
+          * (speed * d_velocity + velocity * velocity.dot(d_velocity) / speed);
+      }
+    }
+
+    d_forces
+      .iter()
+      .zip(&self.vertex_mass)
+      .map(|(d_force, mass)| d_force / *mass)
+      .collect()
+  }
+
   pub fn boundary_vertices_faces(
     &self,
     positions: &[Vector3<S>],
-  ) -> (Vec<Point3<f32>>, Vec<Point3<u16>>) {
+  ) -> (Vec<Point3<f32>>, Vec<Point3<u32>>) {
     (
       self
         .boundary_vertices
@@ -330,12 +1210,21 @@ fn basic_params() -> MeshParams {
     rigidity: 1.0,
     viscous_incompressibility: 1.0,
     viscous_rigidity: 1.0,
+    mass_damping: 0.0,
+    stiffness_damping: 0.0,
+    air_drag: 0.0,
     density: 1.0,
+    material: Material::StVenantKirchhoff,
+    fiber_stiffness: 0.0,
+    yield_strain: 0.05,
+    creep_rate: 0.0,
+    max_plastic_strain: 0.5,
+    toughness: None,
   }
 }
 
 #[cfg(test)]
-type MeshInfo = (SimMesh, Vec<Vector3<S>>, Vec<[u16; 4]>);
+type MeshInfo = (SimMesh, Vec<Vector3<S>>, Vec<[u32; 4]>);
 
 #[cfg(test)]
 #[derive(Debug, Arbitrary)]
@@ -359,7 +1248,8 @@ impl MeshOptions {
         ];
         let tetras = vec![[0, 1, 2, 3]];
         let mesh =
-          SimMesh::new((to_f32(&positions), tetras.clone()), params.clone());
+          SimMesh::new((to_f32(&positions), tetras.clone()), params.clone())
+            .unwrap();
 
         (mesh, positions, tetras)
       }
@@ -373,7 +1263,8 @@ impl MeshOptions {
         ];
         let tetras = vec![[0, 1, 2, 3], [4, 1, 2, 3]];
         let mesh =
-          SimMesh::new((to_f32(&positions), tetras.clone()), params.clone());
+          SimMesh::new((to_f32(&positions), tetras.clone()), params.clone())
+            .unwrap();
 
         (mesh, positions, tetras)
       }
@@ -381,17 +1272,586 @@ impl MeshOptions {
   }
 }
 
+#[test]
+fn degenerate_tet_error() {
+  let positions = vec![
+    Vector3::new(0.0, 0.0, 0.0),
+    Vector3::new(1.0, 0.0, 0.0),
+    Vector3::new(0.0, 1.0, 0.0),
+    Vector3::new(0.0, 0.0, 1.0),
+    Vector3::new(1.0, 1.0, 0.0),
+  ];
+
+  match SimMesh::new(
+    (positions.clone(), vec![[0, 1, 2, 3], [0, 1, 2, 4]]),
+    basic_params(),
+  ) {
+    Err(MeshError::DegenerateTetra { tetra: 1 }) => {}
+    _ => panic!("coplanar tet should be degenerate"),
+  }
+
+  match SimMesh::new((positions, vec![[0, 1, 1, 3]]), basic_params()) {
+    Err(MeshError::DegenerateTetra { tetra: 0 }) => {}
+    _ => panic!("repeated vertex should be degenerate"),
+  }
+}
+
+#[test]
+fn inverted_tets_are_accepted() {
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::new(1.0, 1.0, 1.0), [2, 2, 2]);
+  let positions: Vec<Vector3<S>> = vertices
+    .iter()
+    .map(|v| {
+      nalgebra::convert::<_, Vector3<S>>(*v)
+        .component_mul(&Vector3::new(1.1, 1.0, 0.9))
+    })
+    .collect();
+  let velocities: Vec<_> = positions.iter().map(|v| v.yzx()).collect();
+  let zeros = vec![Vector3::zeros(); positions.len()];
+
+  let accels = |tetras: Vec<[u32; 4]>| {
+    SimMesh::new((vertices.clone(), tetras), basic_params())
+      .unwrap()
+      .vertex_accels(&positions, &velocities, &zeros, 9.8)
+  };
+
+  let mut flipped = tetras.clone();
+  flipped[3].swap(0, 1);
+  for (accel, expected) in accels(flipped).iter().zip(&accels(tetras)) {
+    assert!((accel - expected).norm() < 1e-9 * (1.0 + expected.norm()));
+  }
+}
+
+// positions deformed so there are elastic forces, along with velocities
+#[cfg(test)]
+fn deformed_state(
+  vertices: &[Vector3<f32>],
+) -> (Vec<Vector3<S>>, Vec<Vector3<S>>) {
+  let positions: Vec<Vector3<S>> = vertices
+    .iter()
+    .map(|v| {
+      let v: Vector3<S> = nalgebra::convert(*v);
+      v + Vector3::new(0.1 * v[1] * v[1], 0.2 * v[0], -0.1 * v[2])
+    })
+    .collect();
+  let velocities = positions.iter().map(|v| v.yzx()).collect();
+
+  (positions, velocities)
+}
+
+// accelerations with positions and velocities in the original vertex order,
+// returned in the original order
+#[cfg(test)]
+fn original_order_accels(
+  mesh: &SimMesh,
+  positions: &[Vector3<S>],
+  velocities: &[Vector3<S>],
+) -> Vec<Vector3<S>> {
+  let reorder = |vals: &[Vector3<S>]| {
+    mesh
+      .reordering()
+      .vertices
+      .iter()
+      .map(|idx| vals[*idx as usize])
+      .collect::<Vec<_>>()
+  };
+  let zeros = vec![Vector3::zeros(); positions.len()];
+
+  mesh.vertex_values_original_order(&mesh.vertex_accels(
+    &reorder(positions),
+    &reorder(velocities),
+    &zeros,
+    9.8,
+  ))
+}
+
+#[test]
+fn reordering_matches_original() {
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::new(2.0, 1.0, 1.0), [4, 2, 3]);
+  let (positions, velocities) = deformed_state(&vertices);
+
+  let original =
+    SimMesh::new((vertices.clone(), tetras.clone()), basic_params()).unwrap();
+  let expected = original_order_accels(&original, &positions, &velocities);
+
+  for ordering in &[VertexOrdering::Rcm, VertexOrdering::Morton] {
+    let mesh = SimMesh::with_ordering(
+      (vertices.clone(), tetras.clone()),
+      basic_params(),
+      *ordering,
+    )
+    .unwrap();
+    assert_eq!(mesh.tetras_original_order(), tetras);
+
+    let accels = original_order_accels(&mesh, &positions, &velocities);
+    for (accel, expected) in accels.iter().zip(&expected) {
+      assert!((accel - expected).norm() < 1e-9 * (1.0 + expected.norm()));
+    }
+  }
+
+  // errors use the original numbering
+  let mut tetras = tetras;
+  tetras[5] = [0, 1, 1, 2];
+  match SimMesh::with_ordering(
+    (vertices, tetras),
+    basic_params(),
+    VertexOrdering::Rcm,
+  ) {
+    Err(MeshError::DegenerateTetra { tetra: 5 }) => {}
+    _ => panic!("tet 5 should be degenerate"),
+  }
+}
+
+#[test]
+fn per_tetra_params() {
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::new(2.0, 1.0, 1.0), [4, 2, 2]);
+  let soft = basic_params();
+  let stiff = MeshParams {
+    rigidity: 10.0,
+    viscous_rigidity: 3.0,
+    density: 3.0,
+    material: Material::NeoHookean,
+    ..basic_params()
+  };
+  // stiff for x > 0
+  let tetra_param_idxs: Vec<u32> = tetras
+    .iter()
+    .map(|tetra| {
+      let centroid_x: f32 =
+        tetra.iter().map(|idx| vertices[*idx as usize][0]).sum();
+      (centroid_x > 0.0) as u32
+    })
+    .collect();
+
+  let (positions, velocities) = deformed_state(&vertices);
+
+  let accels_with = |params: Vec<MeshParams>,
+                     tetra_param_idxs: Vec<u32>,
+                     ordering: VertexOrdering| {
+    let mesh = SimMesh::with_tetra_params(
+      (vertices.clone(), tetras.clone()),
+      params,
+      tetra_param_idxs,
+      ordering,
+    )
+    .unwrap();
+
+    (
+      mesh.vertex_values_original_order(&mesh.vertex_mass),
+      original_order_accels(&mesh, &positions, &velocities),
+    )
+  };
+
+  let (masses, accels) = accels_with(
+    vec![soft.clone(), stiff.clone()],
+    tetra_param_idxs.clone(),
+    VertexOrdering::Original,
+  );
+  // unit volume on each side
+  assert!((masses.iter().sum::<S>() - (1.0 + 3.0)).abs() < 1e-9);
+
+  // vertices away from the middle only see one set of parameters
+  let (_, soft_accels) = accels_with(
+    vec![soft.clone()],
+    vec![0; tetras.len()],
+    VertexOrdering::Original,
+  );
+  let (_, stiff_accels) =
+    accels_with(vec![stiff], vec![0; tetras.len()], VertexOrdering::Original);
+  for (vertex_idx, vertex) in vertices.iter().enumerate() {
+    let expected = match vertex[0] {
+      x if x < -0.25 => soft_accels[vertex_idx],
+      x if x > 0.25 => stiff_accels[vertex_idx],
+      _ => continue,
+    };
+    assert!((accels[vertex_idx] - expected).norm() < 1e-9);
+  }
+
+  // invalid indices are errors naming the tet
+  let mesh_with = |tetra_param_idxs: Vec<u32>| {
+    SimMesh::with_tetra_params(
+      (vertices.clone(), tetras.clone()),
+      vec![soft.clone()],
+      tetra_param_idxs,
+      VertexOrdering::Rcm,
+    )
+  };
+  match mesh_with(vec![0; 3]) {
+    Err(MeshError::ParamIndexCount {
+      count: 3,
+      num_tetras,
+    }) if num_tetras == tetras.len() => {}
+    _ => panic!("expected a parameter index count error"),
+  }
+  let mut bad_idxs = vec![0; tetras.len()];
+  bad_idxs[4] = 1;
+  match mesh_with(bad_idxs) {
+    Err(MeshError::ParamIndexOutOfRange {
+      tetra: 4,
+      param: 1,
+      num_params: 1,
+    }) => {}
+    _ => panic!("tet 4 should have an invalid parameter index"),
+  }
+
+  // parameters follow their tets when renumbered
+  let (_, reordered_accels) = accels_with(
+    vec![soft, basic_params()],
+    tetra_param_idxs.clone(),
+    VertexOrdering::Rcm,
+  );
+  for (accel, expected) in reordered_accels.iter().zip(&soft_accels) {
+    assert!((accel - expected).norm() < 1e-9 * (1.0 + expected.norm()));
+  }
+}
+
+// compares the differential of the accelerations without velocities to
+// central differences
+#[cfg(test)]
+fn assert_static_differential_matches(
+  mesh: &SimMesh,
+  positions: &[Vector3<S>],
+) {
+  let zeros = vec![Vector3::zeros(); positions.len()];
+  let d_positions: Vec<_> = positions.iter().map(|v| v.yzx()).collect();
+  let epsilon = 1e-6;
+  let shifted = |sign: S| {
+    let positions: Vec<_> = positions
+      .iter()
+      .zip(&d_positions)
+      .map(|(v, d_v)| v + sign * epsilon * d_v)
+      .collect();
+    mesh.vertex_accels(&positions, &zeros, &zeros, 0.0)
+  };
+
+  let d_accels = mesh.vertex_accels_differential(
+    positions,
+    &zeros,
+    &d_positions,
+    &zeros,
+    &zeros,
+  );
+  for ((d_accel, plus), minus) in
+    d_accels.iter().zip(shifted(1.0)).zip(shifted(-1.0))
+  {
+    let finite_difference = (plus - minus) / (2.0 * epsilon);
+    assert!(
+      (d_accel - finite_difference).norm()
+        < 1e-5 * (1.0 + finite_difference.norm())
+    );
+  }
+}
+
+#[test]
+fn fibers_stiffen_along_their_direction() {
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::new(4.0, 1.0, 1.0), [8, 2, 2]);
+  let params = MeshParams {
+    fiber_stiffness: 20.0,
+    ..basic_params()
+  };
+
+  // force on the end of a beam stretched by 1% along x
+  let end_force = |fiber_direction: Vector3<S>| {
+    let mut mesh =
+      SimMesh::new((vertices.clone(), tetras.clone()), params.clone()).unwrap();
+    mesh.set_fiber_directions(&vec![fiber_direction; tetras.len()]);
+
+    let positions: Vec<Vector3<S>> = vertices
+      .iter()
+      .map(|v| {
+        nalgebra::convert::<_, Vector3<S>>(*v)
+          .component_mul(&Vector3::new(1.01, 1.0, 1.0))
+      })
+      .collect();
+    let zeros = vec![Vector3::zeros(); positions.len()];
+    let accels = mesh.vertex_accels(&positions, &zeros, &zeros, 0.0);
+
+    // the differential includes the fibers
+    assert_static_differential_matches(&mesh, &positions);
+
+    vertices
+      .iter()
+      .zip(accels.iter().zip(&mesh.vertex_mass))
+      .filter(|(v, _)| v[0] > 1.99)
+      .map(|(_, (accel, mass))| accel[0] * mass)
+      .sum::<S>()
+  };
+
+  let isotropic = end_force(Vector3::zeros());
+  let along = end_force(Vector3::new(2.0, 0.0, 0.0));
+  let across = end_force(Vector3::new(0.0, 1.0, 0.0));
+
+  // pulled back, the fibers add about their stiffness times the strain over
+  // the unit cross section
+  assert!(isotropic < 0.0);
+  assert!(
+    along < isotropic - 0.5 * 20.0 * 0.01,
+    "{} vs {}",
+    along,
+    isotropic
+  );
+  assert!((across - isotropic).abs() < 1e-9 * isotropic.abs());
+}
+
+#[test]
+fn plastic_flow_changes_rest_shape() {
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::repeat(1.0), [2, 2, 2]);
+  let params = MeshParams {
+    yield_strain: 0.02,
+    creep_rate: 10.0,
+    max_plastic_strain: 0.5,
+    ..basic_params()
+  };
+  let mut mesh =
+    SimMesh::new((vertices.clone(), tetras.clone()), params).unwrap();
+
+  let rest: Vec<Vector3<S>> =
+    vertices.iter().map(|v| nalgebra::convert(*v)).collect();
+  // squashed along y, keeping the volume
+  let squashed: Vec<_> = rest
+    .iter()
+    .map(|v| {
+      v.component_mul(&Vector3::new(
+        1.0 / 0.8f64.sqrt(),
+        0.8,
+        1.0 / 0.8f64.sqrt(),
+      ))
+    })
+    .collect();
+  let zeros = vec![Vector3::zeros(); rest.len()];
+  let force_norm = |mesh: &SimMesh, positions: &[Vector3<S>]| {
+    mesh
+      .vertex_accels(positions, &zeros, &zeros, 0.0)
+      .iter()
+      .map(|accel| accel.norm())
+      .sum::<S>()
+  };
+  let squashed_force = force_norm(&mesh, &squashed);
+
+  // elastic meshes keep their rest shape
+  let mut elastic = SimMesh::new((vertices, tetras), basic_params()).unwrap();
+  assert!(!elastic.update_plasticity(&squashed, 0.01));
+  assert!(elastic
+    .plastic_strains()
+    .iter()
+    .all(|strain| *strain == Matrix3::zeros()));
+
+  // held squashed for a second
+  for _ in 0..100 {
+    assert!(mesh.update_plasticity(&squashed, 0.01));
+  }
+  for plastic_strain in mesh.plastic_strains() {
+    assert!(plastic_strain[(1, 1)] < -0.1);
+    assert!(plastic_strain.norm() <= 0.5 + 1e-12);
+    assert!(plastic_strain.trace().abs() < 1e-9);
+  }
+
+  // the squashed shape is close to the new rest shape, so the dent stays
+  assert!(force_norm(&mesh, &squashed) < 0.2 * squashed_force);
+  assert!(force_norm(&mesh, &rest) > 0.5 * squashed_force);
+  assert_static_differential_matches(&mesh, &squashed);
+}
+
+// volume enclosed by the boundary, which is only the volume of the mesh if
+// the faces are oriented outward
+#[cfg(test)]
+fn boundary_volume(mesh: &SimMesh, positions: &[Vector3<S>]) -> f32 {
+  let (vertices, faces) = mesh.boundary_vertices_faces(positions);
+
+  faces
+    .iter()
+    .map(|face| {
+      let get_vertex = |idx: usize| vertices[face[idx] as usize].coords;
+      get_vertex(0).dot(&get_vertex(1).cross(&get_vertex(2))) / 6.0
+    })
+    .sum()
+}
+
+#[test]
+fn rayleigh_damping_and_air_drag() {
+  let (vertices, tetras) =
+    crate::mesh::box_mesh(Vector3::repeat(1.0), [2, 2, 2]);
+  let get_mesh =
+    |params| SimMesh::new((vertices.clone(), tetras.clone()), params).unwrap();
+  let rest = get_mesh(basic_params()).vertices_obj_space().to_vec();
+  let zeros = vec![Vector3::zeros(); rest.len()];
+  let forces = |mesh: &SimMesh, velocities: &[Vector3<S>]| {
+    mesh
+      .vertex_accels(&rest, velocities, &zeros, 0.0)
+      .iter()
+      .zip(&mesh.vertex_mass)
+      .map(|(accel, mass)| accel * *mass)
+      .collect::<Vec<_>>()
+  };
+  let inviscid = MeshParams {
+    viscous_incompressibility: 0.0,
+    viscous_rigidity: 0.0,
+    ..basic_params()
+  };
+
+  // mass damping slows every vertex at the same rate
+  let velocity = Vector3::new(0.3, -0.2, 0.1);
+  let translation = vec![velocity; rest.len()];
+  let mesh = get_mesh(MeshParams {
+    mass_damping: 2.0,
+    ..inviscid.clone()
+  });
+  for accel in mesh.vertex_accels(&rest, &translation, &zeros, 0.0) {
+    assert!((accel + 2.0 * velocity).norm() < 1e-12);
+  }
+
+  // air drag is quadratic in the speed and acts on the surface, which has an
+  // area of 6
+  let mesh = get_mesh(MeshParams {
+    air_drag: 0.5,
+    ..inviscid.clone()
+  });
+  let total: Vector3<S> = forces(&mesh, &translation).iter().sum();
+  assert!((total + 0.5 * 6.0 * velocity.norm() * velocity).norm() < 1e-12);
+
+  // stiffness damping doesn't slow rigid rotation but resists stretching
+  let mesh = get_mesh(MeshParams {
+    stiffness_damping: 0.1,
+    ..inviscid
+  });
+  let rotation: Vec<_> = rest
+    .iter()
+    .map(|pos| Vector3::new(0.0, 0.0, 1.0).cross(pos))
+    .collect();
+  assert!(forces(&mesh, &rotation)
+    .iter()
+    .all(|force| force.norm() < 1e-12));
+  let stretching: Vec<_> = rest
+    .iter()
+    .map(|pos| Vector3::new(pos[0], 0.0, 0.0))
+    .collect();
+  let power: S = forces(&mesh, &stretching)
+    .iter()
+    .zip(&stretching)
+    .map(|(force, velocity)| force.dot(velocity))
+    .sum();
+  assert!(power < 0.0);
+}
+
+// two tets sharing the face at z = 0, which separate when stretched along z
+#[cfg(test)]
+pub(crate) fn face_sharing_tetras() -> LoadedMesh {
+  (
+    vec![
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(0.0, 0.0, 1.0),
+      Vector3::new(0.0, 0.0, -1.0),
+    ],
+    vec![[0, 1, 2, 3], [0, 2, 1, 4]],
+  )
+}
+
+#[test]
+fn fracture_separates_stretched_tets() {
+  let (vertices, tetras) = face_sharing_tetras();
+  let positions: Vec<_> = vertices
+    .iter()
+    .map(|vertex| {
+      Vector3::new(vertex[0] as S, vertex[1] as S, 1.5 * vertex[2] as S)
+    })
+    .collect();
+  let velocities = vec![Vector3::zeros(); positions.len()];
+  let get_mesh = |toughness| {
+    let params = MeshParams {
+      toughness: Some(toughness),
+      ..basic_params()
+    };
+    SimMesh::new((vertices.clone(), tetras.clone()), params).unwrap()
+  };
+
+  let mut mesh = get_mesh(1e3);
+  let total_mass: S = mesh.vertex_mass.iter().sum();
+  assert_eq!(mesh.boundary_faces.len(), 6);
+  assert!(
+    (boundary_volume(&mesh, mesh.vertices_obj_space()) - 1.0 / 3.0).abs()
+      < 1e-6
+  );
+  assert!(mesh.fracture(&positions, &velocities).is_empty());
+  assert_eq!(mesh.num_vertices(), 5);
+
+  let mut mesh = get_mesh(0.1);
+  let sources = mesh.fracture(&positions, &velocities);
+  assert_eq!(sources, vec![0, 1, 2]);
+  assert_eq!(mesh.num_vertices(), 8);
+  assert!(mesh.tetras[0]
+    .iter()
+    .all(|idx| !mesh.tetras[1].contains(idx)));
+
+  // each tet keeps its own mass and is now closed on its own
+  assert!((mesh.vertex_mass.iter().sum::<S>() - total_mass).abs() < 1e-12);
+  assert!(mesh.vertex_mass.iter().all(|mass| *mass > 0.0));
+  let mut positions = positions;
+  for idx in &sources {
+    positions.push(positions[*idx as usize]);
+  }
+  assert_eq!(mesh.boundary_faces.len(), 8);
+  assert!((boundary_volume(&mesh, &positions) - 1.5 / 3.0).abs() < 1e-6);
+
+  // duplicates follow the loaded vertices
+  let original = mesh.vertex_values_original_order(&positions);
+  assert_eq!(original[..3], positions[..3]);
+  assert_eq!(original[5..], positions[5..]);
+
+  // nothing is left to separate
+  let velocities = vec![Vector3::zeros(); positions.len()];
+  assert!(mesh.fracture(&positions, &velocities).is_empty());
+}
+
+#[test]
+fn parallel_matches_serial() {
+  let (vertices, tetras) = crate::mesh::sphere_mesh(1.0, 6);
+  let positions: Vec<Vector3<S>> = vertices
+    .iter()
+    .map(|v| nalgebra::convert::<_, Vector3<S>>(*v) * 1.1)
+    .collect();
+  let velocities: Vec<_> = positions.iter().map(|v| v.zxy()).collect();
+  let zeros = vec![Vector3::zeros(); positions.len()];
+
+  let mut mesh = SimMesh::new((vertices, tetras), basic_params()).unwrap();
+
+  let mut accels = Vec::new();
+  for threshold in &[usize::MAX, 0] {
+    mesh.set_parallel_threshold(Some(*threshold));
+    accels.push((
+      mesh.vertex_accels(&positions, &velocities, &zeros, 9.8),
+      mesh.vertex_accels_differential(
+        &positions,
+        &velocities,
+        &velocities,
+        &positions,
+        &zeros,
+      ),
+    ));
+  }
+
+  // summed in the same order
+  assert_eq!(accels[0], accels[1]);
+  assert!(accels[0].0.iter().any(|accel| accel.norm() > 1.0));
+}
+
 #[test]
 fn single_tet_basic() {
   let params = basic_params();
   let (mesh, positions, tetras) = MeshOptions::SingleTet.get_mesh(&params);
 
   assert_eq!(mesh.vertex_positions_obj_space, positions);
-  assert_eq!(mesh.params, params);
+  assert_eq!(mesh.params, vec![params.clone()]);
   assert_eq!(mesh.tetras, tetras);
 
   // assert_eq!(
-  //   HashSet::<[u16; 3]>::from_iter(mesh.boundary_faces.iter().cloned().map(
+  //   HashSet::<[u32; 3]>::from_iter(mesh.boundary_faces.iter().cloned().map(
   //     // Sort to avoid ordering issues
   //     |mut v| {
   //       v.sort();
@@ -422,11 +1882,11 @@ fn double_tet_basic() {
   let (mesh, positions, tetras) = MeshOptions::DoubleTet.get_mesh(&params);
 
   assert_eq!(mesh.vertex_positions_obj_space, positions);
-  assert_eq!(mesh.params, params);
+  assert_eq!(mesh.params, vec![params.clone()]);
   assert_eq!(mesh.tetras, tetras);
 
   // assert_eq!(
-  //   HashSet::<[u16; 3]>::from_iter(mesh.boundary_faces.iter().cloned().map(
+  //   HashSet::<[u32; 3]>::from_iter(mesh.boundary_faces.iter().cloned().map(
   //     // Sort to avoid ordering issues
   //     |mut v| {
   //       v.sort();
@@ -471,6 +1931,7 @@ fn rigid_transform_gravity(
   viscous_incompressibility in 0.001f64..1000.0,
   viscous_rigidity in 0.001f64..1000.0,
   density in 0.01f64..10000.0,
+  material : Material,
   mesh_option : MeshOptions,
   translation in prop::array::uniform3(-2.0f64..2.0),
   rotation in prop::array::uniform3(-2.0f64..2.0),
@@ -480,7 +1941,16 @@ fn rigid_transform_gravity(
     rigidity,
     viscous_incompressibility,
     viscous_rigidity,
+    mass_damping: 0.0,
+    stiffness_damping: 0.0,
+    air_drag: 0.0,
     density,
+    material,
+    fiber_stiffness: 0.0,
+    yield_strain: 0.05,
+    creep_rate: 0.0,
+    max_plastic_strain: 0.5,
+    toughness: None,
   };
   let (mesh, positions, _) = mesh_option.get_mesh(&params);
 
@@ -502,3 +1972,95 @@ fn rigid_transform_gravity(
   }
 }
 }
+
+#[cfg(test)]
+proptest! {
+#[test]
+fn accels_differential_matches_finite_difference(
+  incompressibility in 0.1f64..100.0,
+  rigidity in 0.1f64..100.0,
+  viscous_incompressibility in 0.1f64..100.0,
+  viscous_rigidity in 0.1f64..100.0,
+  mass_damping in 0.0f64..10.0,
+  stiffness_damping in 0.0f64..1.0,
+  air_drag in 0.0f64..10.0,
+  material : Material,
+  mesh_option : MeshOptions,
+  position_noise in
+    prop::collection::vec(prop::array::uniform3(-0.2f64..0.2), 5),
+  velocities in prop::collection::vec(prop::array::uniform3(-1.0f64..1.0), 5),
+  d_positions in prop::collection::vec(prop::array::uniform3(-1.0f64..1.0), 5),
+  d_velocities in prop::collection::vec(prop::array::uniform3(-1.0f64..1.0), 5),
+) {
+  let params = MeshParams {
+    incompressibility,
+    rigidity,
+    viscous_incompressibility,
+    viscous_rigidity,
+    mass_damping,
+    stiffness_damping,
+    air_drag,
+    density: 1.0,
+    material,
+    fiber_stiffness: 0.0,
+    yield_strain: 0.05,
+    creep_rate: 0.0,
+    max_plastic_strain: 0.5,
+    toughness: None,
+  };
+  let (mesh, positions, _) = mesh_option.get_mesh(&params);
+
+  let to_vectors = |vals: &Vec<[S; 3]>| {
+    vals[..positions.len()]
+      .iter()
+      .map(|v| Vector3::from(*v))
+      .collect::<Vec<_>>()
+  };
+  let positions = positions
+    .iter()
+    .zip(to_vectors(&position_noise))
+    .map(|(pos, noise)| pos + noise)
+    .collect::<Vec<_>>();
+  let velocities = to_vectors(&velocities);
+  let d_positions = to_vectors(&d_positions);
+  let d_velocities = to_vectors(&d_velocities);
+  let zeros = vec![Vector3::zeros(); positions.len()];
+
+  let d_accels = mesh.vertex_accels_differential(
+    &positions,
+    &velocities,
+    &d_positions,
+    &d_velocities,
+    &zeros,
+  );
+
+  let epsilon = 1e-6;
+  let perturbed_accels = |sign: S| {
+    let perturb = |vals: &[Vector3<S>], d_vals: &[Vector3<S>]| {
+      vals
+        .iter()
+        .zip(d_vals)
+        .map(|(val, d_val)| val + sign * epsilon * d_val)
+        .collect::<Vec<_>>()
+    };
+    mesh.vertex_accels(
+      &perturb(&positions, &d_positions),
+      &perturb(&velocities, &d_velocities),
+      &zeros,
+      0.0,
+    )
+  };
+
+  for ((d_accel, plus), minus) in d_accels
+    .iter()
+    .zip(perturbed_accels(1.0))
+    .zip(perturbed_accels(-1.0))
+  {
+    let finite_difference = (plus - minus) / (2.0 * epsilon);
+    assert!(
+      (d_accel - finite_difference).norm()
+        < 1e-4 * (1.0 + finite_difference.norm())
+    );
+  }
+}
+}
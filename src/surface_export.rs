@@ -0,0 +1,185 @@
+use nalgebra::{Point3, Vector3};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, prelude::*, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SurfaceFormat {
+  Obj,
+  Ply,
+}
+
+impl SurfaceFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      SurfaceFormat::Obj => "obj",
+      SurfaceFormat::Ply => "ply",
+    }
+  }
+}
+
+impl FromStr for SurfaceFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "obj" => Ok(SurfaceFormat::Obj),
+      "ply" => Ok(SurfaceFormat::Ply),
+      _ => Err(format!(
+        "unknown surface format '{}' (expected obj or ply)",
+        s
+      )),
+    }
+  }
+}
+
+/// Area weighted vertex normals, faces are expected to be wound
+/// counterclockwise when viewed from outside.
+pub fn vertex_normals(
+  positions: &[Point3<f32>],
+  faces: &[Point3<u32>],
+) -> Vec<Vector3<f32>> {
+  let mut normals = vec![Vector3::zeros(); positions.len()];
+
+  for face in faces {
+    let get_vertex = |idx: usize| positions[face[idx] as usize];
+    // magnitude is twice the face area
+    let normal =
+      (get_vertex(1) - get_vertex(0)).cross(&(get_vertex(2) - get_vertex(0)));
+
+    for vertex_idx in face.iter() {
+      normals[*vertex_idx as usize] += normal;
+    }
+  }
+
+  for normal in &mut normals {
+    let norm = normal.norm();
+    if norm > 0.0 {
+      *normal /= norm;
+    }
+  }
+
+  normals
+}
+
+pub fn write_obj<W: Write>(
+  writer: &mut W,
+  positions: &[Point3<f32>],
+  faces: &[Point3<u32>],
+  normals: Option<&[Vector3<f32>]>,
+) -> io::Result<()> {
+  for pos in positions {
+    writeln!(writer, "v {} {} {}", pos[0], pos[1], pos[2])?;
+  }
+
+  if let Some(normals) = normals {
+    for normal in normals {
+      writeln!(writer, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+    }
+  }
+
+  // obj indices start at 1
+  for face in faces {
+    let [a, b, c] = [face[0] + 1, face[1] + 1, face[2] + 1];
+    if normals.is_some() {
+      writeln!(writer, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+    } else {
+      writeln!(writer, "f {} {} {}", a, b, c)?;
+    }
+  }
+
+  Ok(())
+}
+
+pub fn write_ply<W: Write>(
+  writer: &mut W,
+  positions: &[Point3<f32>],
+  faces: &[Point3<u32>],
+  normals: Option<&[Vector3<f32>]>,
+) -> io::Result<()> {
+  writeln!(writer, "ply")?;
+  writeln!(writer, "format ascii 1.0")?;
+  writeln!(writer, "element vertex {}", positions.len())?;
+  for property in &["x", "y", "z"] {
+    writeln!(writer, "property float {}", property)?;
+  }
+  if normals.is_some() {
+    for property in &["nx", "ny", "nz"] {
+      writeln!(writer, "property float {}", property)?;
+    }
+  }
+  writeln!(writer, "element face {}", faces.len())?;
+  writeln!(writer, "property list uchar int vertex_indices")?;
+  writeln!(writer, "end_header")?;
+
+  for (vertex_idx, pos) in positions.iter().enumerate() {
+    write!(writer, "{} {} {}", pos[0], pos[1], pos[2])?;
+    if let Some(normals) = normals {
+      let normal = normals[vertex_idx];
+      write!(writer, " {} {} {}", normal[0], normal[1], normal[2])?;
+    }
+    writeln!(writer)?;
+  }
+
+  for face in faces {
+    writeln!(writer, "3 {} {} {}", face[0], face[1], face[2])?;
+  }
+
+  Ok(())
+}
+
+pub fn write_surface(
+  path: &Path,
+  format: SurfaceFormat,
+  positions: &[Point3<f32>],
+  faces: &[Point3<u32>],
+  with_normals: bool,
+) -> io::Result<()> {
+  let mut writer = BufWriter::new(File::create(path)?);
+
+  let normals = if with_normals {
+    Some(vertex_normals(positions, faces))
+  } else {
+    None
+  };
+  let normals = normals.as_deref();
+
+  match format {
+    SurfaceFormat::Obj => write_obj(&mut writer, positions, faces, normals)?,
+    SurfaceFormat::Ply => write_ply(&mut writer, positions, faces, normals)?,
+  }
+
+  writer.flush()
+}
+
+#[test]
+fn tet_normals_point_outward() {
+  let positions = vec![
+    Point3::new(0.0, 0.0, 0.0),
+    Point3::new(1.0, 0.0, 0.0),
+    Point3::new(0.0, 1.0, 0.0),
+    Point3::new(0.0, 0.0, 1.0),
+  ];
+  let faces = vec![
+    Point3::new(0, 2, 1),
+    Point3::new(0, 1, 3),
+    Point3::new(0, 3, 2),
+    Point3::new(1, 2, 3),
+  ];
+
+  let centroid = Point3::new(0.25, 0.25, 0.25);
+  for (pos, normal) in positions.iter().zip(vertex_normals(&positions, &faces))
+  {
+    assert!((normal.norm() - 1.0).abs() < 1e-6);
+    assert!(normal.dot(&(pos - centroid)) > 0.0);
+  }
+
+  let mut obj = Vec::new();
+  write_obj(&mut obj, &positions, &faces, None).unwrap();
+  let obj = String::from_utf8(obj).unwrap();
+  assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 4);
+  assert!(obj.lines().any(|l| l == "f 2 3 4"));
+}
@@ -1,6 +1,25 @@
+use std::fs::create_dir_all;
+use std::io;
+use std::path::Path;
+
 #[macro_export]
 macro_rules! assert_float_eq {
   ($l : expr, $r : expr) => {
     assert!(($l - $r).abs() < 1e-5.into());
   };
 }
+
+/// Creates the directory if needed. Returns false (after printing a
+/// message) if the path exists and isn't a directory.
+pub fn prepare_output_dir(dir: &Path, description: &str) -> io::Result<bool> {
+  if dir.exists() {
+    if !dir.is_dir() {
+      eprintln!("{} exists and isn't directory, exiting", description);
+      return Ok(false);
+    }
+  } else {
+    create_dir_all(dir)?;
+  }
+
+  Ok(true)
+}
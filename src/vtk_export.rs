@@ -0,0 +1,173 @@
+use crate::mesh::vtk::{write_tetra_cells, write_tetra_types};
+use nalgebra::{Scalar, Vector3};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, prelude::*, BufWriter, SeekFrom};
+use std::path::Path;
+
+/// Writes a tetrahedral mesh as an ascii XML unstructured grid (.vtu). Each
+/// named array must have one entry per point or per cell respectively.
+pub fn write_vtu<W: Write, N: Scalar + Display>(
+  writer: &mut W,
+  points: &[Vector3<N>],
+  tetras: &[[u32; 4]],
+  point_vectors: &[(&str, &[Vector3<N>])],
+  cell_scalars: &[(&str, &[N])],
+) -> io::Result<()> {
+  writeln!(writer, "<?xml version=\"1.0\"?>")?;
+  writeln!(
+    writer,
+    "<VTKFile type=\"UnstructuredGrid\" version=\"0.1\" \
+     byte_order=\"LittleEndian\">"
+  )?;
+  writeln!(writer, "<UnstructuredGrid>")?;
+  writeln!(
+    writer,
+    "<Piece NumberOfPoints=\"{}\" NumberOfCells=\"{}\">",
+    points.len(),
+    tetras.len()
+  )?;
+
+  writeln!(writer, "<PointData>")?;
+  for (name, values) in point_vectors {
+    debug_assert_eq!(values.len(), points.len());
+    write_vectors(writer, Some(name), values)?;
+  }
+  writeln!(writer, "</PointData>")?;
+
+  writeln!(writer, "<CellData>")?;
+  for (name, values) in cell_scalars {
+    debug_assert_eq!(values.len(), tetras.len());
+    writeln!(
+      writer,
+      "<DataArray type=\"Float64\" Name=\"{}\" format=\"ascii\">",
+      name
+    )?;
+    for value in values.iter() {
+      writeln!(writer, "{}", value)?;
+    }
+    writeln!(writer, "</DataArray>")?;
+  }
+  writeln!(writer, "</CellData>")?;
+
+  writeln!(writer, "<Points>")?;
+  write_vectors(writer, None, points)?;
+  writeln!(writer, "</Points>")?;
+
+  writeln!(writer, "<Cells>")?;
+  writeln!(
+    writer,
+    "<DataArray type=\"Int32\" Name=\"connectivity\" format=\"ascii\">"
+  )?;
+  write_tetra_cells(writer, tetras, "")?;
+  writeln!(writer, "</DataArray>")?;
+  writeln!(
+    writer,
+    "<DataArray type=\"Int32\" Name=\"offsets\" format=\"ascii\">"
+  )?;
+  for tetra_idx in 0..tetras.len() {
+    writeln!(writer, "{}", 4 * (tetra_idx + 1))?;
+  }
+  writeln!(writer, "</DataArray>")?;
+  writeln!(
+    writer,
+    "<DataArray type=\"UInt8\" Name=\"types\" format=\"ascii\">"
+  )?;
+  write_tetra_types(writer, tetras.len())?;
+  writeln!(writer, "</DataArray>")?;
+  writeln!(writer, "</Cells>")?;
+
+  writeln!(writer, "</Piece>")?;
+  writeln!(writer, "</UnstructuredGrid>")?;
+  writeln!(writer, "</VTKFile>")?;
+
+  Ok(())
+}
+
+fn write_vectors<W: Write, N: Scalar + Display>(
+  writer: &mut W,
+  name: Option<&str>,
+  values: &[Vector3<N>],
+) -> io::Result<()> {
+  write!(writer, "<DataArray type=\"Float64\"")?;
+  if let Some(name) = name {
+    write!(writer, " Name=\"{}\"", name)?;
+  }
+  writeln!(writer, " NumberOfComponents=\"3\" format=\"ascii\">")?;
+  for value in values {
+    writeln!(writer, "{} {} {}", value[0], value[1], value[2])?;
+  }
+  writeln!(writer, "</DataArray>")
+}
+
+const PVD_FOOTER: &str = "</Collection>\n</VTKFile>\n";
+
+/// ParaView time series index (.pvd). Entries are added as frames are
+/// written, the file is kept valid after every entry so interrupted runs can
+/// still be opened.
+pub struct PvdWriter {
+  file: File,
+  footer_pos: u64,
+}
+
+impl PvdWriter {
+  pub fn create(path: &Path) -> io::Result<Self> {
+    let mut file = File::create(path)?;
+
+    let header = "<?xml version=\"1.0\"?>\n\
+                  <VTKFile type=\"Collection\" version=\"0.1\" \
+                  byte_order=\"LittleEndian\">\n\
+                  <Collection>\n";
+    file.write_all(header.as_bytes())?;
+    file.write_all(PVD_FOOTER.as_bytes())?;
+
+    Ok(Self {
+      file,
+      footer_pos: header.len() as u64,
+    })
+  }
+
+  /// file is relative to the directory of the pvd, part distinguishes
+  /// datasets at the same time (one per mesh)
+  pub fn add<T: Display>(
+    &mut self,
+    time: T,
+    part: usize,
+    file: &str,
+  ) -> io::Result<()> {
+    self.file.seek(SeekFrom::Start(self.footer_pos))?;
+
+    let mut writer = BufWriter::new(&mut self.file);
+    let entry = format!(
+      "<DataSet timestep=\"{}\" part=\"{}\" file=\"{}\"/>\n",
+      time, part, file
+    );
+    writer.write_all(entry.as_bytes())?;
+    writer.write_all(PVD_FOOTER.as_bytes())?;
+    writer.flush()?;
+
+    self.footer_pos += entry.len() as u64;
+
+    Ok(())
+  }
+}
+
+#[test]
+fn pvd_stays_valid() {
+  let path = std::env::temp_dir()
+    .join(format!("pvd_stays_valid_{}.pvd", std::process::id()));
+
+  let mut pvd = PvdWriter::create(&path).unwrap();
+  pvd.add(0.0, 0, "mesh_0_0.vtu").unwrap();
+  pvd.add(0.5, 1, "mesh_1_0.vtu").unwrap();
+
+  let contents = std::fs::read_to_string(&path).unwrap();
+  std::fs::remove_file(&path).unwrap();
+
+  assert!(contents.ends_with(PVD_FOOTER));
+  assert_eq!(contents.matches(PVD_FOOTER).count(), 1);
+  assert!(contents.contains(concat!(
+    "<DataSet timestep=\"0.5\" part=\"1\" file=\"mesh_1_0.vtu\"/>\n",
+    "</Collection>"
+  )));
+}